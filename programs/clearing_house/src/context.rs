@@ -5,7 +5,10 @@ use crate::controller::position::PositionDirection;
 use crate::state::bank::Bank;
 use crate::state::market::Market;
 use crate::state::state::State;
-use crate::state::user::{OrderTriggerCondition, OrderType, User, UserStats};
+use crate::state::user::{
+    AuctionPriceCurve, OrderTriggerCondition, OrderType, SelfTradePrevention,
+    TrailingStopOffsetType, User, UserStats,
+};
 
 #[derive(Accounts)]
 pub struct Initialize<'info> {
@@ -229,6 +232,22 @@ pub struct TransferDeposit<'info> {
     pub state: Box<Account<'info, State>>,
 }
 
+#[derive(Accounts)]
+pub struct TransferPosition<'info> {
+    #[account(
+        mut,
+        has_one = authority,
+    )]
+    pub from_user: AccountLoader<'info, User>,
+    #[account(
+        mut,
+        has_one = authority,
+    )]
+    pub to_user: AccountLoader<'info, User>,
+    pub authority: Signer<'info>,
+    pub state: Box<Account<'info, State>>,
+}
+
 #[derive(Accounts)]
 pub struct UpdateBankCumulativeInterest<'info> {
     #[account(mut)]
@@ -271,9 +290,46 @@ pub struct WithdrawFromMarketToInsuranceVault<'info> {
     pub token_program: Program<'info, Token>,
 }
 
+#[derive(Accounts)]
+#[instruction(bank_index: u64,)]
+pub struct WithdrawFromBankToInsuranceVault<'info> {
+    #[account(
+        has_one = admin
+    )]
+    pub state: Box<Account<'info, State>>,
+    pub admin: Signer<'info>,
+    #[account(
+        seeds = [b"bank", bank_index.to_le_bytes().as_ref()],
+        bump,
+        mut
+    )]
+    pub bank: AccountLoader<'info, Bank>,
+    #[account(
+        mut,
+        seeds = [b"bank_vault".as_ref(), bank_index.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub bank_vault: Box<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        seeds = [b"bank_vault_authority".as_ref(), bank_index.to_le_bytes().as_ref()],
+        bump,
+    )]
+    /// CHECK: this is the pda for the bank vault
+    pub bank_vault_authority: AccountInfo<'info>,
+    #[account(
+        mut,
+        constraint = &state.insurance_vault.eq(&insurance_vault.key()),
+        token::mint = bank_vault.mint
+    )]
+    pub insurance_vault: Box<Account<'info, TokenAccount>>,
+    pub token_program: Program<'info, Token>,
+}
+
 #[derive(Accounts)]
 pub struct WithdrawFromInsuranceVault<'info> {
     #[account(
+        mut,
         has_one = admin
     )]
     pub state: Box<Account<'info, State>>,
@@ -372,6 +428,36 @@ pub struct FillOrder<'info> {
     pub user_stats: AccountLoader<'info, UserStats>,
 }
 
+#[derive(Accounts)]
+pub struct MatchOrders<'info> {
+    pub state: Box<Account<'info, State>>,
+    pub authority: Signer<'info>,
+    #[account(
+        mut,
+        has_one = authority
+    )]
+    pub filler: AccountLoader<'info, User>,
+    #[account(
+        mut,
+        has_one = authority,
+    )]
+    pub filler_stats: AccountLoader<'info, UserStats>,
+    #[account(mut)]
+    pub user: AccountLoader<'info, User>,
+    #[account(
+        mut,
+        constraint = user_stats.load()?.authority.eq(&user.load()?.authority),
+    )]
+    pub user_stats: AccountLoader<'info, UserStats>,
+    #[account(mut)]
+    pub counterparty: AccountLoader<'info, User>,
+    #[account(
+        mut,
+        constraint = counterparty_stats.load()?.authority.eq(&counterparty.load()?.authority),
+    )]
+    pub counterparty_stats: AccountLoader<'info, UserStats>,
+}
+
 #[derive(Accounts)]
 pub struct PlaceOrder<'info> {
     pub state: Box<Account<'info, State>>,
@@ -400,6 +486,18 @@ pub struct OrderParams {
     pub position_limit: u128,
     pub oracle_price_offset: i128,
     pub auction_duration: u8,
+    pub trailing_stop_offset: u128,
+    pub trailing_stop_offset_type: TrailingStopOffsetType,
+    pub max_ts: Option<i64>,
+    pub iceberg_display_amount: Option<u128>,
+    pub twap_slices: u8,
+    pub twap_slot_interval: u64,
+    pub self_trade_prevention: SelfTradePrevention,
+    pub post_only_slide: bool,
+    pub fill_or_kill: bool,
+    pub quote_asset_amount: Option<u128>,
+    pub trigger_oracle: Option<Pubkey>,
+    pub auction_price_curve: Option<AuctionPriceCurve>,
     pub padding0: bool,
     pub padding1: bool,
 }
@@ -411,6 +509,12 @@ impl Default for OrderType {
     }
 }
 
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
+pub struct ModifyOrderParams {
+    pub base_asset_amount: Option<u128>,
+    pub price: Option<u128>,
+}
+
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
 pub struct OrderParamsOptionalAccounts {
     pub discount_token: bool,
@@ -491,6 +595,19 @@ pub struct TriggerOrder<'info> {
     pub user: AccountLoader<'info, User>,
 }
 
+#[derive(Accounts)]
+pub struct ExpireOrder<'info> {
+    pub state: Box<Account<'info, State>>,
+    pub authority: Signer<'info>,
+    #[account(
+        mut,
+        has_one = authority
+    )]
+    pub filler: AccountLoader<'info, User>,
+    #[account(mut)]
+    pub user: AccountLoader<'info, User>,
+}
+
 #[derive(Accounts)]
 pub struct LiquidatePerp<'info> {
     pub state: Box<Account<'info, State>>,
@@ -564,6 +681,126 @@ pub struct ResolvePerpBankruptcy<'info> {
     pub liquidator: AccountLoader<'info, User>,
     #[account(mut)]
     pub user: AccountLoader<'info, User>,
+    #[account(
+        mut,
+        seeds = [b"bank_vault".as_ref(), 0_u64.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub bank_vault: Box<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        constraint = &state.insurance_vault.eq(&insurance_vault.key())
+    )]
+    pub insurance_vault: Box<Account<'info, TokenAccount>>,
+    /// CHECK: withdraw fails if this isn't vault owner
+    #[account(
+        constraint = &state.insurance_vault_authority.eq(&insurance_vault_authority.key())
+    )]
+    pub insurance_vault_authority: AccountInfo<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct AddInsuranceFundStake<'info> {
+    #[account(mut)]
+    pub state: Box<Account<'info, State>>,
+    #[account(
+        mut,
+        has_one = authority
+    )]
+    pub user_stats: AccountLoader<'info, UserStats>,
+    pub authority: Signer<'info>,
+    #[account(
+        mut,
+        constraint = &state.insurance_vault.eq(&insurance_vault.key())
+    )]
+    pub insurance_vault: Box<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        constraint = &insurance_vault.mint.eq(&user_token_account.mint)
+    )]
+    pub user_token_account: Box<Account<'info, TokenAccount>>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct RequestRemoveInsuranceFundStake<'info> {
+    pub state: Box<Account<'info, State>>,
+    #[account(
+        mut,
+        has_one = authority
+    )]
+    pub user_stats: AccountLoader<'info, UserStats>,
+    pub authority: Signer<'info>,
+    #[account(
+        constraint = &state.insurance_vault.eq(&insurance_vault.key())
+    )]
+    pub insurance_vault: Box<Account<'info, TokenAccount>>,
+}
+
+#[derive(Accounts)]
+pub struct RemoveInsuranceFundStake<'info> {
+    #[account(mut)]
+    pub state: Box<Account<'info, State>>,
+    #[account(
+        mut,
+        has_one = authority
+    )]
+    pub user_stats: AccountLoader<'info, UserStats>,
+    pub authority: Signer<'info>,
+    #[account(
+        mut,
+        constraint = &state.insurance_vault.eq(&insurance_vault.key())
+    )]
+    pub insurance_vault: Box<Account<'info, TokenAccount>>,
+    /// CHECK: withdraw fails if this isn't vault owner
+    #[account(
+        constraint = &state.insurance_vault_authority.eq(&insurance_vault_authority.key())
+    )]
+    pub insurance_vault_authority: AccountInfo<'info>,
+    #[account(
+        mut,
+        constraint = &insurance_vault.mint.eq(&user_token_account.mint)
+    )]
+    pub user_token_account: Box<Account<'info, TokenAccount>>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct SettleMarketFeesToInsuranceFund<'info> {
+    #[account(
+        has_one = admin
+    )]
+    pub state: Box<Account<'info, State>>,
+    pub admin: Signer<'info>,
+    #[account(
+        seeds = [b"bank", 0_u64.to_le_bytes().as_ref()],
+        bump,
+        mut
+    )]
+    pub bank: AccountLoader<'info, Bank>,
+    #[account(
+        mut,
+        seeds = [b"bank_vault".as_ref(), 0_u64.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub bank_vault: Box<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        seeds = [b"bank_vault_authority".as_ref(), 0_u64.to_le_bytes().as_ref()],
+        bump,
+    )]
+    /// CHECK: this is the pda for the bank vault
+    pub bank_vault_authority: AccountInfo<'info>,
+    #[account(mut)]
+    pub market: AccountLoader<'info, Market>,
+    #[account(
+        mut,
+        constraint = &state.insurance_vault.eq(&insurance_vault.key()),
+        token::mint = bank_vault.mint
+    )]
+    pub insurance_vault: Box<Account<'info, TokenAccount>>,
+    pub token_program: Program<'info, Token>,
 }
 
 #[derive(Accounts)]
@@ -582,6 +819,13 @@ pub struct UpdateFundingRate<'info> {
     pub oracle: AccountInfo<'info>,
 }
 
+#[derive(Accounts)]
+pub struct RebalanceK<'info> {
+    pub state: Box<Account<'info, State>>,
+    #[account(mut)]
+    pub market: AccountLoader<'info, Market>,
+}
+
 #[derive(Accounts)]
 pub struct RepegCurve<'info> {
     #[account(
@@ -1,5 +1,5 @@
 use crate::controller::position::PositionDirection;
-use crate::error::ClearingHouseResult;
+use crate::error::{ClearingHouseResult, ErrorCode};
 use crate::get_then_update_id;
 use crate::math::amm::{
     calculate_quote_asset_amount_swapped, calculate_spread_reserves, get_spread_reserves,
@@ -7,12 +7,16 @@ use crate::math::amm::{
 };
 use crate::math::bank_balance::get_token_amount;
 use crate::math::casting::{cast_to_i128, cast_to_i64, cast_to_u128};
-use crate::math::constants::PRICE_TO_PEG_PRECISION_RATIO;
+use crate::math::constants::{
+    K_BPS_INCREASE_MAX, K_BPS_UPDATE_SCALE, PRICE_TO_PEG_PRECISION_RATIO, REBALANCE_K_PERIOD,
+    TWENTY_FOUR_HOUR,
+};
 use crate::math::{amm, bn, quote_asset::*};
 use crate::math_error;
 use crate::state::events::CurveRecord;
 use crate::state::market::{Market, AMM};
 use crate::state::oracle::OraclePriceData;
+use crate::validate;
 use anchor_lang::prelude::*;
 use solana_program::msg;
 use std::cmp::{max, min};
@@ -80,6 +84,15 @@ pub fn swap_base_asset(
         SwapDirection::Remove => PositionDirection::Long,
     };
 
+    validate!(
+        base_asset_swap_amount
+            <= amm::calculate_max_base_asset_amount_for_inventory(amm, position_direction)?,
+        ErrorCode::MaxNetBaseAssetAmountBreached,
+        "swap would push net base asset amount {} past max {}",
+        amm.net_base_asset_amount,
+        amm.max_net_base_asset_amount
+    )?;
+
     let mark_price = match precomputed_mark_price {
         Some(mark_price) => mark_price,
         None => amm.mark_price()?,
@@ -207,10 +220,35 @@ pub fn update_spreads(amm: &mut AMM, mark_price: u128) -> ClearingHouseResult<(u
             amm.net_base_asset_amount,
             mark_price,
             amm.total_fee_minus_distributions,
+            amm.sqrt_k,
+            amm.mark_std,
+            amm.inventory_spread_scale,
+            amm.volatility_spread_scale,
+            amm.confidence_spread_scale,
         )?
     } else {
         let half_base_spread = (amm.base_spread / 2) as u128;
-        (half_base_spread, half_base_spread)
+        let (dynamic_long_spread, dynamic_short_spread) = amm::calculate_dynamic_spread(
+            amm.net_base_asset_amount,
+            amm.sqrt_k,
+            amm.mark_std,
+            mark_price,
+            amm.last_oracle_conf_pct,
+            amm.inventory_spread_scale,
+            amm.volatility_spread_scale,
+            amm.confidence_spread_scale,
+        )?;
+
+        // only the dynamic part is bounded, the base spread always applies
+        amm::cap_spread_to_max_spread(
+            half_base_spread
+                .checked_add(dynamic_long_spread)
+                .ok_or_else(math_error!())?,
+            half_base_spread
+                .checked_add(dynamic_short_spread)
+                .ok_or_else(math_error!())?,
+            max(amm.max_spread, amm.base_spread as u32),
+        )?
     };
 
     amm.long_spread = long_spread;
@@ -319,6 +357,127 @@ pub fn formulaic_update_k(
     Ok(())
 }
 
+/// Permissionless k update budgeted by the fee revenue since the last rebalance. The total change
+/// is capped per day on top of the per update bounds in calculate_budgeted_k_scale.
+pub fn rebalance_k(market: &mut Market, now: i64) -> ClearingHouseResult {
+    validate!(
+        market.amm.curve_update_intensity > 0,
+        ErrorCode::InvalidRebalanceK,
+        "curve updates are disabled for market {}",
+        market.market_index
+    )?;
+
+    // first rebalance only records the starting point so historical fees aren't treated as budget
+    if market.amm.last_rebalance_k_ts == 0 {
+        market.amm.last_rebalance_k_ts = now;
+        market.amm.rebalance_k_day_start_ts = now;
+        market.amm.rebalance_k_day_start_sqrt_k = market.amm.sqrt_k;
+        market.amm.last_rebalance_k_total_fee = market.amm.total_fee;
+        return Ok(());
+    }
+
+    validate!(
+        now.checked_sub(market.amm.last_rebalance_k_ts)
+            .ok_or_else(math_error!())?
+            >= REBALANCE_K_PERIOD,
+        ErrorCode::InvalidRebalanceK,
+        "last rebalance_k was at {}",
+        market.amm.last_rebalance_k_ts
+    )?;
+
+    if now
+        .checked_sub(market.amm.rebalance_k_day_start_ts)
+        .ok_or_else(math_error!())?
+        >= TWENTY_FOUR_HOUR
+    {
+        market.amm.rebalance_k_day_start_ts = now;
+        market.amm.rebalance_k_day_start_sqrt_k = market.amm.sqrt_k;
+    }
+
+    market.amm.last_rebalance_k_ts = now;
+
+    let budget = amm::calculate_rebalance_k_budget(market)?;
+    if budget == 0 {
+        return Ok(());
+    }
+
+    let (k_scale_numerator, k_scale_denominator) = if market.amm.net_base_asset_amount == 0 {
+        // no inventory, k changes are free so only deepen by the single update bound
+        (
+            cast_to_u128(
+                K_BPS_UPDATE_SCALE
+                    + K_BPS_INCREASE_MAX * market.amm.curve_update_intensity as i128 / 100,
+            )?,
+            cast_to_u128(K_BPS_UPDATE_SCALE)?,
+        )
+    } else {
+        let mark_price = market.amm.mark_price()?;
+        amm::calculate_budgeted_k_scale(market, budget, mark_price)?
+    };
+
+    let new_sqrt_k = bn::U192::from(market.amm.sqrt_k)
+        .checked_mul(bn::U192::from(k_scale_numerator))
+        .ok_or_else(math_error!())?
+        .checked_div(bn::U192::from(k_scale_denominator))
+        .ok_or_else(math_error!())?
+        .try_to_u128()?;
+
+    let new_sqrt_k =
+        amm::bound_sqrt_k_to_daily_change(new_sqrt_k, market.amm.rebalance_k_day_start_sqrt_k)?;
+
+    if new_sqrt_k == market.amm.sqrt_k {
+        return Ok(());
+    }
+
+    let peg_multiplier_before = market.amm.peg_multiplier;
+    let base_asset_reserve_before = market.amm.base_asset_reserve;
+    let quote_asset_reserve_before = market.amm.quote_asset_reserve;
+    let sqrt_k_before = market.amm.sqrt_k;
+
+    let update_k_result = get_update_k_result(market, bn::U192::from(new_sqrt_k), true)?;
+
+    let adjustment_cost = amm::adjust_k_cost(market, &update_k_result)?;
+
+    let cost_applied = apply_cost_to_market(market, adjustment_cost, true)?;
+
+    if cost_applied {
+        amm::update_k(market, &update_k_result)?;
+
+        // revenue spent here shouldn't count towards the next budget
+        market.amm.last_rebalance_k_total_fee = market.amm.total_fee;
+
+        let peg_multiplier_after = market.amm.peg_multiplier;
+        let base_asset_reserve_after = market.amm.base_asset_reserve;
+        let quote_asset_reserve_after = market.amm.quote_asset_reserve;
+        let sqrt_k_after = market.amm.sqrt_k;
+
+        emit!(CurveRecord {
+            ts: now,
+            record_id: get_then_update_id!(market, next_curve_record_id),
+            market_index: market.market_index,
+            peg_multiplier_before,
+            base_asset_reserve_before,
+            quote_asset_reserve_before,
+            sqrt_k_before,
+            peg_multiplier_after,
+            base_asset_reserve_after,
+            quote_asset_reserve_after,
+            sqrt_k_after,
+            base_asset_amount_long: market.base_asset_amount_long.unsigned_abs(),
+            base_asset_amount_short: market.base_asset_amount_short.unsigned_abs(),
+            net_base_asset_amount: market.amm.net_base_asset_amount,
+            open_interest: market.open_interest,
+            adjustment_cost,
+            total_fee: market.amm.total_fee,
+            total_fee_minus_distributions: market.amm.total_fee_minus_distributions,
+            oracle_price: market.amm.last_oracle_price,
+            fill_record: market.next_fill_record_id as u128,
+        });
+    }
+
+    Ok(())
+}
+
 pub fn update_pool_balances(
     market: &mut Market,
     bank: &mut Bank,
@@ -456,8 +615,8 @@ pub fn move_to_price(amm: &mut AMM, target_price: u128) -> ClearingHouseResult {
 mod test {
     use super::*;
     use crate::math::constants::{
-        AMM_RESERVE_PRECISION, BANK_CUMULATIVE_INTEREST_PRECISION, MARK_PRICE_PRECISION,
-        QUOTE_PRECISION,
+        AMM_RESERVE_PRECISION, BANK_CUMULATIVE_INTEREST_PRECISION, BID_ASK_SPREAD_PRECISION,
+        MARK_PRICE_PRECISION, PEG_PRECISION, QUOTE_PRECISION,
     };
     #[test]
     fn formualic_k_tests() {
@@ -657,4 +816,205 @@ mod test {
         assert_eq!(pnl_pool_token_amount, 99_000_000_000 + 2 - 987_789);
         assert_eq!(amm_fee_pool_token_amount, 0);
     }
+
+    #[test]
+    fn swap_base_asset_respects_max_net_base_asset_amount() {
+        let mut amm = AMM {
+            base_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+            quote_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+            sqrt_k: 100 * AMM_RESERVE_PRECISION,
+            peg_multiplier: 100 * PEG_PRECISION,
+            net_base_asset_amount: AMM_RESERVE_PRECISION as i128,
+            max_net_base_asset_amount: 2 * AMM_RESERVE_PRECISION,
+            base_asset_amount_step_size: 1,
+            max_base_asset_reserve: u128::MAX,
+            ask_base_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+            ask_quote_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+            bid_base_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+            bid_quote_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+            ..AMM::default()
+        };
+
+        let result = swap_base_asset(
+            &mut amm,
+            AMM_RESERVE_PRECISION + 1,
+            SwapDirection::Remove,
+            0,
+            None,
+        );
+        assert_eq!(result, Err(ErrorCode::MaxNetBaseAssetAmountBreached));
+
+        swap_base_asset(
+            &mut amm,
+            AMM_RESERVE_PRECISION,
+            SwapDirection::Remove,
+            0,
+            None,
+        )
+        .unwrap();
+        assert_eq!(amm.base_asset_reserve, 99 * AMM_RESERVE_PRECISION);
+
+        // reducing the amm's exposure is always allowed
+        swap_base_asset(
+            &mut amm,
+            3 * AMM_RESERVE_PRECISION,
+            SwapDirection::Add,
+            0,
+            None,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn update_spreads_applies_dynamic_spread_without_curve_updates() {
+        let mut amm = AMM {
+            base_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+            quote_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+            sqrt_k: 100 * AMM_RESERVE_PRECISION,
+            peg_multiplier: 100 * PEG_PRECISION,
+            base_spread: 500,
+            max_spread: 10_000,
+            last_oracle_conf_pct: 1_000,
+            confidence_spread_scale: 2 * BID_ASK_SPREAD_PRECISION as u32,
+            curve_update_intensity: 0,
+            ..AMM::default()
+        };
+        let mark_price = amm.mark_price().unwrap();
+
+        // half the base spread plus twice the oracle confidence on each side
+        let (long_spread, short_spread) = update_spreads(&mut amm, mark_price).unwrap();
+        assert_eq!(long_spread, 2_250);
+        assert_eq!(short_spread, 2_250);
+        assert!(amm.ask_base_asset_reserve < amm.base_asset_reserve);
+        assert!(amm.bid_base_asset_reserve > amm.base_asset_reserve);
+
+        // still bounded by max spread
+        amm.max_spread = 3_000;
+        let (long_spread, short_spread) = update_spreads(&mut amm, mark_price).unwrap();
+        assert_eq!(long_spread + short_spread, 3_000);
+
+        // but never below the base spread
+        amm.max_spread = 0;
+        let (long_spread, short_spread) = update_spreads(&mut amm, mark_price).unwrap();
+        assert_eq!(long_spread + short_spread, 500);
+    }
+
+    #[test]
+    fn rebalance_k_tests() {
+        let mut market = Market {
+            amm: AMM {
+                base_asset_reserve: 5122950819670000,
+                quote_asset_reserve: 488 * AMM_RESERVE_PRECISION,
+                sqrt_k: 500 * AMM_RESERVE_PRECISION,
+                peg_multiplier: 50000,
+                net_base_asset_amount: -122950819670000,
+                total_fee: 1000 * QUOTE_PRECISION,
+                total_fee_minus_distributions: 1000 * QUOTE_PRECISION as i128,
+                curve_update_intensity: 100,
+                ..AMM::default()
+            },
+            open_interest: 1,
+            ..Market::default()
+        };
+
+        let mut now = 1_660_000_000;
+
+        // first call only records the fees the market already has
+        rebalance_k(&mut market, now).unwrap();
+        assert_eq!(market.amm.sqrt_k, 500 * AMM_RESERVE_PRECISION);
+        assert_eq!(market.amm.last_rebalance_k_ts, now);
+        assert_eq!(
+            market.amm.last_rebalance_k_total_fee,
+            1000 * QUOTE_PRECISION
+        );
+
+        // new fee revenue deepens k by the single update bound
+        market.amm.total_fee += 100 * QUOTE_PRECISION;
+        market.amm.total_fee_minus_distributions += 100 * QUOTE_PRECISION as i128;
+        now += REBALANCE_K_PERIOD;
+        rebalance_k(&mut market, now).unwrap();
+        assert_eq!(market.amm.sqrt_k, 5005 * AMM_RESERVE_PRECISION / 10);
+        assert_eq!(
+            market.amm.rebalance_k_day_start_sqrt_k,
+            500 * AMM_RESERVE_PRECISION
+        );
+        assert_eq!(market.amm.last_rebalance_k_total_fee, market.amm.total_fee);
+
+        // too soon
+        assert_eq!(
+            rebalance_k(&mut market, now + 1),
+            Err(ErrorCode::InvalidRebalanceK)
+        );
+
+        // no new revenue, no change
+        let sqrt_k_before = market.amm.sqrt_k;
+        now += REBALANCE_K_PERIOD;
+        rebalance_k(&mut market, now).unwrap();
+        assert_eq!(market.amm.sqrt_k, sqrt_k_before);
+
+        // funding paid into the fee pool isn't fee revenue
+        market.amm.total_fee_minus_distributions += 100 * QUOTE_PRECISION as i128;
+        now += REBALANCE_K_PERIOD;
+        rebalance_k(&mut market, now).unwrap();
+        assert_eq!(market.amm.sqrt_k, sqrt_k_before);
+
+        // keeps deepening with revenue until the daily cap
+        for _ in 0..20 {
+            market.amm.total_fee += 100 * QUOTE_PRECISION;
+            market.amm.total_fee_minus_distributions += 100 * QUOTE_PRECISION as i128;
+            now += REBALANCE_K_PERIOD;
+            rebalance_k(&mut market, now).unwrap();
+        }
+        assert_eq!(market.amm.sqrt_k, 505 * AMM_RESERVE_PRECISION);
+
+        // new day, new cap
+        market.amm.total_fee += 100 * QUOTE_PRECISION;
+        market.amm.total_fee_minus_distributions += 100 * QUOTE_PRECISION as i128;
+        now += TWENTY_FOUR_HOUR;
+        rebalance_k(&mut market, now).unwrap();
+        assert!(market.amm.sqrt_k > 505 * AMM_RESERVE_PRECISION);
+        assert_eq!(
+            market.amm.rebalance_k_day_start_sqrt_k,
+            505 * AMM_RESERVE_PRECISION
+        );
+
+        // disabled
+        market.amm.curve_update_intensity = 0;
+        assert_eq!(
+            rebalance_k(&mut market, now + REBALANCE_K_PERIOD),
+            Err(ErrorCode::InvalidRebalanceK)
+        );
+    }
+
+    #[test]
+    fn rebalance_k_ignores_fees_earned_before_first_call() {
+        let mut market = Market {
+            amm: AMM {
+                base_asset_reserve: 5122950819670000,
+                quote_asset_reserve: 488 * AMM_RESERVE_PRECISION,
+                sqrt_k: 500 * AMM_RESERVE_PRECISION,
+                peg_multiplier: 50000,
+                net_base_asset_amount: -122950819670000,
+                total_fee: 1_000_000 * QUOTE_PRECISION,
+                total_fee_minus_distributions: 1_000_000 * QUOTE_PRECISION as i128,
+                curve_update_intensity: 100,
+                ..AMM::default()
+            },
+            open_interest: 1,
+            ..Market::default()
+        };
+
+        let mut now = 1_660_000_000;
+        rebalance_k(&mut market, now).unwrap();
+        assert_eq!(market.amm.sqrt_k, 500 * AMM_RESERVE_PRECISION);
+
+        // no revenue since the first call, so no budget
+        now += REBALANCE_K_PERIOD;
+        rebalance_k(&mut market, now).unwrap();
+        assert_eq!(market.amm.sqrt_k, 500 * AMM_RESERVE_PRECISION);
+        assert_eq!(
+            market.amm.total_fee_minus_distributions,
+            1_000_000 * QUOTE_PRECISION as i128
+        );
+    }
 }
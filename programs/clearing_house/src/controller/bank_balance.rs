@@ -7,7 +7,7 @@ use crate::math::bank_balance::{
     InterestAccumulated,
 };
 use crate::math::casting::{cast, cast_to_i128, cast_to_u64};
-use crate::math::constants::TWENTY_FOUR_HOUR;
+use crate::math::constants::{BANK_RESERVE_FACTOR_PRECISION, TWENTY_FOUR_HOUR};
 use crate::math_error;
 use crate::state::bank::{Bank, BankBalance, BankBalanceType};
 use crate::state::market::Market;
@@ -68,6 +68,9 @@ pub fn update_bank_cumulative_interest(bank: &mut Bank, now: i64) -> ClearingHou
 
     if interest_update || no_utilization {
         if interest_update {
+            let borrow_token_amount_before =
+                get_token_amount(bank.borrow_balance, bank, &BankBalanceType::Borrow)?;
+
             bank.cumulative_deposit_interest = bank
                 .cumulative_deposit_interest
                 .checked_add(deposit_interest)
@@ -77,6 +80,23 @@ pub fn update_bank_cumulative_interest(bank: &mut Bank, now: i64) -> ClearingHou
                 .cumulative_borrow_interest
                 .checked_add(borrow_interest)
                 .ok_or_else(math_error!())?;
+
+            if bank.reserve_factor > 0 {
+                let borrow_interest_token_amount =
+                    get_token_amount(bank.borrow_balance, bank, &BankBalanceType::Borrow)?
+                        .checked_sub(borrow_token_amount_before)
+                        .ok_or_else(math_error!())?;
+
+                update_revenue_pool_balances(
+                    borrow_interest_token_amount
+                        .checked_mul(bank.reserve_factor)
+                        .ok_or_else(math_error!())?
+                        .checked_div(BANK_RESERVE_FACTOR_PRECISION)
+                        .ok_or_else(math_error!())?,
+                    &BankBalanceType::Deposit,
+                    bank,
+                )?;
+            }
         }
 
         update_bank_twap_stats(bank, utilization, now)?;
@@ -86,6 +106,18 @@ pub fn update_bank_cumulative_interest(bank: &mut Bank, now: i64) -> ClearingHou
     Ok(())
 }
 
+pub fn update_revenue_pool_balances(
+    token_amount: u128,
+    update_direction: &BankBalanceType,
+    bank: &mut Bank,
+) -> ClearingHouseResult {
+    let mut revenue_pool = bank.revenue_pool;
+    update_bank_balances(token_amount, update_direction, bank, &mut revenue_pool)?;
+    bank.revenue_pool = revenue_pool;
+
+    Ok(())
+}
+
 pub fn update_bank_balances(
     mut token_amount: u128,
     update_direction: &BankBalanceType,
@@ -234,8 +266,8 @@ mod test {
     use crate::create_anchor_account_info;
     use crate::math::constants::{
         AMM_RESERVE_PRECISION, BANK_CUMULATIVE_INTEREST_PRECISION, BANK_INTEREST_PRECISION,
-        BANK_WEIGHT_PRECISION, BASE_PRECISION_I128, LIQUIDATION_FEE_PRECISION, PEG_PRECISION,
-        QUOTE_PRECISION, QUOTE_PRECISION_I128,
+        BANK_RESERVE_FACTOR_PRECISION, BANK_WEIGHT_PRECISION, BASE_PRECISION_I128,
+        LIQUIDATION_FEE_PRECISION, PEG_PRECISION, QUOTE_PRECISION, QUOTE_PRECISION_I128,
     };
     use crate::state::bank::{Bank, BankBalanceType};
     use crate::state::bank_map::BankMap;
@@ -548,4 +580,47 @@ mod test {
         check_bank_market_valid(&market, &sol_bank, &mut user.bank_balances[1], 100000_u64)
             .unwrap();
     }
+
+    #[test]
+    fn reserve_factor_accrues_to_revenue_pool() {
+        let now = 0_i64;
+        let mut bank = Bank {
+            bank_index: 0,
+            decimals: 6,
+            optimal_utilization: BANK_INTEREST_PRECISION / 2,
+            optimal_borrow_rate: BANK_INTEREST_PRECISION / 5,
+            max_borrow_rate: BANK_INTEREST_PRECISION,
+            cumulative_deposit_interest: BANK_CUMULATIVE_INTEREST_PRECISION,
+            cumulative_borrow_interest: BANK_CUMULATIVE_INTEREST_PRECISION,
+            deposit_balance: 100 * BANK_INTEREST_PRECISION,
+            borrow_balance: 50 * BANK_INTEREST_PRECISION,
+            reserve_factor: BANK_RESERVE_FACTOR_PRECISION / 10,
+            last_updated: 0,
+            ..Bank::default()
+        };
+
+        let borrow_token_amount_before =
+            get_token_amount(bank.borrow_balance, &bank, &BankBalanceType::Borrow).unwrap();
+        let deposit_token_amount_before =
+            get_token_amount(bank.deposit_balance, &bank, &BankBalanceType::Deposit).unwrap();
+
+        update_bank_cumulative_interest(&mut bank, now + 3600 * 24 * 365).unwrap();
+
+        let borrow_interest_token_amount =
+            get_token_amount(bank.borrow_balance, &bank, &BankBalanceType::Borrow).unwrap()
+                - borrow_token_amount_before;
+        let revenue_pool_token_amount =
+            get_token_amount(bank.revenue_pool.balance, &bank, &BankBalanceType::Deposit).unwrap();
+
+        assert_eq!(borrow_interest_token_amount, 10000000); // 20% on 50
+        assert_eq!(revenue_pool_token_amount, 999999);
+
+        // depositors get the rest of the interest
+        let deposit_token_amount =
+            get_token_amount(bank.deposit_balance, &bank, &BankBalanceType::Deposit).unwrap();
+        assert_eq!(
+            deposit_token_amount - deposit_token_amount_before - revenue_pool_token_amount,
+            9000000
+        );
+    }
 }
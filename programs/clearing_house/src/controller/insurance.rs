@@ -0,0 +1,248 @@
+use crate::error::{ClearingHouseResult, ErrorCode};
+use crate::math::insurance::{
+    calculate_if_shares_to_burn_for_withdrawal, if_shares_to_vault_amount,
+    vault_amount_to_if_shares,
+};
+use crate::math_error;
+use crate::state::state::State;
+use crate::state::user::UserStats;
+use crate::validate;
+use solana_program::msg;
+use std::cmp::min;
+
+#[cfg(test)]
+mod tests;
+
+pub fn add_insurance_fund_stake(
+    amount: u64,
+    insurance_fund_vault_balance: u64,
+    user_stats: &mut UserStats,
+    state: &mut State,
+) -> ClearingHouseResult {
+    validate!(
+        amount > 0,
+        ErrorCode::InsufficientDeposit,
+        "amount must be positive"
+    )?;
+
+    // a bankruptcy drained the vault, so the outstanding shares are worthless and are written off
+    if state.insurance_fund_total_shares > 0 && insurance_fund_vault_balance == 0 {
+        msg!(
+            "insurance fund vault is empty, writing off {} shares",
+            state.insurance_fund_total_shares
+        );
+        state.insurance_fund_total_shares = 0;
+        state.insurance_fund_user_shares = 0;
+        state.insurance_fund_shares_epoch = state
+            .insurance_fund_shares_epoch
+            .checked_add(1)
+            .ok_or_else(math_error!())?;
+    }
+
+    apply_insurance_fund_share_write_off(user_stats, state);
+
+    // first staker: the protocol owns everything already sitting in the vault
+    if state.insurance_fund_total_shares == 0 {
+        state.insurance_fund_total_shares = insurance_fund_vault_balance as u128;
+    }
+
+    let shares = vault_amount_to_if_shares(
+        amount,
+        state.insurance_fund_total_shares,
+        insurance_fund_vault_balance,
+    )?;
+
+    // otherwise the tokens are donated to the existing stakers
+    validate!(
+        shares > 0,
+        ErrorCode::InsuranceFundStakeTooSmall,
+        "stake of {} rounds to zero shares",
+        amount
+    )?;
+
+    user_stats.insurance_fund_shares = user_stats
+        .insurance_fund_shares
+        .checked_add(shares)
+        .ok_or_else(math_error!())?;
+
+    state.insurance_fund_user_shares = state
+        .insurance_fund_user_shares
+        .checked_add(shares)
+        .ok_or_else(math_error!())?;
+
+    state.insurance_fund_total_shares = state
+        .insurance_fund_total_shares
+        .checked_add(shares)
+        .ok_or_else(math_error!())?;
+
+    Ok(())
+}
+
+pub fn request_remove_insurance_fund_stake(
+    shares: u128,
+    insurance_fund_vault_balance: u64,
+    user_stats: &mut UserStats,
+    state: &State,
+    now: i64,
+) -> ClearingHouseResult {
+    apply_insurance_fund_share_write_off(user_stats, state);
+
+    let user_shares = user_stats.insurance_fund_shares;
+    validate!(
+        shares > 0 && shares <= user_shares,
+        ErrorCode::InsufficientInsuranceFundShares,
+        "requested {} shares but user has {}",
+        shares,
+        user_shares
+    )?;
+
+    user_stats.unstake_request_shares = shares;
+    user_stats.unstake_request_amount = if_shares_to_vault_amount(
+        shares,
+        state.insurance_fund_total_shares,
+        insurance_fund_vault_balance,
+    )?;
+    user_stats.unstake_request_ts = now;
+
+    Ok(())
+}
+
+pub fn cancel_request_remove_insurance_fund_stake(
+    user_stats: &mut UserStats,
+    state: &State,
+) -> ClearingHouseResult {
+    apply_insurance_fund_share_write_off(user_stats, state);
+
+    validate!(
+        user_stats.unstake_request_shares > 0,
+        ErrorCode::NoInsuranceFundUnstakeRequest
+    )?;
+
+    user_stats.unstake_request_shares = 0;
+    user_stats.unstake_request_amount = 0;
+    user_stats.unstake_request_ts = 0;
+
+    Ok(())
+}
+
+pub fn remove_insurance_fund_stake(
+    insurance_fund_vault_balance: u64,
+    user_stats: &mut UserStats,
+    state: &mut State,
+    now: i64,
+) -> ClearingHouseResult<u64> {
+    apply_insurance_fund_share_write_off(user_stats, state);
+
+    let shares = user_stats.unstake_request_shares;
+    validate!(shares > 0, ErrorCode::NoInsuranceFundUnstakeRequest)?;
+
+    validate!(
+        shares <= user_stats.insurance_fund_shares,
+        ErrorCode::InsufficientInsuranceFundShares
+    )?;
+
+    let time_since_request = now
+        .checked_sub(user_stats.unstake_request_ts)
+        .ok_or_else(math_error!())?;
+    let unstaking_period = state.insurance_fund_unstaking_period;
+    validate!(
+        time_since_request >= unstaking_period,
+        ErrorCode::TryingToRemoveInsuranceFundStakeTooFast,
+        "unstake requested {}s ago, unstaking period is {}s",
+        time_since_request,
+        unstaking_period
+    )?;
+
+    // a request lapses once the window closes so it cant be held open to front run a bankruptcy
+    let unstaking_deadline = unstaking_period
+        .checked_add(state.insurance_fund_unstaking_window)
+        .ok_or_else(math_error!())?;
+    validate!(
+        time_since_request <= unstaking_deadline,
+        ErrorCode::InsuranceFundUnstakeRequestExpired,
+        "unstake requested {}s ago, request expired after {}s",
+        time_since_request,
+        unstaking_deadline
+    )?;
+
+    // stakers absorb losses during the cooldown but forfeit revenue earned after the request
+    let amount = min(
+        if_shares_to_vault_amount(
+            shares,
+            state.insurance_fund_total_shares,
+            insurance_fund_vault_balance,
+        )?,
+        user_stats.unstake_request_amount,
+    );
+
+    user_stats.insurance_fund_shares = user_stats
+        .insurance_fund_shares
+        .checked_sub(shares)
+        .ok_or_else(math_error!())?;
+
+    state.insurance_fund_user_shares = state
+        .insurance_fund_user_shares
+        .checked_sub(shares)
+        .ok_or_else(math_error!())?;
+
+    state.insurance_fund_total_shares = state
+        .insurance_fund_total_shares
+        .checked_sub(shares)
+        .ok_or_else(math_error!())?;
+
+    user_stats.unstake_request_shares = 0;
+    user_stats.unstake_request_amount = 0;
+    user_stats.unstake_request_ts = 0;
+
+    Ok(amount)
+}
+
+pub fn admin_withdraw_from_insurance_fund(
+    amount: u64,
+    insurance_fund_vault_balance: u64,
+    state: &mut State,
+) -> ClearingHouseResult {
+    // before anyone stakes the vault is entirely protocol owned
+    if state.insurance_fund_total_shares == 0 {
+        return Ok(());
+    }
+
+    let shares = calculate_if_shares_to_burn_for_withdrawal(
+        amount,
+        state.insurance_fund_total_shares,
+        insurance_fund_vault_balance,
+    )?;
+
+    let protocol_shares = state
+        .insurance_fund_total_shares
+        .checked_sub(state.insurance_fund_user_shares)
+        .ok_or_else(math_error!())?;
+
+    validate!(
+        shares <= protocol_shares,
+        ErrorCode::AdminWithdrawTooLarge,
+        "withdraw requires {} shares but protocol only owns {}",
+        shares,
+        protocol_shares
+    )?;
+
+    state.insurance_fund_total_shares = state
+        .insurance_fund_total_shares
+        .checked_sub(shares)
+        .ok_or_else(math_error!())?;
+
+    Ok(())
+}
+
+/// Shares from before the last write off are void, drop them the next time the staker is touched
+fn apply_insurance_fund_share_write_off(user_stats: &mut UserStats, state: &State) {
+    if user_stats.insurance_fund_shares_epoch == state.insurance_fund_shares_epoch {
+        return;
+    }
+
+    user_stats.insurance_fund_shares = 0;
+    user_stats.unstake_request_shares = 0;
+    user_stats.unstake_request_amount = 0;
+    user_stats.unstake_request_ts = 0;
+    user_stats.insurance_fund_shares_epoch = state.insurance_fund_shares_epoch;
+}
@@ -0,0 +1,185 @@
+use crate::controller::insurance::{
+    add_insurance_fund_stake, admin_withdraw_from_insurance_fund,
+    cancel_request_remove_insurance_fund_stake, remove_insurance_fund_stake,
+    request_remove_insurance_fund_stake,
+};
+use crate::error::ErrorCode;
+use crate::math::constants::{QUOTE_PRECISION, TWENTY_FOUR_HOUR};
+use crate::state::state::State;
+use crate::state::user::UserStats;
+
+fn get_state() -> State {
+    State {
+        insurance_fund_unstaking_period: TWENTY_FOUR_HOUR,
+        insurance_fund_unstaking_window: TWENTY_FOUR_HOUR,
+        ..State::default()
+    }
+}
+
+#[test]
+pub fn first_stake_leaves_existing_vault_to_protocol() {
+    let mut state = get_state();
+    let mut user_stats = UserStats::default();
+    let amount = QUOTE_PRECISION as u64;
+
+    // vault already holds 100 of protocol fees
+    add_insurance_fund_stake(amount, 100 * amount, &mut user_stats, &mut state).unwrap();
+
+    assert_eq!(user_stats.insurance_fund_shares, QUOTE_PRECISION);
+    assert_eq!(state.insurance_fund_user_shares, QUOTE_PRECISION);
+    assert_eq!(state.insurance_fund_total_shares, 101 * QUOTE_PRECISION);
+
+    // protocol cant withdraw the staker's share
+    let err = admin_withdraw_from_insurance_fund(101 * amount, 101 * amount, &mut state);
+    assert_eq!(err, Err(ErrorCode::AdminWithdrawTooLarge));
+
+    admin_withdraw_from_insurance_fund(100 * amount, 101 * amount, &mut state).unwrap();
+    assert_eq!(state.insurance_fund_total_shares, QUOTE_PRECISION);
+}
+
+#[test]
+pub fn unstake_requires_cooldown() {
+    let mut state = get_state();
+    let mut user_stats = UserStats::default();
+    let amount = 100 * QUOTE_PRECISION as u64;
+
+    add_insurance_fund_stake(amount, 0, &mut user_stats, &mut state).unwrap();
+
+    let err = remove_insurance_fund_stake(amount, &mut user_stats, &mut state, 0);
+    assert_eq!(err, Err(ErrorCode::NoInsuranceFundUnstakeRequest));
+
+    let err = request_remove_insurance_fund_stake(
+        user_stats.insurance_fund_shares + 1,
+        amount,
+        &mut user_stats,
+        &state,
+        0,
+    );
+    assert_eq!(err, Err(ErrorCode::InsufficientInsuranceFundShares));
+
+    let shares = user_stats.insurance_fund_shares / 2;
+    request_remove_insurance_fund_stake(shares, amount, &mut user_stats, &state, 0).unwrap();
+    assert_eq!(user_stats.unstake_request_amount, amount / 2);
+
+    let err =
+        remove_insurance_fund_stake(amount, &mut user_stats, &mut state, TWENTY_FOUR_HOUR - 1);
+    assert_eq!(err, Err(ErrorCode::TryingToRemoveInsuranceFundStakeTooFast));
+
+    cancel_request_remove_insurance_fund_stake(&mut user_stats, &state).unwrap();
+    let err = remove_insurance_fund_stake(amount, &mut user_stats, &mut state, TWENTY_FOUR_HOUR);
+    assert_eq!(err, Err(ErrorCode::NoInsuranceFundUnstakeRequest));
+
+    request_remove_insurance_fund_stake(shares, amount, &mut user_stats, &state, 0).unwrap();
+    let withdraw_amount =
+        remove_insurance_fund_stake(amount, &mut user_stats, &mut state, TWENTY_FOUR_HOUR).unwrap();
+    assert_eq!(withdraw_amount, amount / 2);
+    assert_eq!(user_stats.insurance_fund_shares, shares);
+    assert_eq!(state.insurance_fund_user_shares, shares);
+    assert_eq!(state.insurance_fund_total_shares, shares);
+    assert_eq!(user_stats.unstake_request_shares, 0);
+}
+
+#[test]
+pub fn unstake_pays_lesser_of_request_and_current_value() {
+    let mut state = get_state();
+    let mut user_stats = UserStats::default();
+    let amount = 100 * QUOTE_PRECISION as u64;
+
+    add_insurance_fund_stake(amount, 0, &mut user_stats, &mut state).unwrap();
+    let shares = user_stats.insurance_fund_shares;
+
+    // revenue accrued after request is forfeited
+    request_remove_insurance_fund_stake(shares, amount, &mut user_stats, &state, 0).unwrap();
+    let withdraw_amount =
+        remove_insurance_fund_stake(2 * amount, &mut user_stats, &mut state, TWENTY_FOUR_HOUR)
+            .unwrap();
+    assert_eq!(withdraw_amount, amount);
+
+    // losses during cooldown are absorbed
+    add_insurance_fund_stake(amount, amount, &mut user_stats, &mut state).unwrap();
+    let shares = user_stats.insurance_fund_shares;
+    request_remove_insurance_fund_stake(shares, 2 * amount, &mut user_stats, &state, 0).unwrap();
+    let withdraw_amount =
+        remove_insurance_fund_stake(amount, &mut user_stats, &mut state, TWENTY_FOUR_HOUR).unwrap();
+    assert_eq!(withdraw_amount, amount / 2);
+}
+
+#[test]
+pub fn unstake_request_lapses_after_window() {
+    let mut state = get_state();
+    let mut user_stats = UserStats::default();
+    let amount = 100 * QUOTE_PRECISION as u64;
+
+    add_insurance_fund_stake(amount, 0, &mut user_stats, &mut state).unwrap();
+
+    let shares = user_stats.insurance_fund_shares;
+    request_remove_insurance_fund_stake(shares, amount, &mut user_stats, &state, 0).unwrap();
+
+    // request held open past the window cant be used to exit ahead of a bankruptcy
+    let err = remove_insurance_fund_stake(
+        amount,
+        &mut user_stats,
+        &mut state,
+        2 * TWENTY_FOUR_HOUR + 1,
+    );
+    assert_eq!(err, Err(ErrorCode::InsuranceFundUnstakeRequestExpired));
+    assert_eq!(user_stats.insurance_fund_shares, shares);
+
+    // staker has to request again and wait out the full cooldown
+    let now = 2 * TWENTY_FOUR_HOUR + 1;
+    request_remove_insurance_fund_stake(shares, amount, &mut user_stats, &state, now).unwrap();
+    let err = remove_insurance_fund_stake(amount, &mut user_stats, &mut state, now + 1);
+    assert_eq!(err, Err(ErrorCode::TryingToRemoveInsuranceFundStakeTooFast));
+
+    let withdraw_amount = remove_insurance_fund_stake(
+        amount,
+        &mut user_stats,
+        &mut state,
+        now + 2 * TWENTY_FOUR_HOUR,
+    )
+    .unwrap();
+    assert_eq!(withdraw_amount, amount);
+    assert_eq!(user_stats.insurance_fund_shares, 0);
+}
+
+#[test]
+pub fn stake_rounding_to_zero_shares_is_rejected() {
+    let mut state = get_state();
+    let mut user_stats = UserStats::default();
+
+    add_insurance_fund_stake(100, 0, &mut user_stats, &mut state).unwrap();
+    assert_eq!(state.insurance_fund_total_shares, 100);
+
+    // vault has grown far past the share supply, so 1 token buys less than a share
+    let mut attacker_stats = UserStats::default();
+    let err = add_insurance_fund_stake(1, 1_000_100, &mut attacker_stats, &mut state);
+    assert_eq!(err, Err(ErrorCode::InsuranceFundStakeTooSmall));
+    assert_eq!(state.insurance_fund_total_shares, 100);
+}
+
+#[test]
+pub fn depleted_vault_writes_off_outstanding_shares() {
+    let mut state = get_state();
+    let mut user_stats = UserStats::default();
+    let amount = 100 * QUOTE_PRECISION as u64;
+
+    add_insurance_fund_stake(amount, 0, &mut user_stats, &mut state).unwrap();
+    let shares = user_stats.insurance_fund_shares;
+    request_remove_insurance_fund_stake(shares, amount, &mut user_stats, &state, 0).unwrap();
+
+    // bankruptcy drains the vault, new stakers start from scratch
+    let mut new_user_stats = UserStats::default();
+    add_insurance_fund_stake(amount, 0, &mut new_user_stats, &mut state).unwrap();
+    assert_eq!(state.insurance_fund_shares_epoch, 1);
+    assert_eq!(new_user_stats.insurance_fund_shares, amount as u128);
+    assert_eq!(state.insurance_fund_user_shares, amount as u128);
+    assert_eq!(state.insurance_fund_total_shares, amount as u128);
+
+    // old shares and the pending request are void
+    let err = remove_insurance_fund_stake(amount, &mut user_stats, &mut state, TWENTY_FOUR_HOUR);
+    assert_eq!(err, Err(ErrorCode::NoInsuranceFundUnstakeRequest));
+    let err = request_remove_insurance_fund_stake(shares, amount, &mut user_stats, &state, 0);
+    assert_eq!(err, Err(ErrorCode::InsufficientInsuranceFundShares));
+    assert_eq!(user_stats.insurance_fund_shares, 0);
+    assert_eq!(user_stats.insurance_fund_shares_epoch, 1);
+}
@@ -9,8 +9,11 @@ use crate::error::{ClearingHouseResult, ErrorCode};
 use crate::get_then_update_id;
 use crate::math::bank_balance::get_token_amount;
 use crate::math::bankruptcy::is_user_bankrupt;
-use crate::math::casting::{cast, cast_to_i128};
-use crate::math::constants::{BANK_WEIGHT_PRECISION, LIQUIDATION_FEE_PRECISION, MARGIN_PRECISION};
+use crate::math::casting::{cast, cast_to_i128, cast_to_u64};
+use crate::math::constants::{
+    BANK_WEIGHT_PRECISION, LIQUIDATION_FEE_PRECISION, MARGIN_PRECISION, QUOTE_ASSET_BANK_INDEX,
+};
+use crate::math::insurance::calculate_if_bankruptcy_payment;
 use crate::math::liquidation::{
     calculate_asset_transfer_for_liability_transfer,
     calculate_base_asset_amount_to_cover_margin_shortage,
@@ -1278,7 +1281,8 @@ pub fn resolve_perp_bankruptcy(
     bank_map: &BankMap,
     oracle_map: &mut OracleMap,
     now: i64,
-) -> ClearingHouseResult {
+    insurance_fund_vault_balance: u64,
+) -> ClearingHouseResult<u64> {
     validate!(
         user.bankrupt,
         ErrorCode::UserNotBankrupt,
@@ -1320,8 +1324,15 @@ pub fn resolve_perp_bankruptcy(
         oracle_map,
     )?;
 
+    // insurance fund covers what it can before the loss is socialized
+    let if_payment =
+        calculate_if_bankruptcy_payment(loss.unsigned_abs(), insurance_fund_vault_balance)?;
+    let loss_to_socialize = loss
+        .checked_add(cast_to_i128(if_payment)?)
+        .ok_or_else(math_error!())?;
+
     let cumulative_funding_rate_delta = calculate_funding_rate_deltas_to_resolve_bankruptcy(
-        loss,
+        loss_to_socialize,
         market_map.get_ref(&market_index)?.deref(),
     )?;
 
@@ -1331,6 +1342,16 @@ pub fn resolve_perp_bankruptcy(
 
         let mut market = market_map.get_ref_mut(&market_index)?;
 
+        if if_payment > 0 {
+            let mut quote_asset_bank = bank_map.get_quote_asset_bank_mut()?;
+            update_bank_balances(
+                if_payment,
+                &BankBalanceType::Deposit,
+                &mut quote_asset_bank,
+                &mut market.pnl_pool,
+            )?;
+        }
+
         market.amm.cumulative_funding_rate_long = market
             .amm
             .cumulative_funding_rate_long
@@ -1367,12 +1388,13 @@ pub fn resolve_perp_bankruptcy(
         perp_bankruptcy: PerpBankruptcyRecord {
             market_index,
             pnl: loss,
+            if_payment,
             cumulative_funding_rate_delta,
         },
         ..LiquidationRecord::default()
     });
 
-    Ok(())
+    cast_to_u64(if_payment)
 }
 
 pub fn resolve_bank_bankruptcy(
@@ -1385,7 +1407,8 @@ pub fn resolve_bank_bankruptcy(
     bank_map: &BankMap,
     oracle_map: &mut OracleMap,
     now: i64,
-) -> ClearingHouseResult {
+    insurance_fund_vault_balance: u64,
+) -> ClearingHouseResult<u64> {
     validate!(
         user.bankrupt,
         ErrorCode::UserNotBankrupt,
@@ -1434,9 +1457,18 @@ pub fn resolve_bank_bankruptcy(
         )?
     };
 
+    // insurance fund is denominated in the quote asset so can only cover quote borrows
+    let if_payment = if bank_index == QUOTE_ASSET_BANK_INDEX {
+        calculate_if_bankruptcy_payment(borrow_amount, insurance_fund_vault_balance)?
+    } else {
+        0
+    };
+
     let cumulative_deposit_interest_delta =
         calculate_cumulative_deposit_interest_delta_to_resolve_bankruptcy(
-            borrow_amount,
+            borrow_amount
+                .checked_sub(if_payment)
+                .ok_or_else(math_error!())?,
             bank_map.get_ref(&bank_index)?.deref(),
         )?;
 
@@ -1479,10 +1511,11 @@ pub fn resolve_bank_bankruptcy(
         borrow_bankruptcy: BorrowBankruptcyRecord {
             bank_index,
             borrow_amount,
+            if_payment,
             cumulative_deposit_interest_delta,
         },
         ..LiquidationRecord::default()
     });
 
-    Ok(())
+    cast_to_u64(if_payment)
 }
@@ -17,16 +17,17 @@ use crate::math::amm::is_oracle_valid;
 use crate::math::auction::{
     calculate_auction_end_price, calculate_auction_start_price, is_auction_complete,
 };
-use crate::math::casting::{cast, cast_to_i128};
+use crate::math::casting::{cast, cast_to_i128, cast_to_u128};
 use crate::math::fulfillment::determine_fulfillment_methods;
 use crate::math::liquidation::validate_user_not_being_liquidated;
 use crate::math::matching::{
-    are_orders_same_market_but_different_sides, calculate_fill_for_matched_orders, do_orders_cross,
-    is_maker_for_taker,
+    are_orders_same_market_but_different_sides, calculate_fill_for_matched_orders,
+    calculate_self_trade_prevention_decrements, do_orders_cross, is_maker_for_taker,
 };
+use crate::math::oracle::is_trigger_oracle_valid;
 use crate::math::{amm, fees, margin::*, orders::*};
 use crate::math_error;
-use crate::order_validation::validate_order;
+use crate::order_validation::{validate_oco_order_params, validate_order};
 use crate::print_error;
 use crate::state::bank_map::BankMap;
 use crate::state::events::{emit_stack, OrderRecord};
@@ -37,7 +38,9 @@ use crate::state::market_map::MarketMap;
 use crate::state::oracle::OraclePriceData;
 use crate::state::oracle_map::OracleMap;
 use crate::state::state::*;
-use crate::state::user::{MarketPosition, Order, OrderStatus, OrderType, UserStats};
+use crate::state::user::{
+    MarketPosition, Order, OrderStatus, OrderType, SelfTradePrevention, UserStats,
+};
 use crate::state::user::{OrderDiscountTier, User};
 use crate::validate;
 use std::alloc::{alloc_zeroed, Layout};
@@ -57,13 +60,256 @@ pub fn place_order(
     clock: &Clock,
     params: OrderParams,
 ) -> ClearingHouseResult {
-    let now = clock.unix_timestamp;
-    let slot = clock.slot;
     let user_key = user.key();
     let user = &mut load_mut!(user)?;
-    controller::funding::settle_funding_payment(
+
+    let worst_case_base_asset_amounts_before =
+        get_worst_case_base_asset_amounts(user, &[params.market_index])?;
+
+    _place_order(
+        state, user, &user_key, market_map, bank_map, oracle_map, clock, params, 0,
+    )?;
+
+    validate_margin_after_placing_orders(
+        user,
+        &worst_case_base_asset_amounts_before,
+        market_map,
+        bank_map,
+        oracle_map,
+    )
+}
+
+/// Places every order before running a single margin check, so a ladder of quotes can be
+/// refreshed in one transaction
+pub fn place_orders(
+    state: &State,
+    user: &AccountLoader<User>,
+    market_map: &MarketMap,
+    bank_map: &BankMap,
+    oracle_map: &mut OracleMap,
+    clock: &Clock,
+    params: Vec<OrderParams>,
+) -> ClearingHouseResult {
+    let user_key = user.key();
+    let user = &mut load_mut!(user)?;
+
+    _place_orders(
+        state, user, &user_key, market_map, bank_map, oracle_map, clock, params,
+    )
+}
+
+/// Cancels the orders matching the filters then places the new orders, with one margin check at the end
+pub fn cancel_and_place(
+    state: &State,
+    user: &AccountLoader<User>,
+    market_map: &MarketMap,
+    bank_map: &BankMap,
+    oracle_map: &mut OracleMap,
+    clock: &Clock,
+    cancel_market_index: Option<u64>,
+    cancel_direction: Option<PositionDirection>,
+    params: Vec<OrderParams>,
+) -> ClearingHouseResult {
+    let user_key = user.key();
+    let user = &mut load_mut!(user)?;
+
+    let market_indexes = params
+        .iter()
+        .map(|params| params.market_index)
+        .collect::<Vec<u64>>();
+    let worst_case_base_asset_amounts_before =
+        get_worst_case_base_asset_amounts(user, &market_indexes)?;
+
+    _cancel_orders(
+        user,
+        &user_key,
+        market_map,
+        oracle_map,
+        clock,
+        cancel_market_index,
+        cancel_direction,
+    )?;
+
+    for params in params {
+        _place_order(
+            state, user, &user_key, market_map, bank_map, oracle_map, clock, params, 0,
+        )?;
+    }
+
+    validate_margin_after_placing_orders(
+        user,
+        &worst_case_base_asset_amounts_before,
+        market_map,
+        bank_map,
+        oracle_map,
+    )
+}
+
+fn _place_orders(
+    state: &State,
+    user: &mut User,
+    user_key: &Pubkey,
+    market_map: &MarketMap,
+    bank_map: &BankMap,
+    oracle_map: &mut OracleMap,
+    clock: &Clock,
+    params: Vec<OrderParams>,
+) -> ClearingHouseResult {
+    let market_indexes = params
+        .iter()
+        .map(|params| params.market_index)
+        .collect::<Vec<u64>>();
+    let worst_case_base_asset_amounts_before =
+        get_worst_case_base_asset_amounts(user, &market_indexes)?;
+
+    for params in params {
+        _place_order(
+            state, user, user_key, market_map, bank_map, oracle_map, clock, params, 0,
+        )?;
+    }
+
+    validate_margin_after_placing_orders(
+        user,
+        &worst_case_base_asset_amounts_before,
+        market_map,
+        bank_map,
+        oracle_map,
+    )
+}
+
+fn get_worst_case_base_asset_amounts(
+    user: &User,
+    market_indexes: &[u64],
+) -> ClearingHouseResult<Vec<(u64, i128)>> {
+    let mut worst_case_base_asset_amounts: Vec<(u64, i128)> =
+        Vec::with_capacity(market_indexes.len());
+    for market_index in market_indexes.iter() {
+        if worst_case_base_asset_amounts
+            .iter()
+            .any(|(index, _)| index == market_index)
+        {
+            continue;
+        }
+
+        let worst_case_base_asset_amount = match user.get_position(*market_index) {
+            Ok(market_position) => market_position.worst_case_base_asset_amount()?,
+            Err(_) => 0,
+        };
+
+        worst_case_base_asset_amounts.push((*market_index, worst_case_base_asset_amount));
+    }
+
+    Ok(worst_case_base_asset_amounts)
+}
+
+// Orders fail if they're risk increasing and they bring the user collateral below the margin requirement
+fn validate_margin_after_placing_orders(
+    user: &User,
+    worst_case_base_asset_amounts_before: &[(u64, i128)],
+    market_map: &MarketMap,
+    bank_map: &BankMap,
+    oracle_map: &mut OracleMap,
+) -> ClearingHouseResult {
+    let mut risk_decreasing = true;
+    for (market_index, worst_case_base_asset_amount_before) in
+        worst_case_base_asset_amounts_before.iter()
+    {
+        let worst_case_base_asset_amount_after = match user.get_position(*market_index) {
+            Ok(market_position) => market_position.worst_case_base_asset_amount()?,
+            Err(_) => 0,
+        };
+
+        if worst_case_base_asset_amount_after.unsigned_abs()
+            > worst_case_base_asset_amount_before.unsigned_abs()
+        {
+            risk_decreasing = false;
+            break;
+        }
+    }
+
+    let meets_initial_maintenance_requirement =
+        meets_initial_margin_requirement(user, market_map, bank_map, oracle_map)?;
+
+    if !meets_initial_maintenance_requirement && !risk_decreasing {
+        return Err(ErrorCode::InsufficientCollateral);
+    }
+
+    Ok(())
+}
+
+/// Places a take profit and a stop loss order linked so that the first to trigger or fill cancels the other
+pub fn place_oco_orders(
+    state: &State,
+    user: &AccountLoader<User>,
+    market_map: &MarketMap,
+    bank_map: &BankMap,
+    oracle_map: &mut OracleMap,
+    clock: &Clock,
+    take_profit_params: OrderParams,
+    stop_loss_params: OrderParams,
+) -> ClearingHouseResult {
+    validate_oco_order_params(&take_profit_params, &stop_loss_params)?;
+
+    let user_key = user.key();
+    let user = &mut load_mut!(user)?;
+
+    let worst_case_base_asset_amounts_before =
+        get_worst_case_base_asset_amounts(user, &[take_profit_params.market_index])?;
+
+    let take_profit_order_id = user.next_order_id;
+    let stop_loss_order_id = take_profit_order_id
+        .checked_add(1)
+        .ok_or_else(math_error!())?;
+
+    _place_order(
+        state,
+        user,
+        &user_key,
+        market_map,
+        bank_map,
+        oracle_map,
+        clock,
+        take_profit_params,
+        stop_loss_order_id,
+    )?;
+
+    _place_order(
+        state,
         user,
         &user_key,
+        market_map,
+        bank_map,
+        oracle_map,
+        clock,
+        stop_loss_params,
+        take_profit_order_id,
+    )?;
+
+    validate_margin_after_placing_orders(
+        user,
+        &worst_case_base_asset_amounts_before,
+        market_map,
+        bank_map,
+        oracle_map,
+    )
+}
+
+fn _place_order(
+    state: &State,
+    user: &mut User,
+    user_key: &Pubkey,
+    market_map: &MarketMap,
+    bank_map: &BankMap,
+    oracle_map: &mut OracleMap,
+    clock: &Clock,
+    params: OrderParams,
+    oco_order_id: u64,
+) -> ClearingHouseResult {
+    let now = clock.unix_timestamp;
+    let slot = clock.slot;
+    controller::funding::settle_funding_payment(
+        user,
+        user_key,
         market_map.get_ref(&params.market_index)?.deref(),
         now,
     )?;
@@ -96,24 +342,84 @@ pub fn place_order(
         }
     }
 
+    if params.quote_asset_amount.is_some() {
+        validate!(
+            params.base_asset_amount == 0,
+            ErrorCode::InvalidOrder,
+            "Quote sized order must not set base_asset_amount"
+        )?;
+
+        validate!(
+            matches!(params.order_type, OrderType::Market | OrderType::Limit)
+                && !params.reduce_only,
+            ErrorCode::InvalidOrder,
+            "Quote sized order must be a non reduce only market or limit order"
+        )?;
+    }
+
+    validate!(
+        !params.post_only_slide || params.post_only,
+        ErrorCode::InvalidOrder,
+        "post_only_slide requires post_only"
+    )?;
+
+    if let Some(max_ts) = params.max_ts {
+        validate!(
+            max_ts > now,
+            ErrorCode::InvalidOrder,
+            "Order max_ts ({}) must be after now ({})",
+            max_ts,
+            now
+        )?;
+    }
+
     let market_index = params.market_index;
     let market = &market_map.get_ref(&market_index)?;
 
     let position_index = get_position_index(&user.positions, market_index)
         .or_else(|_| add_new_position(&mut user.positions, market_index))?;
 
-    let worst_case_base_asset_amount_before =
-        user.positions[position_index].worst_case_base_asset_amount()?;
-
     // Increment open orders for existing position
     let (existing_position_direction, order_base_asset_amount) = {
         let market_position = &mut user.positions[position_index];
         market_position.open_orders += 1;
 
-        let standardized_base_asset_amount = standardize_base_asset_amount(
-            params.base_asset_amount,
-            market.amm.base_asset_amount_step_size,
-        )?;
+        let standardized_base_asset_amount = match params.quote_asset_amount {
+            // limit orders fill at their limit price rather than along the amm curve
+            Some(quote_asset_amount) if params.order_type == OrderType::Limit => {
+                let limit_price = if params.oracle_price_offset != 0 {
+                    let oracle_price = oracle_map.get_price_data(&market.amm.oracle)?.price;
+                    cast_to_u128(
+                        oracle_price
+                            .checked_add(params.oracle_price_offset)
+                            .ok_or_else(math_error!())?,
+                    )?
+                } else {
+                    params.price
+                };
+
+                validate!(
+                    limit_price > 0,
+                    ErrorCode::InvalidOrder,
+                    "Quote sized limit order must have a limit price"
+                )?;
+
+                calculate_base_asset_amount_for_quote_asset_amount_at_price(
+                    quote_asset_amount,
+                    limit_price,
+                    market.amm.base_asset_amount_step_size,
+                )?
+            }
+            Some(quote_asset_amount) => calculate_base_asset_amount_for_quote_asset_amount(
+                quote_asset_amount,
+                params.direction,
+                &market.amm,
+            )?,
+            None => standardize_base_asset_amount(
+                params.base_asset_amount,
+                market.amm.base_asset_amount_step_size,
+            )?,
+        };
 
         let base_asset_amount = if params.reduce_only {
             calculate_base_asset_amount_for_reduce_only_order(
@@ -135,7 +441,7 @@ pub fn place_order(
 
         if !matches!(
             &params.order_type,
-            OrderType::TriggerMarket | OrderType::TriggerLimit
+            OrderType::TriggerMarket | OrderType::TriggerLimit | OrderType::TrailingStop
         ) {
             increase_open_bids_and_asks(market_position, &params.direction, base_asset_amount)?;
         }
@@ -148,19 +454,29 @@ pub fn place_order(
         (existing_position_direction, base_asset_amount)
     };
 
-    let (auction_start_price, auction_end_price) = if let OrderType::Market = params.order_type {
-        let auction_start_price = calculate_auction_start_price(market, params.direction)?;
-        let auction_end_price = if params.price == 0 {
-            calculate_auction_end_price(market, params.direction, order_base_asset_amount)?
+    let (auction_start_price, auction_end_price) =
+        if let OrderType::Market | OrderType::Twap = params.order_type {
+            // twap auctions are sized for a single slice
+            let auction_base_asset_amount = if params.order_type == OrderType::Twap {
+                order_base_asset_amount
+                    .checked_div(max(params.twap_slices, 1) as u128)
+                    .ok_or_else(math_error!())?
+            } else {
+                order_base_asset_amount
+            };
+
+            let auction_start_price = calculate_auction_start_price(market, params.direction)?;
+            let auction_end_price = if params.price == 0 {
+                calculate_auction_end_price(market, params.direction, auction_base_asset_amount)?
+            } else {
+                params.price
+            };
+            (auction_start_price, auction_end_price)
         } else {
-            params.price
+            (0_u128, 0_u128)
         };
-        (auction_start_price, auction_end_price)
-    } else {
-        (0_u128, 0_u128)
-    };
 
-    let new_order = Order {
+    let mut new_order = Order {
         status: OrderStatus::Open,
         order_type: params.order_type,
         ts: now,
@@ -190,6 +506,23 @@ pub fn place_order(
             max(state.min_auction_duration, params.auction_duration),
             state.max_auction_duration,
         ),
+        oco_order_id,
+        trailing_stop_offset: params.trailing_stop_offset,
+        trailing_stop_offset_type: params.trailing_stop_offset_type,
+        max_ts: params.max_ts.unwrap_or(0),
+        iceberg_display_amount: params.iceberg_display_amount.unwrap_or(0),
+        iceberg_visible_amount: params.iceberg_display_amount.unwrap_or(0),
+        twap_slices: params.twap_slices,
+        twap_slot_interval: params.twap_slot_interval,
+        self_trade_prevention: params.self_trade_prevention,
+        fill_or_kill: params.fill_or_kill,
+        quote_asset_amount: params.quote_asset_amount.unwrap_or(0),
+        trigger_oracle: params.trigger_oracle.unwrap_or_default(),
+        twap_start_slot: slot,
+        auction_price_curve: params
+            .auction_price_curve
+            .unwrap_or(market.auction_price_curve),
+        auction_start_oracle_price: 0,
         padding: [0; 3],
     };
 
@@ -200,28 +533,47 @@ pub fn place_order(
         &state.oracle_guard_rails.validity,
     )?;
 
-    validate_order(&new_order, market, state, valid_oracle_price, slot)?;
+    new_order.auction_start_oracle_price = valid_oracle_price.unwrap_or(0);
 
-    user.orders[new_order_index] = new_order;
+    if params.post_only_slide {
+        let limit_price = new_order.get_limit_price(&market.amm, valid_oracle_price, slot)?;
+        if let Some(slide_price) =
+            calculate_post_only_slide_price(new_order.direction, limit_price, &market.amm)?
+        {
+            if new_order.has_oracle_price_offset() {
+                let price_delta = cast_to_i128(slide_price)?
+                    .checked_sub(cast_to_i128(limit_price)?)
+                    .ok_or_else(math_error!())?;
+                new_order.oracle_price_offset = new_order
+                    .oracle_price_offset
+                    .checked_add(price_delta)
+                    .ok_or_else(math_error!())?;
+            } else {
+                new_order.price = slide_price;
+            }
+        }
+    }
 
-    let worst_case_base_asset_amount_after =
-        user.positions[position_index].worst_case_base_asset_amount()?;
+    if new_order.order_type == OrderType::TrailingStop {
+        let oracle_price = valid_oracle_price.ok_or(ErrorCode::InvalidOracle)?;
+        new_order.trigger_price = 0;
+        new_order.trigger_price =
+            calculate_trailing_stop_trigger_price(&new_order, oracle_price.unsigned_abs())?;
+    }
 
-    // Order fails if it's risk increasing and it brings the user collateral below the margin requirement
-    let risk_decreasing = worst_case_base_asset_amount_after.unsigned_abs()
-        <= worst_case_base_asset_amount_before.unsigned_abs();
+    if new_order.has_cross_market_trigger() {
+        // trigger oracle must be passed in so keepers can't be handed an arbitrary account later
+        oracle_map.get_price_data(&new_order.trigger_oracle)?;
+    }
 
-    let meets_initial_maintenance_requirement =
-        meets_initial_margin_requirement(user, market_map, bank_map, oracle_map)?;
+    validate_order(&new_order, market, state, valid_oracle_price, slot)?;
 
-    if !meets_initial_maintenance_requirement && !risk_decreasing {
-        return Err(ErrorCode::InsufficientCollateral);
-    }
+    user.orders[new_order_index] = new_order;
 
     let (taker, taker_order, taker_unsettled_pnl, maker, maker_order, maker_unsettled_pnl) =
-        get_taker_and_maker_for_order_record(&user_key, &new_order, 0);
+        get_taker_and_maker_for_order_record(user_key, &new_order, 0);
 
-    emit_stack::<_, 984>(OrderRecord {
+    emit_stack::<_, 1400>(OrderRecord {
         ts: now,
         slot,
         taker,
@@ -247,53 +599,305 @@ pub fn place_order(
     Ok(())
 }
 
-pub fn cancel_order_by_order_id(
+/// Amends the size and/or price of an open order in place, keeping its order id and slot
+pub fn modify_order(
     order_id: u64,
+    state: &State,
     user: &AccountLoader<User>,
     market_map: &MarketMap,
+    bank_map: &BankMap,
     oracle_map: &mut OracleMap,
     clock: &Clock,
+    params: ModifyOrderParams,
 ) -> ClearingHouseResult {
+    let now = clock.unix_timestamp;
+    let slot = clock.slot;
+
     let user_key = user.key();
     let user = &mut load_mut!(user)?;
-    let order_index = user
-        .orders
-        .iter()
-        .position(|order| order.order_id == order_id)
-        .ok_or_else(print_error!(ErrorCode::OrderDoesNotExist))?;
 
-    cancel_order(
-        order_index,
+    let order_index = user.get_order_index(order_id)?;
+    let (order_status, market_index, order_type, direction, reduce_only) = get_struct_values!(
+        user.orders[order_index],
+        status,
+        market_index,
+        order_type,
+        direction,
+        reduce_only
+    );
+
+    controller::funding::settle_funding_payment(
         user,
         &user_key,
-        market_map,
-        oracle_map,
-        clock.unix_timestamp,
-        clock.slot,
-        OrderActionExplanation::None,
-        None,
-        0,
-        false,
-    )
-}
+        market_map.get_ref(&market_index)?.deref(),
+        now,
+    )?;
 
-pub fn cancel_order_by_user_order_id(
-    user_order_id: u8,
-    user: &AccountLoader<User>,
-    market_map: &MarketMap,
-    oracle_map: &mut OracleMap,
-    clock: &Clock,
-) -> ClearingHouseResult {
-    let user_key = user.key();
-    let user = &mut load_mut!(user)?;
-    let order_index = user
-        .orders
-        .iter()
-        .position(|order| order.user_order_id == user_order_id)
-        .ok_or_else(print_error!(ErrorCode::OrderDoesNotExist))?;
+    validate!(
+        order_status == OrderStatus::Open,
+        ErrorCode::OrderNotOpen,
+        "Order not open"
+    )?;
 
-    cancel_order(
-        order_index,
+    validate!(
+        order_type != OrderType::Market && order_type != OrderType::Twap,
+        ErrorCode::InvalidOrder,
+        "Market and twap orders can not be modified"
+    )?;
+
+    validate!(
+        !user.orders[order_index].is_quote_sized(),
+        ErrorCode::InvalidOrder,
+        "Quote sized orders can not be modified"
+    )?;
+
+    validate_user_not_being_liquidated(
+        user,
+        market_map,
+        bank_map,
+        oracle_map,
+        state.liquidation_margin_buffer_ratio,
+    )?;
+
+    validate!(!user.bankrupt, ErrorCode::UserBankrupt)?;
+
+    let worst_case_base_asset_amounts_before =
+        get_worst_case_base_asset_amounts(user, &[market_index])?;
+
+    let market = &market_map.get_ref(&market_index)?;
+    let position_index = get_position_index(&user.positions, market_index)?;
+
+    if let Some(base_asset_amount) = params.base_asset_amount {
+        let base_asset_amount_filled = user.orders[order_index].base_asset_amount_filled;
+        let standardized_base_asset_amount = standardize_base_asset_amount(
+            base_asset_amount,
+            market.amm.base_asset_amount_step_size,
+        )?;
+
+        validate!(
+            standardized_base_asset_amount > base_asset_amount_filled,
+            ErrorCode::InvalidOrder,
+            "New base asset amount ({}) must be greater than base asset amount filled ({})",
+            standardized_base_asset_amount,
+            base_asset_amount_filled
+        )?;
+
+        let base_asset_amount_unfilled = standardized_base_asset_amount
+            .checked_sub(base_asset_amount_filled)
+            .ok_or_else(math_error!())?;
+
+        let base_asset_amount_unfilled = if reduce_only {
+            calculate_base_asset_amount_for_reduce_only_order(
+                base_asset_amount_unfilled,
+                direction,
+                user.positions[position_index].base_asset_amount,
+            )
+        } else {
+            base_asset_amount_unfilled
+        };
+
+        validate!(
+            base_asset_amount_unfilled >= market.amm.base_asset_amount_step_size,
+            ErrorCode::TradeSizeTooSmall,
+            "Order base asset amount unfilled ({}), smaller than step size ({})",
+            base_asset_amount_unfilled,
+            market.amm.base_asset_amount_step_size
+        )?;
+
+        // Trigger orders only add to open bids/asks once triggered
+        if !user.orders[order_index].must_be_triggered() || user.orders[order_index].triggered {
+            let old_base_asset_amount_unfilled =
+                user.orders[order_index].get_base_asset_amount_unfilled()?;
+            let market_position = &mut user.positions[position_index];
+            decrease_open_bids_and_asks(
+                market_position,
+                &direction,
+                old_base_asset_amount_unfilled,
+            )?;
+            increase_open_bids_and_asks(market_position, &direction, base_asset_amount_unfilled)?;
+        }
+
+        user.orders[order_index].base_asset_amount = base_asset_amount_filled
+            .checked_add(base_asset_amount_unfilled)
+            .ok_or_else(math_error!())?;
+
+        if user.orders[order_index].is_iceberg() {
+            user.orders[order_index].iceberg_visible_amount = min(
+                user.orders[order_index].iceberg_display_amount,
+                base_asset_amount_unfilled,
+            );
+        }
+    }
+
+    if let Some(price) = params.price {
+        user.orders[order_index].price = price;
+    }
+
+    let valid_oracle_price = get_valid_oracle_price(
+        oracle_map.get_price_data(&market.amm.oracle)?,
+        market,
+        &user.orders[order_index],
+        &state.oracle_guard_rails.validity,
+    )?;
+
+    validate_order(
+        &user.orders[order_index],
+        market,
+        state,
+        valid_oracle_price,
+        slot,
+    )?;
+
+    validate_margin_after_placing_orders(
+        user,
+        &worst_case_base_asset_amounts_before,
+        market_map,
+        bank_map,
+        oracle_map,
+    )?;
+
+    let (taker, taker_order, taker_unsettled_pnl, maker, maker_order, maker_unsettled_pnl) =
+        get_taker_and_maker_for_order_record(&user_key, &user.orders[order_index], 0);
+
+    emit_stack::<_, 1400>(OrderRecord {
+        ts: now,
+        slot,
+        taker,
+        taker_order,
+        maker,
+        maker_order,
+        maker_pnl: maker_unsettled_pnl,
+        taker_pnl: taker_unsettled_pnl,
+        action: OrderAction::Modify,
+        action_explanation: OrderActionExplanation::None,
+        filler: Pubkey::default(),
+        fill_record_id: 0,
+        market_index,
+        base_asset_amount_filled: 0,
+        quote_asset_amount_filled: 0,
+        filler_reward: 0,
+        taker_fee: 0,
+        maker_rebate: 0,
+        quote_asset_amount_surplus: 0,
+        oracle_price: oracle_map.get_price_data(&market.amm.oracle)?.price,
+    });
+
+    Ok(())
+}
+
+pub fn cancel_order_by_order_id(
+    order_id: u64,
+    user: &AccountLoader<User>,
+    market_map: &MarketMap,
+    oracle_map: &mut OracleMap,
+    clock: &Clock,
+) -> ClearingHouseResult {
+    let user_key = user.key();
+    let user = &mut load_mut!(user)?;
+    let order_index = user
+        .orders
+        .iter()
+        .position(|order| order.order_id == order_id)
+        .ok_or_else(print_error!(ErrorCode::OrderDoesNotExist))?;
+
+    cancel_order(
+        order_index,
+        user,
+        &user_key,
+        market_map,
+        oracle_map,
+        clock.unix_timestamp,
+        clock.slot,
+        OrderActionExplanation::None,
+        None,
+        0,
+        false,
+    )
+}
+
+pub fn cancel_orders(
+    user: &AccountLoader<User>,
+    market_map: &MarketMap,
+    oracle_map: &mut OracleMap,
+    clock: &Clock,
+    market_index: Option<u64>,
+    direction: Option<PositionDirection>,
+) -> ClearingHouseResult {
+    let user_key = user.key();
+    let user = &mut load_mut!(user)?;
+
+    _cancel_orders(
+        user,
+        &user_key,
+        market_map,
+        oracle_map,
+        clock,
+        market_index,
+        direction,
+    )
+}
+
+fn _cancel_orders(
+    user: &mut User,
+    user_key: &Pubkey,
+    market_map: &MarketMap,
+    oracle_map: &mut OracleMap,
+    clock: &Clock,
+    market_index: Option<u64>,
+    direction: Option<PositionDirection>,
+) -> ClearingHouseResult {
+    for order_index in 0..user.orders.len() {
+        if user.orders[order_index].status != OrderStatus::Open {
+            continue;
+        }
+
+        if let Some(market_index) = market_index {
+            if user.orders[order_index].market_index != market_index {
+                continue;
+            }
+        }
+
+        if let Some(direction) = direction {
+            if user.orders[order_index].direction != direction {
+                continue;
+            }
+        }
+
+        cancel_order(
+            order_index,
+            user,
+            user_key,
+            market_map,
+            oracle_map,
+            clock.unix_timestamp,
+            clock.slot,
+            OrderActionExplanation::None,
+            None,
+            0,
+            false,
+        )?;
+    }
+
+    Ok(())
+}
+
+pub fn cancel_order_by_user_order_id(
+    user_order_id: u8,
+    user: &AccountLoader<User>,
+    market_map: &MarketMap,
+    oracle_map: &mut OracleMap,
+    clock: &Clock,
+) -> ClearingHouseResult {
+    let user_key = user.key();
+    let user = &mut load_mut!(user)?;
+    let order_index = user
+        .orders
+        .iter()
+        .position(|order| order.user_order_id == user_order_id)
+        .ok_or_else(print_error!(ErrorCode::OrderDoesNotExist))?;
+
+    cancel_order(
+        order_index,
         user,
         &user_key,
         market_map,
@@ -345,7 +949,7 @@ pub fn cancel_order(
                 -cast(filler_reward)?,
             );
 
-        emit_stack::<_, 984>(OrderRecord {
+        emit_stack::<_, 1400>(OrderRecord {
             ts: now,
             slot,
             taker,
@@ -374,18 +978,103 @@ pub fn cancel_order(
 
     // Decrement open orders for existing position
     let position_index = get_position_index(&user.positions, order_market_index)?;
-    let base_asset_amount_unfilled = user.orders[order_index].get_base_asset_amount_unfilled()?;
-    position::decrease_open_bids_and_asks(
-        &mut user.positions[position_index],
-        &order_direction,
-        base_asset_amount_unfilled,
-    )?;
+    // Trigger orders only add to open bids/asks once triggered
+    if !user.orders[order_index].must_be_triggered() || user.orders[order_index].triggered {
+        let base_asset_amount_unfilled =
+            user.orders[order_index].get_base_asset_amount_unfilled()?;
+        position::decrease_open_bids_and_asks(
+            &mut user.positions[position_index],
+            &order_direction,
+            base_asset_amount_unfilled,
+        )?;
+    }
     user.positions[position_index].open_orders -= 1;
+
+    // the linked oco order survives on its own once one leg is canceled
+    let oco_order_id = user.orders[order_index].oco_order_id;
+    if oco_order_id != 0 {
+        if let Some(oco_order) = user
+            .orders
+            .iter_mut()
+            .find(|order| order.order_id == oco_order_id && order.status == OrderStatus::Open)
+        {
+            oco_order.oco_order_id = 0;
+        }
+    }
+
     user.orders[order_index] = Order::default();
 
     Ok(())
 }
 
+/// Cancels the order linked to an oco order once that order is no longer open after a fill. Fully
+/// filled orders get reset, so the oco order id has to be read before the fill.
+fn cancel_oco_order_if_filled(
+    user: &mut User,
+    order_index: usize,
+    oco_order_id: u64,
+    user_key: &Pubkey,
+    market_map: &MarketMap,
+    oracle_map: &mut OracleMap,
+    now: i64,
+    slot: u64,
+    filler_key: &Pubkey,
+) -> ClearingHouseResult {
+    if user.orders[order_index].status == OrderStatus::Open {
+        return Ok(());
+    }
+
+    cancel_oco_order(
+        user,
+        oco_order_id,
+        user_key,
+        market_map,
+        oracle_map,
+        now,
+        slot,
+        filler_key,
+    )
+}
+
+/// Cancels the order linked to a filled oco order, if it is still open
+fn cancel_oco_order(
+    user: &mut User,
+    oco_order_id: u64,
+    user_key: &Pubkey,
+    market_map: &MarketMap,
+    oracle_map: &mut OracleMap,
+    now: i64,
+    slot: u64,
+    filler_key: &Pubkey,
+) -> ClearingHouseResult {
+    if oco_order_id == 0 {
+        return Ok(());
+    }
+
+    let order_index = match user
+        .orders
+        .iter()
+        .position(|order| order.order_id == oco_order_id && order.status == OrderStatus::Open)
+    {
+        Some(order_index) => order_index,
+        None => return Ok(()),
+    };
+
+    cancel_order(
+        order_index,
+        user,
+        user_key,
+        market_map,
+        oracle_map,
+        now,
+        slot,
+        OrderActionExplanation::CanceledForOcoOrder,
+        Some(filler_key),
+        0,
+        false,
+    )
+}
+
 pub fn fill_order(
     order_id: u64,
     state: &State,
@@ -396,9 +1085,7 @@ pub fn fill_order(
     oracle_map: &mut OracleMap,
     filler: &AccountLoader<User>,
     filler_stats: &AccountLoader<UserStats>,
-    maker: Option<&AccountLoader<User>>,
-    maker_stats: Option<&AccountLoader<UserStats>>,
-    maker_order_id: Option<u64>,
+    makers: &[(AccountLoader<User>, AccountLoader<UserStats>, u64)],
     clock: &Clock,
 ) -> ClearingHouseResult<(u128, bool)> {
     let now = clock.unix_timestamp;
@@ -406,6 +1093,7 @@ pub fn fill_order(
 
     let filler_key = filler.key();
     let user_key = user.key();
+    let user_stats_key = user_stats.key();
     let user = &mut load_mut!(user)?;
     let user_stats = &mut load_mut!(user_stats)?;
 
@@ -415,8 +1103,9 @@ pub fn fill_order(
         .position(|order| order.order_id == order_id)
         .ok_or_else(print_error!(ErrorCode::OrderDoesNotExist))?;
 
-    let (order_status, market_index) =
-        get_struct_values!(user.orders[order_index], status, market_index);
+    let (order_status, market_index, oco_order_id) =
+        get_struct_values!(user.orders[order_index], status, market_index, oco_order_id);
+    let has_oco_order = user.orders[order_index].has_oco_order();
 
     controller::funding::settle_funding_payment(
         user,
@@ -447,6 +1136,11 @@ pub fn fill_order(
         state.liquidation_margin_buffer_ratio,
     )?;
 
+    if user.orders[order_index].order_type == OrderType::Twap {
+        let market = market_map.get_ref(&market_index)?;
+        update_twap_order_slice(&mut user.orders[order_index], market.deref(), slot)?;
+    }
+
     let mark_price_before: u128;
     let oracle_mark_spread_pct_before: i128;
     let is_oracle_valid: bool;
@@ -480,33 +1174,85 @@ pub fn fill_order(
         None
     };
 
+    let quote_asset_amount_filled = if user.orders[order_index].is_quote_sized() {
+        let market = market_map.get_ref(&market_index)?;
+        !update_quote_sized_order(user, order_index, market.deref(), valid_oracle_price, slot)?
+    } else {
+        false
+    };
+
     let is_filler_taker = user_key == filler_key;
-    let is_filler_maker = maker.map_or(false, |maker| maker.key() == filler_key);
+    let is_filler_maker = makers.iter().any(|(maker, _, _)| maker.key() == filler_key);
     let (mut filler, mut filler_stats) = if !is_filler_maker && !is_filler_taker {
         (Some(load_mut!(filler)?), Some(load_mut!(filler_stats)?))
     } else {
         (None, None)
     };
 
-    let (mut maker, mut maker_stats, maker_key, maker_order_index) = sanitize_maker_order(
-        market_map,
-        oracle_map,
-        maker,
-        maker_stats,
-        maker_order_id,
-        &user_key,
-        &user.orders[order_index],
-        &mut filler.as_deref_mut(),
-        &filler_key,
-        state.fee_structure.cancel_order_fee,
-        oracle_price,
-        now,
-        slot,
-    )?;
+    let mut sanitized_makers = Vec::with_capacity(makers.len());
+    let mut makers_stats: Vec<(Pubkey, RefMut<UserStats>)> = Vec::with_capacity(makers.len());
+    for (maker, maker_stats, maker_order_id) in makers.iter() {
+        // a maker account can only be borrowed once per fill
+        if sanitized_makers
+            .iter()
+            .any(|(_, _, maker_key, _)| *maker_key == maker.key())
+        {
+            continue;
+        }
+
+        if let Some((maker, maker_key, maker_order_index)) = sanitize_maker_order(
+            market_map,
+            oracle_map,
+            maker,
+            *maker_order_id,
+            &user_key,
+            user,
+            order_index,
+            &mut filler.as_deref_mut(),
+            &filler_key,
+            state.fee_structure.cancel_order_fee,
+            oracle_price,
+            now,
+            slot,
+        )? {
+            // self trades allowed by the taker cant borrow the taker's user stats a second time
+            let maker_stats_key = maker_stats.key();
+            if maker_stats_key == user_stats_key {
+                continue;
+            }
+
+            // sub accounts of the same authority share one user stats account
+            let maker_stats_index = match makers_stats
+                .iter()
+                .position(|(key, _)| *key == maker_stats_key)
+            {
+                Some(maker_stats_index) => maker_stats_index,
+                None => {
+                    makers_stats.push((maker_stats_key, load_mut!(maker_stats)?));
+                    makers_stats.len() - 1
+                }
+            };
+
+            sanitized_makers.push((maker, maker_stats_index, maker_key, maker_order_index));
+        }
+
+        // self trade prevention can cancel the taker order
+        if user.orders[order_index].status != OrderStatus::Open {
+            return Ok((0, true));
+        }
+    }
 
-    let should_expire_order =
-        should_expire_order(user, order_index, slot, state.max_auction_duration)?;
-    if should_expire_order {
+    let expire_explanation = if user.orders[order_index].is_past_max_ts(now) {
+        Some(OrderActionExplanation::OrderExpired)
+    } else if should_expire_order(user, order_index, slot, state.max_auction_duration)? {
+        Some(OrderActionExplanation::MarketOrderAuctionExpired)
+    } else if quote_asset_amount_filled {
+        Some(OrderActionExplanation::QuoteAssetAmountFilled)
+    } else {
+        None
+    };
+
+    if let Some(explanation) = expire_explanation {
         let filler_reward = {
             let mut market = market_map.get_ref_mut(&market_index)?;
             pay_keeper_flat_reward(
@@ -525,7 +1271,7 @@ pub fn fill_order(
             oracle_map,
             now,
             slot,
-            OrderActionExplanation::MarketOrderAuctionExpired,
+            explanation,
             Some(&filler_key),
             filler_reward,
             false,
@@ -533,15 +1279,31 @@ pub fn fill_order(
         return Ok((0, true));
     }
 
+    let maker_oco_order_ids = sanitized_makers
+        .iter()
+        .map(|(maker, _, _, maker_order_index)| maker.orders[*maker_order_index].oco_order_id)
+        .collect::<Vec<_>>();
+
     let (base_asset_amount, potentially_risk_increasing, mut updated_user_state) = fulfill_order(
         user,
         order_index,
         &user_key,
         user_stats,
-        &mut maker.as_deref_mut(),
-        &mut maker_stats.as_deref_mut(),
-        maker_order_index,
-        maker_key.as_ref(),
+        &mut sanitized_makers
+            .iter_mut()
+            .map(|(maker, maker_stats_index, maker_key, maker_order_index)| {
+                (
+                    maker.deref_mut(),
+                    *maker_stats_index,
+                    &*maker_key,
+                    *maker_order_index,
+                )
+            })
+            .collect::<Vec<_>>(),
+        &mut makers_stats
+            .iter_mut()
+            .map(|(_, maker_stats)| maker_stats.deref_mut())
+            .collect::<Vec<_>>(),
         &mut filler.as_deref_mut(),
         &filler_key,
         &mut filler_stats.as_deref_mut(),
@@ -555,6 +1317,40 @@ pub fn fill_order(
         slot,
     )?;
 
+    update_iceberg_visible_amount(&mut user.orders[order_index])?;
+
+    // a partial fill leaves the oco order protecting the rest of the position
+    if has_oco_order && base_asset_amount != 0 {
+        cancel_oco_order_if_filled(
+            user,
+            order_index,
+            oco_order_id,
+            &user_key,
+            market_map,
+            oracle_map,
+            now,
+            slot,
+            &filler_key,
+        )?;
+    }
+
+    for ((maker, _, maker_key, maker_order_index), maker_oco_order_id) in sanitized_makers
+        .iter_mut()
+        .zip(maker_oco_order_ids.into_iter())
+    {
+        cancel_oco_order_if_filled(
+            maker,
+            *maker_order_index,
+            maker_oco_order_id,
+            maker_key,
+            market_map,
+            oracle_map,
+            now,
+            slot,
+            &filler_key,
+        )?;
+    }
+
     if should_cancel_order_after_fulfill(user, order_index, slot)? {
         updated_user_state = true;
 
@@ -639,102 +1435,638 @@ pub fn fill_order(
     Ok((base_asset_amount, updated_user_state))
 }
 
+/// Matches two resting limit orders that cross each other. The earlier order is the maker and
+/// sets the fill price, the later order pays the taker fee.
+pub fn match_orders(
+    order_id: u64,
+    counterparty_order_id: u64,
+    state: &State,
+    user: &AccountLoader<User>,
+    user_stats: &AccountLoader<UserStats>,
+    counterparty: &AccountLoader<User>,
+    counterparty_stats: &AccountLoader<UserStats>,
+    bank_map: &BankMap,
+    market_map: &MarketMap,
+    oracle_map: &mut OracleMap,
+    filler: &AccountLoader<User>,
+    filler_stats: &AccountLoader<UserStats>,
+    clock: &Clock,
+) -> ClearingHouseResult<u128> {
+    let now = clock.unix_timestamp;
+    let slot = clock.slot;
+
+    let filler_key = filler.key();
+    let user_key = user.key();
+    let counterparty_key = counterparty.key();
+
+    validate!(
+        user_key != counterparty_key,
+        ErrorCode::InvalidOrder,
+        "Cannot match orders from the same user"
+    )?;
+
+    let user = &mut load_mut!(user)?;
+    let user_stats = &mut load_mut!(user_stats)?;
+    let counterparty = &mut load_mut!(counterparty)?;
+
+    validate!(
+        user.authority != counterparty.authority,
+        ErrorCode::InvalidOrder,
+        "Cannot match orders from the same authority"
+    )?;
+
+    let counterparty_stats = &mut load_mut!(counterparty_stats)?;
+
+    let order_index = user
+        .orders
+        .iter()
+        .position(|order| order.order_id == order_id)
+        .ok_or_else(print_error!(ErrorCode::OrderDoesNotExist))?;
+
+    let counterparty_order_index = counterparty
+        .orders
+        .iter()
+        .position(|order| order.order_id == counterparty_order_id)
+        .ok_or_else(print_error!(ErrorCode::OrderDoesNotExist))?;
+
+    let market_index = user.orders[order_index].market_index;
+
+    validate!(
+        are_orders_same_market_but_different_sides(
+            &user.orders[order_index],
+            &counterparty.orders[counterparty_order_index],
+        ),
+        ErrorCode::OrdersDoNotCross,
+        "Orders must be on opposite sides of the same market"
+    )?;
+
+    let (mut filler, mut filler_stats) = if filler_key != user_key && filler_key != counterparty_key
+    {
+        (Some(load_mut!(filler)?), Some(load_mut!(filler_stats)?))
+    } else {
+        (None, None)
+    };
+
+    let oracle_price = {
+        let market = market_map.get_ref(&market_index)?;
+        oracle_map.get_price_data(&market.amm.oracle)?.price
+    };
+
+    let mut canceled_resting_order = false;
+    for (_user, _user_key, _order_index) in [
+        (&mut **user, &user_key, order_index),
+        (
+            &mut **counterparty,
+            &counterparty_key,
+            counterparty_order_index,
+        ),
+    ] {
+        validate_resting_order(&_user.orders[_order_index])?;
+
+        controller::funding::settle_funding_payment(
+            _user,
+            _user_key,
+            market_map.get_ref_mut(&market_index)?.deref_mut(),
+            now,
+        )?;
+
+        validate!(!_user.bankrupt, ErrorCode::UserBankrupt)?;
+
+        validate_user_not_being_liquidated(
+            _user,
+            market_map,
+            bank_map,
+            oracle_map,
+            state.liquidation_margin_buffer_ratio,
+        )?;
+
+        let mut cancel_explanation = get_resting_order_cancel_explanation(
+            &_user.orders[_order_index],
+            market_map.get_ref(&market_index)?.deref(),
+            oracle_price,
+            now,
+            slot,
+        )?;
+
+        if cancel_explanation.is_none() && _user.orders[_order_index].is_quote_sized() {
+            let market = market_map.get_ref(&market_index)?;
+            if !update_quote_sized_order(
+                _user,
+                _order_index,
+                market.deref(),
+                Some(oracle_price),
+                slot,
+            )? {
+                cancel_explanation = Some(OrderActionExplanation::QuoteAssetAmountFilled);
+            }
+        }
+
+        if let Some(explanation) = cancel_explanation {
+            let filler_reward = {
+                let mut market = market_map.get_ref_mut(&market_index)?;
+                pay_keeper_flat_reward(
+                    _user,
+                    filler.as_deref_mut(),
+                    market.deref_mut(),
+                    state.fee_structure.cancel_order_fee,
+                )?
+            };
+
+            cancel_order(
+                _order_index,
+                _user,
+                _user_key,
+                market_map,
+                oracle_map,
+                now,
+                slot,
+                explanation,
+                Some(&filler_key),
+                filler_reward,
+                false,
+            )?;
+            canceled_resting_order = true;
+        }
+    }
+
+    // the keeper is paid for clearing stale orders even though nothing is matched
+    if canceled_resting_order {
+        return Ok(0);
+    }
+
+    let (order_slot, order_ts) = get_struct_values!(user.orders[order_index], slot, ts);
+    let (counterparty_order_slot, counterparty_order_ts) =
+        get_struct_values!(counterparty.orders[counterparty_order_index], slot, ts);
+
+    let user_is_maker = (order_slot, order_ts) <= (counterparty_order_slot, counterparty_order_ts);
+
+    let (
+        (taker, taker_stats, taker_order_index, taker_key),
+        (maker, maker_stats, maker_order_index, maker_key),
+    ) = if user_is_maker {
+        (
+            (
+                counterparty,
+                counterparty_stats,
+                counterparty_order_index,
+                &counterparty_key,
+            ),
+            (user, user_stats, order_index, &user_key),
+        )
+    } else {
+        (
+            (user, user_stats, order_index, &user_key),
+            (
+                counterparty,
+                counterparty_stats,
+                counterparty_order_index,
+                &counterparty_key,
+            ),
+        )
+    };
+
+    validate!(
+        !taker.orders[taker_order_index].post_only,
+        ErrorCode::InvalidOrder,
+        "Later order is post only and can not take the earlier order"
+    )?;
+
+    let taker_oco_order_id = taker.orders[taker_order_index].oco_order_id;
+    let maker_oco_order_id = maker.orders[maker_order_index].oco_order_id;
+
+    let taker_position_index = get_position_index(&taker.positions, market_index)?;
+    let taker_worst_case_base_asset_amount_before =
+        taker.positions[taker_position_index].worst_case_base_asset_amount()?;
+    let maker_position_index = get_position_index(&maker.positions, market_index)?;
+    let maker_worst_case_base_asset_amount_before =
+        maker.positions[maker_position_index].worst_case_base_asset_amount()?;
+
+    let mut order_records: Vec<OrderRecord> = vec![];
+    let (base_asset_amount, _) = {
+        let mut market = market_map.get_ref_mut(&market_index)?;
+        fulfill_order_with_match(
+            market.deref_mut(),
+            taker,
+            taker_stats,
+            taker_order_index,
+            taker_key,
+            maker,
+            maker_stats,
+            maker_order_index,
+            maker_key,
+            filler.as_deref_mut(),
+            filler_stats.as_deref_mut(),
+            &filler_key,
+            now,
+            slot,
+            &state.fee_structure,
+            oracle_map,
+            &mut order_records,
+        )?
+    };
+
+    validate!(
+        base_asset_amount != 0,
+        ErrorCode::OrdersDoNotCross,
+        "Orders do not cross"
+    )?;
+
+    update_iceberg_visible_amount(&mut taker.orders[taker_order_index])?;
+
+    for (_user, position_index, worst_case_base_asset_amount_before) in [
+        (
+            &*taker,
+            taker_position_index,
+            taker_worst_case_base_asset_amount_before,
+        ),
+        (
+            &*maker,
+            maker_position_index,
+            maker_worst_case_base_asset_amount_before,
+        ),
+    ] {
+        let risk_decreasing = _user.positions[position_index]
+            .worst_case_base_asset_amount()?
+            .unsigned_abs()
+            < worst_case_base_asset_amount_before.unsigned_abs();
+
+        validate!(
+            risk_decreasing
+                || meets_initial_margin_requirement(_user, market_map, bank_map, oracle_map)?,
+            ErrorCode::InsufficientCollateral,
+            "Matched order breaches margin requirement"
+        )?;
+    }
+
+    for (_user, _user_key, _order_index, _oco_order_id) in [
+        (
+            &mut **taker,
+            taker_key,
+            taker_order_index,
+            taker_oco_order_id,
+        ),
+        (
+            &mut **maker,
+            maker_key,
+            maker_order_index,
+            maker_oco_order_id,
+        ),
+    ] {
+        cancel_oco_order_if_filled(
+            _user,
+            _order_index,
+            _oco_order_id,
+            _user_key,
+            market_map,
+            oracle_map,
+            now,
+            slot,
+            &filler_key,
+        )?;
+    }
+
+    for order_record in order_records {
+        emit!(order_record)
+    }
+
+    // Try to update the funding rate at the end of every trade
+    {
+        let market = &mut market_map.get_ref_mut(&market_index)?;
+        controller::funding::update_funding_rate(
+            market_index,
+            market,
+            oracle_map,
+            now,
+            &state.oracle_guard_rails,
+            state.funding_paused,
+            None,
+        )?;
+    }
+
+    Ok(base_asset_amount)
+}
+
+fn validate_resting_order(order: &Order) -> ClearingHouseResult {
+    validate!(
+        order.status == OrderStatus::Open,
+        ErrorCode::OrderNotOpen,
+        "Order not open"
+    )?;
+
+    validate!(
+        matches!(order.order_type, OrderType::Limit | OrderType::TriggerLimit),
+        ErrorCode::InvalidOrder,
+        "Only resting limit orders can be matched"
+    )?;
+
+    validate!(
+        !order.must_be_triggered() || order.triggered,
+        ErrorCode::OrderMustBeTriggeredFirst,
+        "Order must be triggered first"
+    )?;
+
+    Ok(())
+}
+
+/// Resting orders are canceled instead of filled once they expire or the oracle moves past their
+/// limit price
+fn get_resting_order_cancel_explanation(
+    order: &Order,
+    market: &Market,
+    oracle_price: i128,
+    now: i64,
+    slot: u64,
+) -> ClearingHouseResult<Option<OrderActionExplanation>> {
+    let explanation = if order.is_past_max_ts(now) {
+        Some(OrderActionExplanation::OrderExpired)
+    } else if order_breaches_oracle_price_limits(market, order, oracle_price, slot)? {
+        Some(OrderActionExplanation::OraclePriceBreachedLimitPrice)
+    } else {
+        None
+    };
+
+    Ok(explanation)
+}
+
 #[allow(clippy::type_complexity)]
 fn sanitize_maker_order<'a>(
     market_map: &MarketMap,
     oracle_map: &mut OracleMap,
-    maker: Option<&'a AccountLoader<User>>,
-    maker_stats: Option<&'a AccountLoader<UserStats>>,
-    maker_order_id: Option<u64>,
-    taker_key: &Pubkey,
-    taker_order: &Order,
-    filler: &mut Option<&mut User>,
+    maker: &'a AccountLoader<User>,
+    maker_order_id: u64,
+    taker_key: &Pubkey,
+    taker: &mut User,
+    taker_order_index: usize,
+    filler: &mut Option<&mut User>,
+    filler_key: &Pubkey,
+    filler_reward: u128,
+    oracle_price: i128,
+    now: i64,
+    slot: u64,
+) -> ClearingHouseResult<Option<(RefMut<'a, User>, Pubkey, usize)>> {
+    if &maker.key() == taker_key {
+        if taker.orders[taker_order_index].self_trade_prevention != SelfTradePrevention::None {
+            let maker_order_index = taker.get_order_index(maker_order_id)?;
+            prevent_self_trade(
+                taker,
+                maker_order_index,
+                taker_key,
+                None,
+                taker_order_index,
+                taker_key,
+                market_map,
+                oracle_map,
+                filler_key,
+                oracle_price,
+                now,
+                slot,
+            )?;
+        }
+        return Ok(None);
+    }
+
+    let maker_key = maker.key();
+    let mut maker = load_mut!(maker)?;
+    let maker_order_index = maker.get_order_index(maker_order_id)?;
+
+    {
+        let is_self_trade = maker.authority == taker.authority;
+        if !is_maker_for_taker(
+            &maker.orders[maker_order_index],
+            &taker.orders[taker_order_index],
+            is_self_trade,
+        )? {
+            if is_self_trade {
+                prevent_self_trade(
+                    &mut maker,
+                    maker_order_index,
+                    &maker_key,
+                    Some(taker),
+                    taker_order_index,
+                    taker_key,
+                    market_map,
+                    oracle_map,
+                    filler_key,
+                    oracle_price,
+                    now,
+                    slot,
+                )?;
+            }
+            return Ok(None);
+        }
+    }
+
+    {
+        let maker_order = &maker.orders[maker_order_index];
+
+        if maker.being_liquidated || maker.bankrupt {
+            return Ok(None);
+        }
+
+        validate!(
+            !maker_order.must_be_triggered() || maker_order.triggered,
+            ErrorCode::OrderMustBeTriggeredFirst,
+            "Maker order not triggered"
+        )?;
+    }
+
+    // Dont fulfill with a maker order if it's stale or oracle has diverged significantly
+    let mut cancel_explanation = get_resting_order_cancel_explanation(
+        &maker.orders[maker_order_index],
+        market_map
+            .get_ref(&maker.orders[maker_order_index].market_index)?
+            .deref(),
+        oracle_price,
+        now,
+        slot,
+    )?;
+
+    if cancel_explanation.is_none() && maker.orders[maker_order_index].is_quote_sized() {
+        let market = market_map.get_ref(&maker.orders[maker_order_index].market_index)?;
+        if !update_quote_sized_order(
+            &mut maker,
+            maker_order_index,
+            market.deref(),
+            Some(oracle_price),
+            slot,
+        )? {
+            cancel_explanation = Some(OrderActionExplanation::QuoteAssetAmountFilled);
+        }
+    }
+
+    if let Some(explanation) = cancel_explanation {
+        let filler_reward = {
+            let mut market =
+                market_map.get_ref_mut(&maker.orders[maker_order_index].market_index)?;
+            pay_keeper_flat_reward(
+                &mut maker,
+                filler.as_deref_mut(),
+                market.deref_mut(),
+                filler_reward,
+            )?
+        };
+
+        cancel_order(
+            maker_order_index,
+            maker.deref_mut(),
+            &maker_key,
+            market_map,
+            oracle_map,
+            now,
+            slot,
+            explanation,
+            Some(filler_key),
+            filler_reward,
+            false,
+        )?;
+        return Ok(None);
+    }
+
+    Ok(Some((maker, maker_key, maker_order_index)))
+}
+
+/// Applies the taker order's self trade prevention mode to a crossing maker order owned by the
+/// same authority. When taker is None, the maker order lives on the taker's user account.
+#[allow(clippy::too_many_arguments)]
+fn prevent_self_trade(
+    maker: &mut User,
+    maker_order_index: usize,
+    maker_key: &Pubkey,
+    taker: Option<&mut User>,
+    taker_order_index: usize,
+    taker_key: &Pubkey,
+    market_map: &MarketMap,
+    oracle_map: &mut OracleMap,
+    filler_key: &Pubkey,
+    oracle_price: i128,
+    now: i64,
+    slot: u64,
+) -> ClearingHouseResult {
+    let (maker_decrement, taker_decrement) = {
+        let taker_order = match &taker {
+            Some(taker) => &taker.orders[taker_order_index],
+            None => &maker.orders[taker_order_index],
+        };
+        let maker_order = &maker.orders[maker_order_index];
+
+        if taker_order.self_trade_prevention == SelfTradePrevention::None
+            || maker_order.status != OrderStatus::Open
+            || (maker_order.must_be_triggered() && !maker_order.triggered)
+            || !are_orders_same_market_but_different_sides(maker_order, taker_order)
+        {
+            return Ok(());
+        }
+
+        let market = market_map.get_ref(&taker_order.market_index)?;
+        let maker_price = maker_order.get_limit_price(&market.amm, Some(oracle_price), slot)?;
+        let taker_price = taker_order.get_limit_price(&market.amm, Some(oracle_price), slot)?;
+        if !do_orders_cross(&maker_order.direction, maker_price, taker_price) {
+            return Ok(());
+        }
+
+        calculate_self_trade_prevention_decrements(maker_order, taker_order)?
+    };
+
+    decrement_order_for_self_trade(
+        maker,
+        maker_order_index,
+        maker_key,
+        maker_decrement,
+        market_map,
+        oracle_map,
+        filler_key,
+        now,
+        slot,
+    )?;
+
+    match taker {
+        Some(taker) => decrement_order_for_self_trade(
+            taker,
+            taker_order_index,
+            taker_key,
+            taker_decrement,
+            market_map,
+            oracle_map,
+            filler_key,
+            now,
+            slot,
+        ),
+        None => decrement_order_for_self_trade(
+            maker,
+            taker_order_index,
+            taker_key,
+            taker_decrement,
+            market_map,
+            oracle_map,
+            filler_key,
+            now,
+            slot,
+        ),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn decrement_order_for_self_trade(
+    user: &mut User,
+    order_index: usize,
+    user_key: &Pubkey,
+    decrement: u128,
+    market_map: &MarketMap,
+    oracle_map: &mut OracleMap,
     filler_key: &Pubkey,
-    filler_reward: u128,
-    oracle_price: i128,
     now: i64,
     slot: u64,
-) -> ClearingHouseResult<(
-    Option<RefMut<'a, User>>,
-    Option<RefMut<'a, UserStats>>,
-    Option<Pubkey>,
-    Option<usize>,
-)> {
-    if maker.is_none() || maker_stats.is_none() {
-        return Ok((None, None, None, None));
-    }
-
-    let maker = maker.unwrap();
-    let maker_stats = maker_stats.unwrap();
-    if &maker.key() == taker_key {
-        return Ok((None, None, None, None));
+) -> ClearingHouseResult {
+    if decrement == 0 {
+        return Ok(());
     }
 
-    let maker_key = maker.key();
-    let mut maker = load_mut!(maker)?;
-    let maker_stats = load_mut!(maker_stats)?;
-    let maker_order_index =
-        maker.get_order_index(maker_order_id.ok_or(ErrorCode::MakerOrderNotFound)?)?;
+    let base_asset_amount_unfilled = user.orders[order_index].get_base_asset_amount_unfilled()?;
+    let base_asset_amount_step_size = market_map
+        .get_ref(&user.orders[order_index].market_index)?
+        .amm
+        .base_asset_amount_step_size;
 
+    // twap slices are released from the original size, so a twap order can't be shrunk.
+    // a remainder below the step size could never be filled
+    if decrement >= base_asset_amount_unfilled
+        || user.orders[order_index].order_type == OrderType::Twap
+        || base_asset_amount_unfilled - decrement < base_asset_amount_step_size
     {
-        let maker_order = &maker.orders[maker_order_index];
-        if !is_maker_for_taker(maker_order, taker_order)? {
-            return Ok((None, None, None, None));
-        }
-
-        if maker.being_liquidated || maker.bankrupt {
-            return Ok((None, None, None, None));
-        }
-
-        validate!(
-            !maker_order.must_be_triggered() || maker_order.triggered,
-            ErrorCode::OrderMustBeTriggeredFirst,
-            "Maker order not triggered"
-        )?;
-    }
-
-    // Dont fulfill with a maker order if oracle has diverged significantly
-    if order_breaches_oracle_price_limits(
-        market_map
-            .get_ref(&maker.orders[maker_order_index].market_index)?
-            .deref(),
-        &maker.orders[maker_order_index],
-        oracle_price,
-        slot,
-    )? {
-        let filler_reward = {
-            let mut market =
-                market_map.get_ref_mut(&maker.orders[maker_order_index].market_index)?;
-            pay_keeper_flat_reward(
-                &mut maker,
-                filler.as_deref_mut(),
-                market.deref_mut(),
-                filler_reward,
-            )?
-        };
-
-        cancel_order(
-            maker_order_index,
-            maker.deref_mut(),
-            &maker_key,
+        return cancel_order(
+            order_index,
+            user,
+            user_key,
             market_map,
             oracle_map,
             now,
             slot,
-            OrderActionExplanation::OraclePriceBreachedLimitPrice,
+            OrderActionExplanation::CanceledForSelfTrade,
             Some(filler_key),
-            filler_reward,
+            0,
             false,
-        )?;
-        return Ok((None, None, None, None));
+        );
     }
 
-    Ok((
-        Some(maker),
-        Some(maker_stats),
-        Some(maker_key),
-        Some(maker_order_index),
-    ))
+    let order = &mut user.orders[order_index];
+    order.base_asset_amount = order
+        .base_asset_amount
+        .checked_sub(decrement)
+        .ok_or_else(math_error!())?;
+
+    if order.is_iceberg() {
+        order.iceberg_visible_amount = min(
+            order.iceberg_visible_amount,
+            order.get_base_asset_amount_unfilled()?,
+        );
+    }
+
+    let direction = order.direction;
+    let position_index =
+        get_position_index(&user.positions, user.orders[order_index].market_index)?;
+    decrease_open_bids_and_asks(&mut user.positions[position_index], &direction, decrement)?;
+
+    Ok(())
 }
 
 fn fulfill_order(
@@ -742,10 +2074,8 @@ fn fulfill_order(
     user_order_index: usize,
     user_key: &Pubkey,
     user_stats: &mut UserStats,
-    maker: &mut Option<&mut User>,
-    maker_stats: &mut Option<&mut UserStats>,
-    maker_order_index: Option<usize>,
-    maker_key: Option<&Pubkey>,
+    makers: &mut [(&mut User, usize, &Pubkey, usize)],
+    makers_stats: &mut [&mut UserStats],
     filler: &mut Option<&mut User>,
     filler_key: &Pubkey,
     filler_stats: &mut Option<&mut UserStats>,
@@ -765,17 +2095,15 @@ fn fulfill_order(
         user.positions[position_index].worst_case_base_asset_amount()?;
 
     let user_checkpoint = checkpoint_user(user, user_stats, market_index, Some(user_order_index))?;
-    let maker_checkpoint = if let Some(maker) = maker {
-        let maker_order_index = maker_order_index.ok_or(ErrorCode::MakerOrderNotFound)?;
-        Some(checkpoint_user(
+    let mut maker_checkpoints = Vec::with_capacity(makers.len());
+    for (maker, maker_stats_index, _, maker_order_index) in makers.iter_mut() {
+        maker_checkpoints.push(checkpoint_user(
             maker,
-            maker_stats.as_mut().unwrap(),
+            makers_stats[*maker_stats_index],
             market_index,
-            Some(maker_order_index),
-        )?)
-    } else {
-        None
-    };
+            Some(*maker_order_index),
+        )?);
+    }
     let filler_checkpoint = if let Some(filler) = filler {
         Some(checkpoint_user(
             filler,
@@ -789,13 +2117,50 @@ fn fulfill_order(
 
     let market_checkpoint = clone(market_map.get_ref(&market_index)?.deref());
 
-    let fulfillment_methods =
-        determine_fulfillment_methods(&user.orders[user_order_index], maker.is_some(), slot)?;
+    let fulfillment_methods = {
+        let market = market_map.get_ref(&market_index)?;
+
+        let mut maker_prices = Vec::with_capacity(makers.len());
+        if !makers.is_empty() {
+            let oracle_price = oracle_map.get_price_data(&market.amm.oracle)?.price;
+            for (maker_index, (maker, _, _, maker_order_index)) in makers.iter().enumerate() {
+                let maker_price = maker.orders[*maker_order_index].get_limit_price(
+                    &market.amm,
+                    Some(oracle_price),
+                    slot,
+                )?;
+                maker_prices.push((maker_index, maker_price));
+            }
+        }
+
+        let mark_price = market.amm.mark_price()?;
+        let amm_price = match user.orders[user_order_index].direction {
+            PositionDirection::Long => market.amm.ask_price(mark_price)?,
+            PositionDirection::Short => market.amm.bid_price(mark_price)?,
+        };
+        let auction_price =
+            user.orders[user_order_index].get_limit_price(&market.amm, valid_oracle_price, slot)?;
+
+        determine_fulfillment_methods(
+            &user.orders[user_order_index],
+            maker_prices,
+            amm_price,
+            auction_price,
+            market.amm.amm_jit_intensity > 0,
+            slot,
+        )?
+    };
+
+    let (fill_or_kill,) = get_struct_values!(user.orders[user_order_index], fill_or_kill);
 
-    if fulfillment_methods.is_empty() {
+    // fill or kill orders that can't fill at all fall through to be canceled below
+    if fulfillment_methods.is_empty() && !fill_or_kill {
         return Ok((0, false, false));
     }
 
+    let base_asset_amount_unfilled_before =
+        user.orders[user_order_index].get_base_asset_amount_unfilled()?;
+
     let mut base_asset_amount = 0_u128;
     let mut potentially_risk_increasing = false;
     let mut order_records: Vec<OrderRecord> = vec![];
@@ -807,7 +2172,7 @@ fn fulfill_order(
         let mut market = market_map.get_ref_mut(&market_index)?;
 
         let (_base_asset_amount, _potentially_risk_increasing) = match fulfillment_method {
-            FulfillmentMethod::AMM => fulfill_order_with_amm(
+            FulfillmentMethod::AMM(override_limit_price) => fulfill_order_with_amm(
                 user,
                 user_stats,
                 user_order_index,
@@ -817,6 +2182,8 @@ fn fulfill_order(
                 now,
                 slot,
                 valid_oracle_price,
+                *override_limit_price,
+                false,
                 user_key,
                 filler_key,
                 filler,
@@ -824,25 +2191,48 @@ fn fulfill_order(
                 fee_structure,
                 &mut order_records,
             )?,
-            FulfillmentMethod::Match => fulfill_order_with_match(
-                market.deref_mut(),
+            FulfillmentMethod::AMMJit => fulfill_order_with_amm(
                 user,
                 user_stats,
                 user_order_index,
-                user_key,
-                maker.as_deref_mut().unwrap(),
-                maker_stats.as_deref_mut().unwrap(),
-                maker_order_index.unwrap(),
-                maker_key.unwrap(),
-                filler.as_deref_mut(),
-                filler_stats.as_deref_mut(),
-                filler_key,
+                market.deref_mut(),
+                oracle_map,
+                mark_price_before,
                 now,
                 slot,
+                valid_oracle_price,
+                None,
+                true,
+                user_key,
+                filler_key,
+                filler,
+                filler_stats,
                 fee_structure,
-                oracle_map,
                 &mut order_records,
             )?,
+            FulfillmentMethod::Match(maker_index) => {
+                let (maker, maker_stats_index, maker_key, maker_order_index) =
+                    &mut makers[*maker_index];
+                fulfill_order_with_match(
+                    market.deref_mut(),
+                    user,
+                    user_stats,
+                    user_order_index,
+                    user_key,
+                    maker,
+                    makers_stats[*maker_stats_index],
+                    *maker_order_index,
+                    maker_key,
+                    filler.as_deref_mut(),
+                    filler_stats.as_deref_mut(),
+                    filler_key,
+                    now,
+                    slot,
+                    fee_structure,
+                    oracle_map,
+                    &mut order_records,
+                )?
+            }
         };
 
         potentially_risk_increasing = potentially_risk_increasing || _potentially_risk_increasing;
@@ -863,7 +2253,11 @@ fn fulfill_order(
     let meets_initial_margin_requirement =
         meets_initial_margin_requirement(user, market_map, bank_map, oracle_map)?;
 
-    if meets_initial_margin_requirement || risk_decreasing {
+    // fill or kill orders revert every leg unless the entire order executes
+    let fill_or_kill_not_filled =
+        fill_or_kill && base_asset_amount < base_asset_amount_unfilled_before;
+
+    if !fill_or_kill_not_filled && (meets_initial_margin_requirement || risk_decreasing) {
         for order_record in order_records {
             emit!(order_record)
         }
@@ -871,12 +2265,11 @@ fn fulfill_order(
         updated_user_state = true;
 
         revert_to_checkpoint(user, user_stats, user_checkpoint)?;
-        if let Some(maker) = maker {
-            revert_to_checkpoint(
-                maker,
-                maker_stats.as_mut().unwrap(),
-                maker_checkpoint.unwrap(),
-            )?;
+        // makers sharing user stats were checkpointed with the same pre fill stats
+        for ((maker, maker_stats_index, _, _), maker_checkpoint) in
+            makers.iter_mut().zip(maker_checkpoints.into_iter())
+        {
+            revert_to_checkpoint(maker, makers_stats[*maker_stats_index], maker_checkpoint)?;
         }
         if let Some(filler) = filler {
             revert_to_checkpoint(
@@ -911,7 +2304,11 @@ fn fulfill_order(
             oracle_map,
             now,
             slot,
-            OrderActionExplanation::BreachedMarginRequirement,
+            if fill_or_kill_not_filled {
+                OrderActionExplanation::FillOrKillNotFilled
+            } else {
+                OrderActionExplanation::BreachedMarginRequirement
+            },
             Some(filler_key),
             filler_reward,
             false,
@@ -1007,6 +2404,8 @@ pub fn fulfill_order_with_amm(
     now: i64,
     slot: u64,
     valid_oracle_price: Option<i128>,
+    override_limit_price: Option<u128>,
+    amm_jit: bool,
     user_key: &Pubkey,
     filler_key: &Pubkey,
     filler: &mut Option<&mut User>,
@@ -1015,12 +2414,22 @@ pub fn fulfill_order_with_amm(
     order_records: &mut Vec<OrderRecord>,
 ) -> ClearingHouseResult<(u128, bool)> {
     // Determine the base asset amount the market can fill
-    let base_asset_amount = calculate_base_asset_amount_for_amm_to_fulfill(
-        &user.orders[order_index],
-        market,
-        valid_oracle_price,
-        slot,
-    )?;
+    let base_asset_amount = if amm_jit {
+        calculate_base_asset_amount_for_amm_jit(
+            &user.orders[order_index],
+            market,
+            valid_oracle_price,
+            slot,
+        )?
+    } else {
+        calculate_base_asset_amount_for_amm_to_fulfill(
+            &user.orders[order_index],
+            market,
+            valid_oracle_price,
+            slot,
+            override_limit_price,
+        )?
+    };
 
     if base_asset_amount == 0 {
         msg!("Amm cant fulfill order");
@@ -1031,7 +2440,8 @@ pub fn fulfill_order_with_amm(
 
     let position_index = get_position_index(&user.positions, market.market_index)?;
 
-    let maker_limit_price = if order_post_only {
+    // jit fills happen at the auction price, the amm keeps the difference to its own price
+    let maker_limit_price = if order_post_only || amm_jit {
         Some(user.orders[order_index].get_limit_price(&market.amm, valid_oracle_price, slot)?)
     } else {
         None
@@ -1171,7 +2581,11 @@ pub fn fulfill_order_with_amm(
         taker_pnl,
         maker_pnl,
         action: OrderAction::Fill,
-        action_explanation: OrderActionExplanation::OrderFilledWithAMM,
+        action_explanation: if amm_jit {
+            OrderActionExplanation::OrderFilledWithAMMJit
+        } else {
+            OrderActionExplanation::OrderFilledWithAMM
+        },
         filler: *filler_key,
         fill_record_id,
         market_index: market.market_index,
@@ -1224,13 +2638,13 @@ pub fn fulfill_order_with_match(
     let taker_price =
         taker.orders[taker_order_index].get_limit_price(&market.amm, Some(oracle_price), slot)?;
     let taker_base_asset_amount =
-        taker.orders[taker_order_index].get_base_asset_amount_unfilled()?;
+        taker.orders[taker_order_index].get_visible_base_asset_amount_unfilled()?;
 
     let maker_price =
         maker.orders[maker_order_index].get_limit_price(&market.amm, Some(oracle_price), slot)?;
     let maker_direction = &maker.orders[maker_order_index].direction;
     let maker_base_asset_amount =
-        maker.orders[maker_order_index].get_base_asset_amount_unfilled()?;
+        maker.orders[maker_order_index].get_visible_base_asset_amount_unfilled()?;
 
     let orders_cross = do_orders_cross(maker_direction, maker_price, taker_price);
 
@@ -1425,6 +2839,9 @@ pub fn fulfill_order_with_match(
         maker.orders[maker_order_index] = Order::default();
         let market_position = &mut maker.positions[maker_position_index];
         market_position.open_orders -= 1;
+    } else {
+        // the maker is resting so its next tranche can be shown right away
+        update_iceberg_visible_amount(&mut maker.orders[maker_order_index])?;
     }
 
     Ok((base_asset_amount, false))
@@ -1459,6 +2876,12 @@ pub fn update_order_after_fill(
 
     order.fee = order.fee.checked_add(fee).ok_or_else(math_error!())?;
 
+    if order.is_iceberg() {
+        order.iceberg_visible_amount = order
+            .iceberg_visible_amount
+            .saturating_sub(base_asset_amount);
+    }
+
     if order.get_base_asset_amount_unfilled()? == 0 {
         order.status = OrderStatus::Filled;
     }
@@ -1466,6 +2889,110 @@ pub fn update_order_after_fill(
     Ok(())
 }
 
+/// Re-arms the next iceberg tranche from the hidden remainder. Only called once the order is done
+/// filling for the instruction so a single fill can never take more than the display amount
+pub fn update_iceberg_visible_amount(order: &mut Order) -> ClearingHouseResult {
+    if order.is_iceberg() && order.iceberg_visible_amount == 0 {
+        order.iceberg_visible_amount = min(
+            order.iceberg_display_amount,
+            order.get_base_asset_amount_unfilled()?,
+        );
+    }
+
+    Ok(())
+}
+
+/// Starts a new auction for a twap order once its next slice is released
+pub fn update_twap_order_slice(
+    order: &mut Order,
+    market: &Market,
+    slot: u64,
+) -> ClearingHouseResult {
+    if calculate_twap_slices_released(order, slot)?
+        <= calculate_twap_slices_released(order, order.slot)?
+    {
+        return Ok(());
+    }
+
+    order.slot = slot;
+
+    let slice_base_asset_amount = order.get_visible_base_asset_amount_unfilled()?;
+    if slice_base_asset_amount == 0 {
+        return Ok(());
+    }
+
+    let auction_start_price = calculate_auction_start_price(market, order.direction)?;
+    let auction_end_price = if order.price == 0 {
+        calculate_auction_end_price(market, order.direction, slice_base_asset_amount)?
+    } else {
+        order.price
+    };
+
+    // amm may have moved through the limit price since the last slice
+    order.auction_start_price = match order.direction {
+        PositionDirection::Long => min(auction_start_price, auction_end_price),
+        PositionDirection::Short => max(auction_start_price, auction_end_price),
+    };
+    order.auction_end_price = auction_end_price;
+    order.auction_start_oracle_price = if market.amm.last_oracle_valid {
+        market.amm.last_oracle_price
+    } else {
+        0
+    };
+
+    Ok(())
+}
+
+/// Resizes a quote sized order's unfilled base asset amount at the current amm price
+/// Resizes the order to the base the rest of its quote buys. Returns false once the remaining quote
+/// buys less than a step, at which point the order is done and should be canceled.
+pub fn update_quote_sized_order(
+    user: &mut User,
+    order_index: usize,
+    market: &Market,
+    valid_oracle_price: Option<i128>,
+    slot: u64,
+) -> ClearingHouseResult<bool> {
+    let order = &user.orders[order_index];
+    let quote_asset_amount_unfilled = order
+        .quote_asset_amount
+        .saturating_sub(order.quote_asset_amount_filled);
+
+    let base_asset_amount_unfilled = if order.order_type == OrderType::Limit {
+        calculate_base_asset_amount_for_quote_asset_amount_at_price(
+            quote_asset_amount_unfilled,
+            order.get_limit_price(&market.amm, valid_oracle_price, slot)?,
+            market.amm.base_asset_amount_step_size,
+        )?
+    } else {
+        calculate_base_asset_amount_for_quote_asset_amount(
+            quote_asset_amount_unfilled,
+            order.direction,
+            &market.amm,
+        )?
+    };
+
+    if base_asset_amount_unfilled == 0 {
+        return Ok(false);
+    }
+
+    let old_base_asset_amount_unfilled = order.get_base_asset_amount_unfilled()?;
+    let direction = order.direction;
+
+    let position_index = get_position_index(&user.positions, market.market_index)?;
+    let market_position = &mut user.positions[position_index];
+    decrease_open_bids_and_asks(market_position, &direction, old_base_asset_amount_unfilled)?;
+    increase_open_bids_and_asks(market_position, &direction, base_asset_amount_unfilled)?;
+
+    let order = &mut user.orders[order_index];
+    order.base_asset_amount = order
+        .base_asset_amount_filled
+        .checked_add(base_asset_amount_unfilled)
+        .ok_or_else(math_error!())?;
+
+    Ok(true)
+}
+
 fn get_valid_oracle_price(
     oracle_price_data: &OraclePriceData,
     market: &Market,
@@ -1558,7 +3085,7 @@ pub fn trigger_order(
         "Order is not triggerable"
     )?;
 
-    let market = &mut market_map.get_ref_mut(&market_index)?;
+    let mut market = market_map.get_ref_mut(&market_index)?;
     let oracle_price_data = &oracle_map.get_price_data(&market.amm.oracle)?;
 
     let is_oracle_valid = amm::is_oracle_valid(
@@ -1577,8 +3104,83 @@ pub fn trigger_order(
         "Auction duration must elapse before triggering"
     )?;
 
-    let can_trigger =
-        order_satisfies_trigger_condition(&user.orders[order_index], oracle_price.unsigned_abs());
+    let trigger_oracle_price = if user.orders[order_index].has_cross_market_trigger() {
+        let trigger_oracle_price_data =
+            oracle_map.get_price_data(&user.orders[order_index].trigger_oracle)?;
+        validate!(
+            is_trigger_oracle_valid(
+                trigger_oracle_price_data,
+                &state.oracle_guard_rails.validity
+            )?,
+            ErrorCode::InvalidOracle,
+            "Trigger oracle invalid"
+        )?;
+        trigger_oracle_price_data.price
+    } else {
+        oracle_price
+    };
+
+    let can_trigger = order_satisfies_trigger_condition(
+        &user.orders[order_index],
+        trigger_oracle_price.unsigned_abs(),
+    );
+
+    let is_filler_taker = user_key == filler_key;
+    let mut filler = if !is_filler_taker {
+        Some(load_mut!(filler)?)
+    } else {
+        None
+    };
+
+    if !can_trigger && user.orders[order_index].order_type == OrderType::TrailingStop {
+        let trigger_price = user.orders[order_index].trigger_price;
+        let new_trigger_price = calculate_trailing_stop_trigger_price(
+            &user.orders[order_index],
+            oracle_price.unsigned_abs(),
+        )?;
+
+        if new_trigger_price != trigger_price {
+            msg!(
+                "Trailing stop trigger price updated from {} to {}",
+                trigger_price,
+                new_trigger_price
+            );
+            user.orders[order_index].trigger_price = new_trigger_price;
+
+            let filler_reward = pay_keeper_flat_reward(
+                user,
+                filler.as_deref_mut(),
+                &mut market,
+                state.fee_structure.cancel_order_fee,
+            )?;
+
+            emit!(OrderRecord {
+                ts: now,
+                slot,
+                taker: user_key,
+                taker_order: user.orders[order_index],
+                maker: Pubkey::default(),
+                maker_order: Order::default(),
+                taker_pnl: -cast(filler_reward)?,
+                maker_pnl: 0,
+                action: OrderAction::Modify,
+                action_explanation: OrderActionExplanation::None,
+                filler: filler_key,
+                fill_record_id: 0,
+                market_index,
+                base_asset_amount_filled: 0,
+                quote_asset_amount_filled: 0,
+                filler_reward,
+                taker_fee: 0,
+                maker_rebate: 0,
+                quote_asset_amount_surplus: 0,
+                oracle_price,
+            });
+
+            return Ok(());
+        }
+    }
+
     validate!(can_trigger, ErrorCode::OrderDidNotSatisfyTriggerCondition)?;
 
     {
@@ -1588,31 +3190,29 @@ pub fn trigger_order(
         user.orders[order_index].triggered = true;
         user.orders[order_index].slot = slot;
         let order_type = user.orders[order_index].order_type;
-        if let OrderType::TriggerMarket = order_type {
-            let auction_start_price = calculate_auction_start_price(market, direction)?;
+        if matches!(
+            order_type,
+            OrderType::TriggerMarket | OrderType::TrailingStop
+        ) {
+            let auction_start_price = calculate_auction_start_price(&market, direction)?;
             let auction_end_price =
-                calculate_auction_end_price(market, direction, base_asset_amount)?;
+                calculate_auction_end_price(&market, direction, base_asset_amount)?;
             user.orders[order_index].auction_start_price = auction_start_price;
             user.orders[order_index].auction_end_price = auction_end_price;
+            user.orders[order_index].auction_start_oracle_price = oracle_price;
         }
 
         let user_position = user.get_position_mut(market_index)?;
         increase_open_bids_and_asks(user_position, &direction, base_asset_amount)?;
     }
 
-    let is_filler_taker = user_key == filler_key;
-    let mut filler = if !is_filler_taker {
-        Some(load_mut!(filler)?)
-    } else {
-        None
-    };
-
     let filler_reward = pay_keeper_flat_reward(
         user,
         filler.as_deref_mut(),
-        market,
+        &mut market,
         state.fee_structure.cancel_order_fee,
     )?;
+    drop(market);
 
     emit!(OrderRecord {
         ts: now,
@@ -1640,6 +3240,82 @@ pub fn trigger_order(
     Ok(())
 }
 
+pub fn expire_order(
+    order_id: u64,
+    state: &State,
+    user: &AccountLoader<User>,
+    market_map: &MarketMap,
+    oracle_map: &mut OracleMap,
+    filler: &AccountLoader<User>,
+    clock: &Clock,
+) -> ClearingHouseResult {
+    let now = clock.unix_timestamp;
+    let slot = clock.slot;
+
+    let filler_key = filler.key();
+    let user_key = user.key();
+    let user = &mut load_mut!(user)?;
+
+    let order_index = user
+        .orders
+        .iter()
+        .position(|order| order.order_id == order_id)
+        .ok_or_else(print_error!(ErrorCode::OrderDoesNotExist))?;
+
+    let (order_status, market_index) =
+        get_struct_values!(user.orders[order_index], status, market_index);
+
+    validate!(
+        order_status == OrderStatus::Open,
+        ErrorCode::OrderNotOpen,
+        "Order not open"
+    )?;
+
+    let explanation = if user.orders[order_index].is_past_max_ts(now) {
+        OrderActionExplanation::OrderExpired
+    } else if should_expire_order(user, order_index, slot, state.max_auction_duration)? {
+        OrderActionExplanation::MarketOrderAuctionExpired
+    } else {
+        msg!(
+            "Order max_ts ({}) has not passed (now: {})",
+            user.orders[order_index].max_ts,
+            now
+        );
+        return Err(ErrorCode::OrderNotExpired);
+    };
+
+    let is_filler_taker = user_key == filler_key;
+    let mut filler = if !is_filler_taker {
+        Some(load_mut!(filler)?)
+    } else {
+        None
+    };
+
+    let filler_reward = {
+        let mut market = market_map.get_ref_mut(&market_index)?;
+        pay_keeper_flat_reward(
+            user,
+            filler.as_deref_mut(),
+            market.deref_mut(),
+            state.fee_structure.cancel_order_fee,
+        )?
+    };
+
+    cancel_order(
+        order_index,
+        user,
+        &user_key,
+        market_map,
+        oracle_map,
+        now,
+        slot,
+        explanation,
+        Some(&filler_key),
+        filler_reward,
+        false,
+    )
+}
+
 pub fn pay_keeper_flat_reward(
     user: &mut User,
     filler: Option<&mut User>,
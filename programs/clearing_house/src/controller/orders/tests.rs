@@ -1581,10 +1581,8 @@ pub mod fulfill_order {
             0,
             &taker_key,
             &mut taker_stats,
-            &mut Some(&mut maker),
-            &mut Some(&mut maker_stats),
-            Some(0),
-            Some(&maker_key),
+            &mut [(&mut maker, 0, &maker_key, 0)],
+            &mut [&mut maker_stats],
             &mut Some(&mut filler),
             &filler_key,
             &mut Some(&mut filler_stats),
@@ -1735,10 +1733,8 @@ pub mod fulfill_order {
             0,
             &taker_key,
             &mut taker_stats,
-            &mut Some(&mut maker),
-            &mut Some(&mut maker_stats),
-            Some(0),
-            Some(&maker_key),
+            &mut [(&mut maker, 0, &maker_key, 0)],
+            &mut [&mut maker_stats],
             &mut None,
             &filler_key,
             &mut None,
@@ -1883,10 +1879,8 @@ pub mod fulfill_order {
             0,
             &taker_key,
             &mut taker_stats,
-            &mut None,
-            &mut None,
-            None,
-            None,
+            &mut [],
+            &mut [],
             &mut None,
             &filler_key,
             &mut None,
@@ -2051,10 +2045,8 @@ pub mod fulfill_order {
             0,
             &taker_key,
             &mut taker_stats,
-            &mut Some(&mut maker),
-            &mut Some(&mut maker_stats),
-            Some(0),
-            Some(&maker_key),
+            &mut [(&mut maker, 0, &maker_key, 0)],
+            &mut [&mut maker_stats],
             &mut Some(&mut filler),
             &filler_key,
             &mut Some(&mut filler_stats),
@@ -2253,10 +2245,8 @@ pub mod fulfill_order {
             0,
             &taker_key,
             &mut taker_stats,
-            &mut Some(&mut maker),
-            &mut Some(&mut maker_stats),
-            Some(1),
-            Some(&maker_key),
+            &mut [(&mut maker, 0, &maker_key, 1)],
+            &mut [&mut maker_stats],
             &mut None,
             &filler_key,
             &mut None,
@@ -2467,9 +2457,7 @@ pub mod fill_order {
             &mut oracle_map,
             &filler_account_loader,
             &filler_stats_account_loader,
-            None,
-            None,
-            None,
+            &[],
             &clock,
         )
         .unwrap();
@@ -2593,9 +2581,7 @@ pub mod fill_order {
             &mut oracle_map,
             &filler_account_loader,
             &filler_stats_account_loader,
-            None,
-            None,
-            None,
+            &[],
             &clock,
         )
         .unwrap();
@@ -2611,3 +2597,139 @@ pub mod fill_order {
         assert_eq!(filler_after.positions[0].quote_asset_amount, 10000);
     }
 }
+
+pub mod trigger_order {
+    use super::*;
+    use crate::controller::orders::trigger_order;
+    use crate::controller::position::PositionDirection;
+    use crate::create_account_info;
+    use crate::create_anchor_account_info;
+    use crate::math::constants::{
+        AMM_RESERVE_PRECISION, BASE_PRECISION, BASE_PRECISION_I128, MARK_PRICE_PRECISION,
+        MARK_PRICE_PRECISION_I128, PEG_PRECISION,
+    };
+    use crate::state::market::{Market, AMM};
+    use crate::state::market_map::MarketMap;
+    use crate::state::state::State;
+    use crate::state::user::{OrderStatus, OrderTriggerCondition, OrderType, User};
+    use crate::tests::utils::create_account_info;
+    use crate::tests::utils::*;
+    use anchor_lang::prelude::{AccountLoader, Clock};
+    use std::str::FromStr;
+
+    #[test]
+    fn trigger_keeps_oco_order() {
+        let clock = Clock {
+            slot: 6,
+            epoch_start_timestamp: 0,
+            epoch: 0,
+            leader_schedule_epoch: 0,
+            unix_timestamp: 0,
+        };
+
+        let mut oracle_price = get_pyth_price(85, 10);
+        let oracle_price_key =
+            Pubkey::from_str("J83w4HKfqxwcq3BEMMkPFSppX3gqekLyLJBexebFVkix").unwrap();
+        let pyth_program = crate::ids::pyth_program::id();
+        create_account_info!(
+            oracle_price,
+            &oracle_price_key,
+            &pyth_program,
+            oracle_account_info
+        );
+        let mut oracle_map = OracleMap::load_one(&oracle_account_info, clock.slot).unwrap();
+
+        let mut market = Market {
+            amm: AMM {
+                base_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                quote_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                bid_base_asset_reserve: 101 * AMM_RESERVE_PRECISION,
+                bid_quote_asset_reserve: 99 * AMM_RESERVE_PRECISION,
+                ask_base_asset_reserve: 99 * AMM_RESERVE_PRECISION,
+                ask_quote_asset_reserve: 101 * AMM_RESERVE_PRECISION,
+                sqrt_k: 100 * AMM_RESERVE_PRECISION,
+                peg_multiplier: 100 * PEG_PRECISION,
+                max_slippage_ratio: 100,
+                max_base_asset_amount_ratio: 100,
+                base_asset_amount_step_size: 10000000,
+                last_oracle_price_twap: 85 * MARK_PRICE_PRECISION_I128,
+                oracle: oracle_price_key,
+                ..AMM::default()
+            },
+            margin_ratio_initial: 1000,
+            margin_ratio_maintenance: 500,
+            initialized: true,
+            ..Market::default()
+        };
+        create_anchor_account_info!(market, Market, market_account_info);
+        let market_map = MarketMap::load_one(&market_account_info, true).unwrap();
+
+        let mut orders = [Order::default(); 32];
+        orders[0] = Order {
+            market_index: 0,
+            order_id: 1,
+            status: OrderStatus::Open,
+            order_type: OrderType::TriggerLimit,
+            direction: PositionDirection::Short,
+            base_asset_amount: BASE_PRECISION,
+            price: 110 * MARK_PRICE_PRECISION,
+            trigger_price: 110 * MARK_PRICE_PRECISION,
+            trigger_condition: OrderTriggerCondition::Above,
+            reduce_only: true,
+            oco_order_id: 2,
+            ..Order::default()
+        };
+        orders[1] = Order {
+            market_index: 0,
+            order_id: 2,
+            status: OrderStatus::Open,
+            order_type: OrderType::TriggerMarket,
+            direction: PositionDirection::Short,
+            base_asset_amount: BASE_PRECISION,
+            trigger_price: 90 * MARK_PRICE_PRECISION,
+            trigger_condition: OrderTriggerCondition::Below,
+            reduce_only: true,
+            oco_order_id: 1,
+            ..Order::default()
+        };
+
+        let mut user = User {
+            orders,
+            positions: get_positions(MarketPosition {
+                market_index: 0,
+                base_asset_amount: BASE_PRECISION_I128,
+                open_orders: 2,
+                ..MarketPosition::default()
+            }),
+            ..User::default()
+        };
+        create_anchor_account_info!(user, User, user_account_info);
+        let user_account_loader: AccountLoader<User> =
+            AccountLoader::try_from(&user_account_info).unwrap();
+
+        let filler_key = Pubkey::from_str("My11111111111111111111111111111111111111111").unwrap();
+        create_anchor_account_info!(User::default(), &filler_key, User, filler_account_info);
+        let filler_account_loader: AccountLoader<User> =
+            AccountLoader::try_from(&filler_account_info).unwrap();
+
+        let state = State::default();
+
+        trigger_order(
+            2,
+            &state,
+            &user_account_loader,
+            &market_map,
+            &mut oracle_map,
+            &filler_account_loader,
+            &clock,
+        )
+        .unwrap();
+
+        let user_after = user_account_loader.load().unwrap();
+        assert_eq!(user_after.orders[0].status, OrderStatus::Open); // take profit kept until fill
+        assert_eq!(user_after.orders[0].oco_order_id, 2);
+        assert!(user_after.orders[1].triggered);
+        assert_eq!(user_after.positions[0].open_orders, 2);
+        assert_eq!(user_after.positions[0].open_asks, -BASE_PRECISION_I128);
+    }
+}
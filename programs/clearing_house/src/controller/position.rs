@@ -8,6 +8,7 @@ use crate::error::{ClearingHouseResult, ErrorCode};
 use crate::math::casting::{cast, cast_to_i128};
 use crate::math::constants::{AMM_RESERVE_PRECISION, AMM_RESERVE_PRECISION_I128};
 use crate::math::lp::get_proportion_i128;
+use crate::math::margin::meets_initial_margin_requirement;
 use crate::math::orders::{
     calculate_quote_asset_amount_for_maker_order, get_position_delta_for_fill,
 };
@@ -15,7 +16,11 @@ use crate::math::position::{
     calculate_position_new_quote_base_pnl, get_position_update_type, PositionUpdateType,
 };
 use crate::math_error;
+use crate::state::bank_map::BankMap;
+use crate::state::events::TransferPositionRecord;
 use crate::state::market::Market;
+use crate::state::market_map::MarketMap;
+use crate::state::oracle_map::OracleMap;
 use crate::state::user::{User, UserPositions};
 use crate::validate;
 use crate::MarketPosition;
@@ -641,14 +646,120 @@ pub fn decrease_open_bids_and_asks(
     Ok(())
 }
 
+/// Moves a user's position in a market to another user, both users must still meet the initial
+/// margin requirement afterwards
+#[allow(clippy::too_many_arguments)]
+pub fn transfer_position(
+    market_index: u64,
+    from_user: &mut User,
+    from_user_key: &Pubkey,
+    to_user: &mut User,
+    to_user_key: &Pubkey,
+    market_map: &MarketMap,
+    bank_map: &BankMap,
+    oracle_map: &mut OracleMap,
+    now: i64,
+) -> ClearingHouseResult {
+    validate!(
+        !to_user.bankrupt && !to_user.being_liquidated,
+        ErrorCode::UserBankrupt,
+        "to_user bankrupt or being liquidated"
+    )?;
+    validate!(
+        !from_user.bankrupt && !from_user.being_liquidated,
+        ErrorCode::UserBankrupt,
+        "from_user bankrupt or being liquidated"
+    )?;
+
+    move_position(market_index, from_user, to_user)?;
+
+    validate!(
+        meets_initial_margin_requirement(from_user, market_map, bank_map, oracle_map)?,
+        ErrorCode::InsufficientCollateral,
+        "From user does not meet initial margin requirement"
+    )?;
+
+    validate!(
+        meets_initial_margin_requirement(to_user, market_map, bank_map, oracle_map)?,
+        ErrorCode::InsufficientCollateral,
+        "To user does not meet initial margin requirement"
+    )?;
+
+    let oracle_price = oracle_map
+        .get_price_data(&market_map.get_ref(&market_index)?.amm.oracle)?
+        .price;
+    let position = to_user.get_position(market_index)?;
+
+    emit!(TransferPositionRecord {
+        ts: now,
+        from_user: *from_user_key,
+        to_user: *to_user_key,
+        market_index,
+        base_asset_amount: position.base_asset_amount,
+        quote_asset_amount: position.quote_asset_amount,
+        quote_entry_amount: position.quote_entry_amount,
+        oracle_price,
+    });
+
+    Ok(())
+}
+
+fn move_position(
+    market_index: u64,
+    from_user: &mut User,
+    to_user: &mut User,
+) -> ClearingHouseResult {
+    let from_position_index = get_position_index(&from_user.positions, market_index)?;
+
+    {
+        let from_position = &from_user.positions[from_position_index];
+        validate!(
+            !from_position.has_open_order() && !from_position.is_lp(),
+            ErrorCode::InvalidPositionTransfer,
+            "position in market {} has open orders or lp shares",
+            market_index
+        )?;
+    }
+
+    validate!(
+        get_position_index(&to_user.positions, market_index).is_err(),
+        ErrorCode::InvalidPositionTransfer,
+        "to user already has a position in market {}",
+        market_index
+    )?;
+
+    let to_position_index = add_new_position(&mut to_user.positions, market_index)?;
+
+    // funding checkpoint moves with the position so unsettled funding is preserved
+    let from_position = &mut from_user.positions[from_position_index];
+    let to_position = &mut to_user.positions[to_position_index];
+    to_position.base_asset_amount = from_position.base_asset_amount;
+    to_position.quote_asset_amount = from_position.quote_asset_amount;
+    to_position.quote_entry_amount = from_position.quote_entry_amount;
+    to_position.last_cumulative_funding_rate = from_position.last_cumulative_funding_rate;
+    to_position.last_cumulative_repeg_rebate = from_position.last_cumulative_repeg_rebate;
+    to_position.last_funding_rate_ts = from_position.last_funding_rate_ts;
+
+    *from_position = MarketPosition {
+        market_index,
+        ..MarketPosition::default()
+    };
+
+    Ok(())
+}
+
 #[cfg(test)]
 mod test {
     use crate::controller::position::{
-        update_position_and_market, update_user_and_market_position, PositionDelta,
+        move_position, update_position_and_market, update_user_and_market_position, PositionDelta,
     };
+    use crate::error::ErrorCode;
     use crate::math::constants::{AMM_RESERVE_PRECISION, AMM_RESERVE_PRECISION_I128};
+    use crate::math::constants::{BASE_PRECISION_I128, QUOTE_PRECISION_I128};
     use crate::state::market::{Market, AMM};
     use crate::state::user::MarketPosition;
+    use crate::state::user::User;
+    use crate::tests::utils::get_positions;
 
     #[test]
     fn full_amm_split() {
@@ -1590,4 +1701,64 @@ mod test {
         assert_eq!(market.amm.quote_entry_amount_long, 0);
         assert_eq!(market.amm.quote_entry_amount_short, 0);
     }
+
+    #[test]
+    fn move_position_moves_base_quote_and_funding_checkpoint() {
+        let position = MarketPosition {
+            market_index: 0,
+            base_asset_amount: BASE_PRECISION_I128,
+            quote_asset_amount: -100 * QUOTE_PRECISION_I128,
+            quote_entry_amount: -100 * QUOTE_PRECISION_I128,
+            last_cumulative_funding_rate: 10,
+            last_funding_rate_ts: 1,
+            ..MarketPosition::default()
+        };
+        let mut from_user = User {
+            positions: get_positions(position),
+            ..User::default()
+        };
+        let mut to_user = User::default();
+
+        move_position(0, &mut from_user, &mut to_user).unwrap();
+
+        assert!(from_user.get_position(0).is_err());
+        assert_eq!(*to_user.get_position(0).unwrap(), position);
+    }
+
+    #[test]
+    fn move_position_rejects_open_orders_and_existing_positions() {
+        let position = MarketPosition {
+            market_index: 0,
+            base_asset_amount: BASE_PRECISION_I128,
+            quote_asset_amount: -100 * QUOTE_PRECISION_I128,
+            quote_entry_amount: -100 * QUOTE_PRECISION_I128,
+            ..MarketPosition::default()
+        };
+
+        let mut from_user = User {
+            positions: get_positions(MarketPosition {
+                open_orders: 1,
+                open_bids: BASE_PRECISION_I128,
+                ..position
+            }),
+            ..User::default()
+        };
+        let mut to_user = User::default();
+        let result = move_position(0, &mut from_user, &mut to_user);
+        assert_eq!(result, Err(ErrorCode::InvalidPositionTransfer));
+
+        let mut from_user = User {
+            positions: get_positions(position),
+            ..User::default()
+        };
+        let mut to_user = User {
+            positions: get_positions(position),
+            ..User::default()
+        };
+        let result = move_position(0, &mut from_user, &mut to_user);
+        assert_eq!(result, Err(ErrorCode::InvalidPositionTransfer));
+
+        let result = move_position(1, &mut from_user, &mut to_user);
+        assert_eq!(result, Err(ErrorCode::UserHasNoPositionInMarket));
+    }
 }
@@ -3,10 +3,12 @@ use crate::math::casting::cast_to_i128;
 
 use crate::controller::amm::update_spreads;
 use crate::error::ErrorCode;
+use crate::get_then_update_id;
 use crate::load_mut;
 use crate::math::amm;
 use crate::math::repeg;
 use crate::math_error;
+use crate::state::events::CurveRecord;
 use crate::state::market::Market;
 use crate::state::market_map::MarketMap;
 use crate::state::oracle::OraclePriceData;
@@ -150,6 +152,13 @@ pub fn _update_amm(
         &state.oracle_guard_rails.validity,
     )?;
 
+    if market.amm.oracle_peg_enabled && is_oracle_valid {
+        let oracle_peg_cost = oracle_peg(market, oracle_price_data, now, clock_slot)?;
+        amm_update_cost = amm_update_cost
+            .checked_add(oracle_peg_cost)
+            .ok_or_else(math_error!())?;
+    }
+
     let mark_price_after = market.amm.mark_price()?;
     amm::update_oracle_price_twap(
         &mut market.amm,
@@ -170,6 +179,62 @@ pub fn _update_amm(
     Ok(amm_update_cost)
 }
 
+/// Re-centers the peg of an oracle pegged market on the oracle, spending at most the
+/// budget accrued since the last peg update
+pub fn oracle_peg(
+    market: &mut Market,
+    oracle_price_data: &OraclePriceData,
+    now: i64,
+    clock_slot: u64,
+) -> ClearingHouseResult<i128> {
+    let slots_elapsed = clock_slot.saturating_sub(market.amm.last_oracle_peg_slot);
+    if slots_elapsed == 0 {
+        return Ok(0);
+    }
+
+    let budget = repeg::calculate_oracle_peg_budget(market, slots_elapsed)?;
+    let (new_peg, cost) =
+        repeg::calculate_oracle_peg_and_cost(market, oracle_price_data.price, budget)?;
+
+    market.amm.last_oracle_peg_slot = clock_slot;
+
+    if new_peg == market.amm.peg_multiplier {
+        return Ok(0);
+    }
+
+    if !apply_cost_to_market(market, cost, true)? {
+        return Ok(0);
+    }
+
+    let peg_multiplier_before = market.amm.peg_multiplier;
+    market.amm.peg_multiplier = new_peg;
+
+    emit!(CurveRecord {
+        ts: now,
+        record_id: get_then_update_id!(market, next_curve_record_id),
+        market_index: market.market_index,
+        peg_multiplier_before,
+        base_asset_reserve_before: market.amm.base_asset_reserve,
+        quote_asset_reserve_before: market.amm.quote_asset_reserve,
+        sqrt_k_before: market.amm.sqrt_k,
+        peg_multiplier_after: new_peg,
+        base_asset_reserve_after: market.amm.base_asset_reserve,
+        quote_asset_reserve_after: market.amm.quote_asset_reserve,
+        sqrt_k_after: market.amm.sqrt_k,
+        base_asset_amount_long: market.base_asset_amount_long.unsigned_abs(),
+        base_asset_amount_short: market.base_asset_amount_short.unsigned_abs(),
+        net_base_asset_amount: market.amm.net_base_asset_amount,
+        open_interest: market.open_interest,
+        total_fee: market.amm.total_fee,
+        total_fee_minus_distributions: market.amm.total_fee_minus_distributions,
+        adjustment_cost: cost,
+        oracle_price: oracle_price_data.price,
+        fill_record: 0,
+    });
+
+    Ok(cost)
+}
+
 pub fn apply_cost_to_market(
     market: &mut Market,
     cost: i128,
@@ -218,7 +283,7 @@ pub fn apply_cost_to_market(
 mod test {
     use super::*;
     use crate::math::constants::{
-        AMM_RESERVE_PRECISION, MARK_PRICE_PRECISION, MARK_PRICE_PRECISION_I128,
+        AMM_RESERVE_PRECISION, MARK_PRICE_PRECISION, MARK_PRICE_PRECISION_I128, QUOTE_PRECISION,
     };
     use crate::state::market::AMM;
     use crate::state::state::{PriceDivergenceGuardRails, ValidityGuardRails};
@@ -394,4 +459,67 @@ mod test {
         .unwrap();
         assert!(!is_oracle_valid);
     }
+
+    #[test]
+    pub fn update_amm_oracle_peg_test() {
+        let mut market = Market {
+            amm: AMM {
+                base_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                quote_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                terminal_quote_asset_reserve: 99 * AMM_RESERVE_PRECISION,
+                sqrt_k: 100 * AMM_RESERVE_PRECISION,
+                peg_multiplier: 100_000,
+                net_base_asset_amount: AMM_RESERVE_PRECISION as i128,
+                last_oracle_price_twap: 100 * MARK_PRICE_PRECISION_I128,
+                base_spread: 250,
+                max_spread: 55500,
+                total_fee_minus_distributions: 10 * QUOTE_PRECISION as i128,
+                oracle_peg_enabled: true,
+                oracle_peg_slot_budget_pct: 10_000, // 1% per slot
+                last_oracle_peg_slot: 95,
+                ..AMM::default()
+            },
+            margin_ratio_initial: 555,
+            ..Market::default()
+        };
+
+        let state = State {
+            oracle_guard_rails: OracleGuardRails {
+                validity: ValidityGuardRails {
+                    slots_before_stale: 10,
+                    confidence_interval_max_size: 20000, //2%
+                    too_volatile_ratio: 5,
+                },
+                ..OracleGuardRails::default()
+            },
+            ..State::default()
+        };
+
+        let oracle_price_data = OraclePriceData {
+            price: 101 * MARK_PRICE_PRECISION_I128,
+            confidence: 0,
+            delay: 1,
+            has_sufficient_number_of_data_points: true,
+        };
+
+        // 5 slots of budget is 5% of the 10 quote fee pool
+        let cost = _update_amm(&mut market, &oracle_price_data, &state, 10000, 100).unwrap();
+        assert_eq!(market.amm.peg_multiplier, 100_499);
+        assert_eq!(cost, 499_000);
+        assert_eq!(
+            market.amm.total_fee_minus_distributions,
+            10 * QUOTE_PRECISION as i128 - 499_000
+        );
+        assert_eq!(market.amm.last_oracle_peg_slot, 100);
+        assert_eq!(market.next_curve_record_id, 1);
+
+        // budget already spent this slot
+        let cost = _update_amm(&mut market, &oracle_price_data, &state, 10000, 100).unwrap();
+        assert_eq!(cost, 0);
+        assert_eq!(market.amm.peg_multiplier, 100_499);
+
+        let cost = _update_amm(&mut market, &oracle_price_data, &state, 10001, 110).unwrap();
+        assert_eq!(market.amm.peg_multiplier, 101_000);
+        assert_eq!(cost, 501_000);
+    }
 }
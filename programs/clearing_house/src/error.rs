@@ -233,6 +233,32 @@ pub enum ErrorCode {
     UnableToBurnLPTokens,
     #[msg("Trying to remove liqudity too fast after adding it")]
     TryingToRemoveLiquidityTooFast,
+    #[msg("Order has not expired")]
+    OrderNotExpired,
+    #[msg("Invalid interest rate model")]
+    InvalidInterestRateModel,
+    #[msg("Insufficient insurance fund shares")]
+    InsufficientInsuranceFundShares,
+    #[msg("No insurance fund unstake request")]
+    NoInsuranceFundUnstakeRequest,
+    #[msg("Trying to remove insurance fund stake too fast after requesting it")]
+    TryingToRemoveInsuranceFundStakeTooFast,
+    #[msg("Invalid position transfer")]
+    InvalidPositionTransfer,
+    #[msg("Orders do not cross")]
+    OrdersDoNotCross,
+    #[msg("Amm net base asset amount breaches max")]
+    MaxNetBaseAssetAmountBreached,
+    #[msg("Invalid rebalance k")]
+    InvalidRebalanceK,
+    #[msg("Insurance fund unstake request expired")]
+    InsuranceFundUnstakeRequestExpired,
+    #[msg("Insurance fund stake too small")]
+    InsuranceFundStakeTooSmall,
+    #[msg("Invalid insurance fund unstaking config")]
+    InvalidInsuranceFundUnstakingConfig,
+    #[msg("Invalid order tick size")]
+    InvalidOrderTickSize,
 }
 
 #[macro_export]
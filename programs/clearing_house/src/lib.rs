@@ -5,8 +5,10 @@ use anchor_lang::prelude::*;
 use borsh::BorshSerialize;
 
 use context::*;
+use controller::position::PositionDirection;
 use error::ErrorCode;
 use math::{amm, bn, constants::*, margin::*};
+use state::bank::InterestRateModel;
 use state::oracle::{get_oracle_price, OracleSource};
 
 use crate::math::amm::get_update_k_result;
@@ -23,6 +25,8 @@ mod margin_validation;
 pub mod math;
 pub mod optional_accounts;
 pub mod order_validation;
+#[cfg(any(test, feature = "sim"))]
+pub mod sim;
 pub mod state;
 #[cfg(test)]
 mod tests;
@@ -43,8 +47,8 @@ pub mod clearing_house {
     use crate::margin_validation::validate_margin;
     use crate::math;
     use crate::math::bank_balance::get_token_amount;
-    use crate::math::casting::{cast, cast_to_i128, cast_to_u128};
-    use crate::optional_accounts::get_maker_and_maker_stats;
+    use crate::math::casting::{cast, cast_to_i128, cast_to_u128, cast_to_u64};
+    use crate::optional_accounts::get_makers_and_maker_stats;
     use crate::state::bank::{Bank, BankBalanceType};
     use crate::state::bank_map::{get_writable_banks, BankMap, WritableBanks};
     use crate::state::events::DepositDirection;
@@ -57,6 +61,7 @@ pub mod clearing_house {
     use crate::state::oracle::OraclePriceData;
     use crate::state::oracle_map::OracleMap;
     use crate::state::state::OrderFillerRewardStructure;
+    use crate::state::user::AuctionPriceCurve;
 
     use super::*;
 
@@ -99,6 +104,11 @@ pub mod clearing_house {
             min_auction_duration: 10,
             max_auction_duration: 60,
             liquidation_margin_buffer_ratio: 50, // 2%
+            insurance_fund_total_shares: 0,
+            insurance_fund_user_shares: 0,
+            insurance_fund_unstaking_period: DEFAULT_INSURANCE_FUND_UNSTAKING_PERIOD,
+            insurance_fund_unstaking_window: DEFAULT_INSURANCE_FUND_UNSTAKING_WINDOW,
+            insurance_fund_shares_epoch: 0,
             padding0: 0,
             padding1: 0,
         };
@@ -268,6 +278,12 @@ pub mod clearing_house {
             imf_factor,
             liquidation_fee,
             withdraw_guard_threshold: 0,
+            interest_rate_model: InterestRateModel::Kinked,
+            second_kink_utilization: 0,
+            second_kink_borrow_rate: 0,
+            fixed_borrow_rate: 0,
+            reserve_factor: 0,
+            revenue_pool: PoolBalance { balance: 0 },
         };
 
         Ok(())
@@ -362,6 +378,7 @@ pub mod clearing_house {
             unsettled_maintenance_asset_weight: 100, // 100%
             unsettled_imf_factor: 0,
             liquidation_fee,
+            auction_price_curve: AuctionPriceCurve::Linear,
             padding0: 0,
             padding1: 0,
             padding2: 0,
@@ -445,6 +462,19 @@ pub mod clearing_house {
                 lp_cooldown_time: 1, // TODO: what should this be?
 
                 last_oracle_valid: false,
+                inventory_spread_scale: 0,
+                volatility_spread_scale: 0,
+                confidence_spread_scale: 0,
+                oracle_peg_enabled: false,
+                oracle_peg_slot_budget_pct: 0,
+                last_oracle_peg_slot: 0,
+                amm_jit_intensity: 0,
+                last_rebalance_k_ts: 0,
+                last_rebalance_k_total_fee: 0,
+                rebalance_k_day_start_ts: 0,
+                rebalance_k_day_start_sqrt_k: 0,
+                max_net_base_asset_amount: 0,
+                order_tick_size: (init_mark_price / DEFAULT_ORDER_TICK_SIZE_DENOMINATOR).max(1),
                 padding0: 0,
                 padding1: 0,
                 padding2: 0,
@@ -743,6 +773,47 @@ pub mod clearing_house {
         Ok(())
     }
 
+    #[access_control(
+        exchange_not_paused(&ctx.accounts.state)
+    )]
+    pub fn transfer_position(ctx: Context<TransferPosition>, market_index: u64) -> Result<()> {
+        let clock = Clock::get()?;
+
+        validate!(
+            ctx.accounts.to_user.key() != ctx.accounts.from_user.key(),
+            ErrorCode::InvalidPositionTransfer,
+            "cant transfer position to same user"
+        )?;
+
+        let to_user_key = ctx.accounts.to_user.key();
+        let from_user_key = ctx.accounts.from_user.key();
+        let to_user = &mut load_mut!(ctx.accounts.to_user)?;
+        let from_user = &mut load_mut!(ctx.accounts.from_user)?;
+
+        let remaining_accounts_iter = &mut ctx.remaining_accounts.iter().peekable();
+        let mut oracle_map = OracleMap::load(remaining_accounts_iter, clock.slot)?;
+        let bank_map = BankMap::load(&WritableBanks::new(), remaining_accounts_iter)?;
+        let market_map = MarketMap::load(
+            &MarketSet::new(),
+            &MarketSet::new(),
+            remaining_accounts_iter,
+        )?;
+
+        controller::position::transfer_position(
+            market_index,
+            from_user,
+            &from_user_key,
+            to_user,
+            &to_user_key,
+            &market_map,
+            &bank_map,
+            &mut oracle_map,
+            clock.unix_timestamp,
+        )?;
+
+        Ok(())
+    }
+
     pub fn update_bank_cumulative_interest(
         ctx: Context<UpdateBankCumulativeInterest>,
     ) -> Result<()> {
@@ -942,6 +1013,11 @@ pub mod clearing_house {
             return Err(print_error!(ErrorCode::InvalidOrder)().into());
         }
 
+        if params.fill_or_kill {
+            msg!("fill_or_kill order must be in place_and_take");
+            return Err(print_error!(ErrorCode::InvalidOrder)().into());
+        }
+
         controller::repeg::update_amm(
             params.market_index,
             &market_map,
@@ -963,6 +1039,189 @@ pub mod clearing_house {
         Ok(())
     }
 
+    pub fn place_oco_orders(
+        ctx: Context<PlaceOrder>,
+        take_profit_params: OrderParams,
+        stop_loss_params: OrderParams,
+    ) -> Result<()> {
+        let remaining_accounts_iter = &mut ctx.remaining_accounts.iter().peekable();
+        let mut oracle_map = OracleMap::load(remaining_accounts_iter, Clock::get()?.slot)?;
+        let bank_map = BankMap::load(&WritableBanks::new(), remaining_accounts_iter)?;
+        let market_map = MarketMap::load(
+            &MarketSet::new(),
+            &get_market_set(take_profit_params.market_index),
+            remaining_accounts_iter,
+        )?;
+
+        if take_profit_params.fill_or_kill || stop_loss_params.fill_or_kill {
+            msg!("fill_or_kill order must be in place_and_take");
+            return Err(print_error!(ErrorCode::InvalidOrder)().into());
+        }
+
+        controller::repeg::update_amm(
+            take_profit_params.market_index,
+            &market_map,
+            &mut oracle_map,
+            &ctx.accounts.state,
+            &Clock::get()?,
+        )?;
+
+        controller::orders::place_oco_orders(
+            &ctx.accounts.state,
+            &ctx.accounts.user,
+            &market_map,
+            &bank_map,
+            &mut oracle_map,
+            &Clock::get()?,
+            take_profit_params,
+            stop_loss_params,
+        )?;
+
+        Ok(())
+    }
+
+    pub fn place_orders(ctx: Context<PlaceOrder>, params: Vec<OrderParams>) -> Result<()> {
+        let mut writable_markets = MarketSet::new();
+        for params in params.iter() {
+            if params.immediate_or_cancel {
+                msg!("immediate_or_cancel order must be in place_and_make or place_and_take");
+                return Err(print_error!(ErrorCode::InvalidOrder)().into());
+            }
+            if params.fill_or_kill {
+                msg!("fill_or_kill order must be in place_and_take");
+                return Err(print_error!(ErrorCode::InvalidOrder)().into());
+            }
+            writable_markets.insert(params.market_index);
+        }
+
+        let remaining_accounts_iter = &mut ctx.remaining_accounts.iter().peekable();
+        let mut oracle_map = OracleMap::load(remaining_accounts_iter, Clock::get()?.slot)?;
+        let bank_map = BankMap::load(&WritableBanks::new(), remaining_accounts_iter)?;
+        let market_map = MarketMap::load(
+            &MarketSet::new(),
+            &writable_markets,
+            remaining_accounts_iter,
+        )?;
+
+        for market_index in writable_markets.iter() {
+            controller::repeg::update_amm(
+                *market_index,
+                &market_map,
+                &mut oracle_map,
+                &ctx.accounts.state,
+                &Clock::get()?,
+            )?;
+        }
+
+        controller::orders::place_orders(
+            &ctx.accounts.state,
+            &ctx.accounts.user,
+            &market_map,
+            &bank_map,
+            &mut oracle_map,
+            &Clock::get()?,
+            params,
+        )?;
+
+        Ok(())
+    }
+
+    pub fn cancel_and_place(
+        ctx: Context<PlaceOrder>,
+        cancel_market_index: Option<u64>,
+        cancel_direction: Option<PositionDirection>,
+        params: Vec<OrderParams>,
+    ) -> Result<()> {
+        let mut writable_markets = MarketSet::new();
+        for params in params.iter() {
+            if params.immediate_or_cancel {
+                msg!("immediate_or_cancel order must be in place_and_make or place_and_take");
+                return Err(print_error!(ErrorCode::InvalidOrder)().into());
+            }
+            if params.fill_or_kill {
+                msg!("fill_or_kill order must be in place_and_take");
+                return Err(print_error!(ErrorCode::InvalidOrder)().into());
+            }
+            writable_markets.insert(params.market_index);
+        }
+
+        let remaining_accounts_iter = &mut ctx.remaining_accounts.iter().peekable();
+        let mut oracle_map = OracleMap::load(remaining_accounts_iter, Clock::get()?.slot)?;
+        let bank_map = BankMap::load(&WritableBanks::new(), remaining_accounts_iter)?;
+        let market_map = MarketMap::load(
+            &MarketSet::new(),
+            &writable_markets,
+            remaining_accounts_iter,
+        )?;
+
+        for market_index in writable_markets.iter() {
+            controller::repeg::update_amm(
+                *market_index,
+                &market_map,
+                &mut oracle_map,
+                &ctx.accounts.state,
+                &Clock::get()?,
+            )?;
+        }
+
+        controller::orders::cancel_and_place(
+            &ctx.accounts.state,
+            &ctx.accounts.user,
+            &market_map,
+            &bank_map,
+            &mut oracle_map,
+            &Clock::get()?,
+            cancel_market_index,
+            cancel_direction,
+            params,
+        )?;
+
+        Ok(())
+    }
+
+    pub fn modify_order(
+        ctx: Context<PlaceOrder>,
+        order_id: u64,
+        params: ModifyOrderParams,
+    ) -> Result<()> {
+        let market_index = {
+            let user = &load!(ctx.accounts.user)?;
+            user.get_order(order_id)
+                .map(|order| order.market_index)
+                .ok_or(ErrorCode::OrderDoesNotExist)?
+        };
+
+        let remaining_accounts_iter = &mut ctx.remaining_accounts.iter().peekable();
+        let mut oracle_map = OracleMap::load(remaining_accounts_iter, Clock::get()?.slot)?;
+        let bank_map = BankMap::load(&WritableBanks::new(), remaining_accounts_iter)?;
+        let market_map = MarketMap::load(
+            &MarketSet::new(),
+            &get_market_set(market_index),
+            remaining_accounts_iter,
+        )?;
+
+        controller::repeg::update_amm(
+            market_index,
+            &market_map,
+            &mut oracle_map,
+            &ctx.accounts.state,
+            &Clock::get()?,
+        )?;
+
+        controller::orders::modify_order(
+            order_id,
+            &ctx.accounts.state,
+            &ctx.accounts.user,
+            &market_map,
+            &bank_map,
+            &mut oracle_map,
+            &Clock::get()?,
+            params,
+        )?;
+
+        Ok(())
+    }
+
     pub fn cancel_order(ctx: Context<CancelOrder>, order_id: Option<u64>) -> Result<()> {
         let remaining_accounts_iter = &mut ctx.remaining_accounts.iter().peekable();
         let mut oracle_map = OracleMap::load(remaining_accounts_iter, Clock::get()?.slot)?;
@@ -1010,13 +1269,39 @@ pub mod clearing_house {
         Ok(())
     }
 
+    pub fn cancel_orders(
+        ctx: Context<CancelAllOrders>,
+        market_index: Option<u64>,
+        direction: Option<PositionDirection>,
+    ) -> Result<()> {
+        let remaining_accounts_iter = &mut ctx.remaining_accounts.iter().peekable();
+        let mut oracle_map = OracleMap::load(remaining_accounts_iter, Clock::get()?.slot)?;
+        let _bank_map = BankMap::load(&MarketSet::new(), remaining_accounts_iter)?;
+        let market_map = MarketMap::load(
+            &MarketSet::new(),
+            &MarketSet::new(),
+            remaining_accounts_iter,
+        )?;
+
+        controller::orders::cancel_orders(
+            &ctx.accounts.user,
+            &market_map,
+            &mut oracle_map,
+            &Clock::get()?,
+            market_index,
+            direction,
+        )?;
+
+        Ok(())
+    }
+
     #[access_control(
         exchange_not_paused(&ctx.accounts.state)
     )]
     pub fn fill_order<'info>(
         ctx: Context<FillOrder>,
         order_id: Option<u64>,
-        maker_order_id: Option<u64>,
+        maker_order_ids: Vec<u64>,
     ) -> Result<()> {
         let (order_id, market_index) = {
             let user = &load!(ctx.accounts.user)?;
@@ -1039,13 +1324,7 @@ pub mod clearing_house {
             remaining_accounts_iter,
         )?;
 
-        let (maker, maker_stats) = match maker_order_id {
-            Some(_) => {
-                let (user, user_stats) = get_maker_and_maker_stats(remaining_accounts_iter)?;
-                (Some(user), Some(user_stats))
-            }
-            None => (None, None),
-        };
+        let makers = get_makers_and_maker_stats(remaining_accounts_iter, &maker_order_ids)?;
 
         let clock = &Clock::get()?;
 
@@ -1067,9 +1346,7 @@ pub mod clearing_house {
             &mut oracle_map,
             &ctx.accounts.filler,
             &ctx.accounts.filler_stats,
-            maker.as_ref(),
-            maker_stats.as_ref(),
-            maker_order_id,
+            &makers,
             &Clock::get()?,
         )?;
 
@@ -1080,13 +1357,56 @@ pub mod clearing_house {
         Ok(())
     }
 
+    #[access_control(
+        exchange_not_paused(&ctx.accounts.state)
+    )]
+    pub fn match_orders<'info>(
+        ctx: Context<MatchOrders>,
+        order_id: u64,
+        counterparty_order_id: u64,
+    ) -> Result<()> {
+        let market_index = {
+            let user = &load!(ctx.accounts.user)?;
+            user.get_order(order_id)
+                .map(|order| order.market_index)
+                .ok_or(ErrorCode::OrderDoesNotExist)?
+        };
+
+        let remaining_accounts_iter = &mut ctx.remaining_accounts.iter().peekable();
+        let mut oracle_map = OracleMap::load(remaining_accounts_iter, Clock::get()?.slot)?;
+        let bank_map = BankMap::load(&WritableBanks::new(), remaining_accounts_iter)?;
+        let market_map = MarketMap::load(
+            &get_market_set(market_index),
+            &MarketSet::new(),
+            remaining_accounts_iter,
+        )?;
+
+        controller::orders::match_orders(
+            order_id,
+            counterparty_order_id,
+            &ctx.accounts.state,
+            &ctx.accounts.user,
+            &ctx.accounts.user_stats,
+            &ctx.accounts.counterparty,
+            &ctx.accounts.counterparty_stats,
+            &bank_map,
+            &market_map,
+            &mut oracle_map,
+            &ctx.accounts.filler,
+            &ctx.accounts.filler_stats,
+            &Clock::get()?,
+        )?;
+
+        Ok(())
+    }
+
     #[access_control(
         exchange_not_paused(&ctx.accounts.state)
     )]
     pub fn place_and_take<'info>(
         ctx: Context<PlaceAndTake>,
         params: OrderParams,
-        maker_order_id: Option<u64>,
+        maker_order_ids: Vec<u64>,
     ) -> Result<()> {
         let remaining_accounts_iter = &mut ctx.remaining_accounts.iter().peekable();
         let mut oracle_map = OracleMap::load(remaining_accounts_iter, Clock::get()?.slot)?;
@@ -1103,13 +1423,7 @@ pub mod clearing_house {
             return Err(print_error!(ErrorCode::InvalidOrder)().into());
         }
 
-        let (maker, maker_stats) = match maker_order_id {
-            Some(_) => {
-                let (user, user_stats) = get_maker_and_maker_stats(remaining_accounts_iter)?;
-                (Some(user), Some(user_stats))
-            }
-            None => (None, None),
-        };
+        let makers = get_makers_and_maker_stats(remaining_accounts_iter, &maker_order_ids)?;
 
         let is_immediate_or_cancel = params.immediate_or_cancel;
         let base_asset_amount_to_fill = params.base_asset_amount;
@@ -1145,9 +1459,7 @@ pub mod clearing_house {
             &mut oracle_map,
             &user.clone(),
             &ctx.accounts.user_stats.clone(),
-            maker.as_ref(),
-            maker_stats.as_ref(),
-            maker_order_id,
+            &makers,
             &Clock::get()?,
         )?;
 
@@ -1181,7 +1493,10 @@ pub mod clearing_house {
             remaining_accounts_iter,
         )?;
 
-        if !params.immediate_or_cancel || !params.post_only || params.order_type != OrderType::Limit
+        if !params.immediate_or_cancel
+            || !params.post_only
+            || params.fill_or_kill
+            || params.order_type != OrderType::Limit
         {
             msg!("place_and_make must use IOC post only limit order");
             return Err(print_error!(ErrorCode::InvalidOrder)().into());
@@ -1217,9 +1532,11 @@ pub mod clearing_house {
             &mut oracle_map,
             &ctx.accounts.user.clone(),
             &ctx.accounts.user_stats.clone(),
-            Some(&ctx.accounts.user),
-            Some(&ctx.accounts.user_stats),
-            Some(order_id),
+            &[(
+                ctx.accounts.user.clone(),
+                ctx.accounts.user_stats.clone(),
+                order_id,
+            )],
             &Clock::get()?,
         )?;
 
@@ -1241,6 +1558,39 @@ pub mod clearing_house {
         Ok(())
     }
 
+    #[access_control(
+        exchange_not_paused(&ctx.accounts.state)
+    )]
+    pub fn expire_order<'info>(ctx: Context<ExpireOrder>, order_id: u64) -> Result<()> {
+        let market_index = {
+            let user = &load!(ctx.accounts.user)?;
+            user.get_order(order_id)
+                .map(|order| order.market_index)
+                .ok_or(ErrorCode::OrderDoesNotExist)?
+        };
+
+        let remaining_accounts_iter = &mut ctx.remaining_accounts.iter().peekable();
+        let mut oracle_map = OracleMap::load(remaining_accounts_iter, Clock::get()?.slot)?;
+        BankMap::load(&WritableBanks::new(), remaining_accounts_iter)?;
+        let market_map = MarketMap::load(
+            &MarketSet::new(),
+            &get_market_set(market_index),
+            remaining_accounts_iter,
+        )?;
+
+        controller::orders::expire_order(
+            order_id,
+            &ctx.accounts.state,
+            &ctx.accounts.user,
+            &market_map,
+            &mut oracle_map,
+            &ctx.accounts.filler,
+            &Clock::get()?,
+        )?;
+
+        Ok(())
+    }
+
     #[access_control(
         exchange_not_paused(&ctx.accounts.state)
     )]
@@ -1585,14 +1935,17 @@ pub mod clearing_house {
 
         let remaining_accounts_iter = &mut ctx.remaining_accounts.iter().peekable();
         let mut oracle_map = OracleMap::load(remaining_accounts_iter, clock.slot)?;
-        let bank_map = BankMap::load(&WritableBanks::new(), remaining_accounts_iter)?;
+        let bank_map = BankMap::load(
+            &get_writable_banks(QUOTE_ASSET_BANK_INDEX),
+            remaining_accounts_iter,
+        )?;
         let market_map = MarketMap::load(
             &get_market_set(market_index),
             &MarketSet::new(),
             remaining_accounts_iter,
         )?;
 
-        controller::liquidation::resolve_perp_bankruptcy(
+        let if_payment = controller::liquidation::resolve_perp_bankruptcy(
             market_index,
             user,
             &user_key,
@@ -1602,8 +1955,20 @@ pub mod clearing_house {
             &bank_map,
             &mut oracle_map,
             now,
+            ctx.accounts.insurance_vault.amount,
         )?;
 
+        if if_payment > 0 {
+            controller::token::send_from_insurance_vault(
+                &ctx.accounts.token_program,
+                &ctx.accounts.insurance_vault,
+                &ctx.accounts.bank_vault,
+                &ctx.accounts.insurance_vault_authority,
+                ctx.accounts.state.insurance_vault_nonce,
+                if_payment,
+            )?;
+        }
+
         Ok(())
     }
 
@@ -1637,7 +2002,7 @@ pub mod clearing_house {
             remaining_accounts_iter,
         )?;
 
-        controller::liquidation::resolve_bank_bankruptcy(
+        let if_payment = controller::liquidation::resolve_bank_bankruptcy(
             bank_index,
             user,
             &user_key,
@@ -1647,7 +2012,20 @@ pub mod clearing_house {
             &bank_map,
             &mut oracle_map,
             now,
-        )?;
+            ctx.accounts.insurance_vault.amount,
+        )?;
+
+        // only quote borrows are covered, and the bank vault account is the quote bank vault
+        if if_payment > 0 {
+            controller::token::send_from_insurance_vault(
+                &ctx.accounts.token_program,
+                &ctx.accounts.insurance_vault,
+                &ctx.accounts.bank_vault,
+                &ctx.accounts.insurance_vault_authority,
+                ctx.accounts.state.insurance_vault_nonce,
+                if_payment,
+            )?;
+        }
 
         Ok(())
     }
@@ -1677,31 +2055,69 @@ pub mod clearing_house {
     ) -> Result<()> {
         let market = &mut load_mut!(ctx.accounts.market)?;
 
-        // A portion of fees must always remain in protocol to be used to keep markets optimal
-        let max_withdraw = market
+        let max_withdraw = amm::calculate_max_fee_withdraw(&market.amm)?;
+
+        let bank = &mut load_mut!(ctx.accounts.bank)?;
+
+        let amm_fee_pool_token_amount =
+            get_token_amount(market.amm.fee_pool.balance, bank, &BankBalanceType::Deposit)?;
+
+        if cast_to_u128(amount)? > max_withdraw {
+            msg!("withdraw size exceeds max_withdraw: {:?}", max_withdraw);
+            return Err(ErrorCode::AdminWithdrawTooLarge.into());
+        }
+
+        if cast_to_u128(amount)? > amm_fee_pool_token_amount {
+            msg!(
+                "withdraw size exceeds amm_fee_pool_token_amount: {:?}",
+                amm_fee_pool_token_amount
+            );
+            return Err(ErrorCode::AdminWithdrawTooLarge.into());
+        }
+
+        controller::token::send_from_bank_vault(
+            &ctx.accounts.token_program,
+            &ctx.accounts.bank_vault,
+            &ctx.accounts.recipient,
+            &ctx.accounts.bank_vault_authority,
+            0,
+            bank.vault_authority_nonce,
+            amount,
+        )?;
+
+        controller::bank_balance::update_bank_balances(
+            cast_to_u128(amount)?,
+            &BankBalanceType::Borrow,
+            bank,
+            &mut market.amm.fee_pool,
+        )?;
+
+        market.amm.total_fee_withdrawn = market
             .amm
-            .total_exchange_fee
-            .checked_mul(SHARE_OF_FEES_ALLOCATED_TO_CLEARING_HOUSE_NUMERATOR)
-            .ok_or_else(math_error!())?
-            .checked_div(SHARE_OF_FEES_ALLOCATED_TO_CLEARING_HOUSE_DENOMINATOR)
-            .ok_or_else(math_error!())?
-            .checked_sub(market.amm.total_fee_withdrawn)
+            .total_fee_withdrawn
+            .checked_add(cast(amount)?)
             .ok_or_else(math_error!())?;
 
+        Ok(())
+    }
+
+    pub fn withdraw_from_bank_to_insurance_vault(
+        ctx: Context<WithdrawFromBankToInsuranceVault>,
+        bank_index: u64,
+        amount: u64,
+    ) -> Result<()> {
         let bank = &mut load_mut!(ctx.accounts.bank)?;
 
-        let amm_fee_pool_token_amount =
-            get_token_amount(market.amm.fee_pool.balance, bank, &BankBalanceType::Deposit)?;
+        let now = Clock::get()?.unix_timestamp;
+        controller::bank_balance::update_bank_cumulative_interest(bank, now)?;
 
-        if cast_to_u128(amount)? > max_withdraw {
-            msg!("withdraw size exceeds max_withdraw: {:?}", max_withdraw);
-            return Err(ErrorCode::AdminWithdrawTooLarge.into());
-        }
+        let revenue_pool_token_amount =
+            get_token_amount(bank.revenue_pool.balance, bank, &BankBalanceType::Deposit)?;
 
-        if cast_to_u128(amount)? > amm_fee_pool_token_amount {
+        if cast_to_u128(amount)? > revenue_pool_token_amount {
             msg!(
-                "withdraw size exceeds amm_fee_pool_token_amount: {:?}",
-                amm_fee_pool_token_amount
+                "withdraw size exceeds revenue_pool_token_amount: {:?}",
+                revenue_pool_token_amount
             );
             return Err(ErrorCode::AdminWithdrawTooLarge.into());
         }
@@ -1709,26 +2125,19 @@ pub mod clearing_house {
         controller::token::send_from_bank_vault(
             &ctx.accounts.token_program,
             &ctx.accounts.bank_vault,
-            &ctx.accounts.recipient,
+            &ctx.accounts.insurance_vault,
             &ctx.accounts.bank_vault_authority,
-            0,
+            bank_index,
             bank.vault_authority_nonce,
             amount,
         )?;
 
-        controller::bank_balance::update_bank_balances(
+        controller::bank_balance::update_revenue_pool_balances(
             cast_to_u128(amount)?,
             &BankBalanceType::Borrow,
             bank,
-            &mut market.amm.fee_pool,
         )?;
 
-        market.amm.total_fee_withdrawn = market
-            .amm
-            .total_fee_withdrawn
-            .checked_add(cast(amount)?)
-            .ok_or_else(math_error!())?;
-
         Ok(())
     }
 
@@ -1736,6 +2145,12 @@ pub mod clearing_house {
         ctx: Context<WithdrawFromInsuranceVault>,
         amount: u64,
     ) -> Result<()> {
+        controller::insurance::admin_withdraw_from_insurance_fund(
+            amount,
+            ctx.accounts.insurance_vault.amount,
+            &mut ctx.accounts.state,
+        )?;
+
         controller::token::send_from_insurance_vault(
             &ctx.accounts.token_program,
             &ctx.accounts.insurance_vault,
@@ -1754,6 +2169,12 @@ pub mod clearing_house {
         ctx: Context<WithdrawFromInsuranceVaultToMarket>,
         amount: u64,
     ) -> Result<()> {
+        controller::insurance::admin_withdraw_from_insurance_fund(
+            amount,
+            ctx.accounts.insurance_vault.amount,
+            &mut ctx.accounts.state,
+        )?;
+
         let market = &mut load_mut!(ctx.accounts.market)?;
 
         // The admin can move fees from the insurance fund back to the protocol so that money in
@@ -1951,6 +2372,130 @@ pub mod clearing_house {
         Ok(())
     }
 
+    pub fn add_insurance_fund_stake(
+        ctx: Context<AddInsuranceFundStake>,
+        amount: u64,
+    ) -> Result<()> {
+        let user_stats = &mut load_mut!(ctx.accounts.user_stats)?;
+
+        controller::insurance::add_insurance_fund_stake(
+            amount,
+            ctx.accounts.insurance_vault.amount,
+            user_stats,
+            &mut ctx.accounts.state,
+        )?;
+
+        controller::token::receive(
+            &ctx.accounts.token_program,
+            &ctx.accounts.user_token_account,
+            &ctx.accounts.insurance_vault,
+            &ctx.accounts.authority,
+            amount,
+        )?;
+
+        Ok(())
+    }
+
+    pub fn request_remove_insurance_fund_stake(
+        ctx: Context<RequestRemoveInsuranceFundStake>,
+        shares: u128,
+    ) -> Result<()> {
+        let clock = Clock::get()?;
+        let user_stats = &mut load_mut!(ctx.accounts.user_stats)?;
+
+        controller::insurance::request_remove_insurance_fund_stake(
+            shares,
+            ctx.accounts.insurance_vault.amount,
+            user_stats,
+            &ctx.accounts.state,
+            clock.unix_timestamp,
+        )?;
+
+        Ok(())
+    }
+
+    pub fn cancel_request_remove_insurance_fund_stake(
+        ctx: Context<RequestRemoveInsuranceFundStake>,
+    ) -> Result<()> {
+        let user_stats = &mut load_mut!(ctx.accounts.user_stats)?;
+
+        controller::insurance::cancel_request_remove_insurance_fund_stake(
+            user_stats,
+            &ctx.accounts.state,
+        )?;
+
+        Ok(())
+    }
+
+    pub fn remove_insurance_fund_stake(ctx: Context<RemoveInsuranceFundStake>) -> Result<()> {
+        let clock = Clock::get()?;
+        let user_stats = &mut load_mut!(ctx.accounts.user_stats)?;
+
+        let amount = controller::insurance::remove_insurance_fund_stake(
+            ctx.accounts.insurance_vault.amount,
+            user_stats,
+            &mut ctx.accounts.state,
+            clock.unix_timestamp,
+        )?;
+
+        controller::token::send_from_insurance_vault(
+            &ctx.accounts.token_program,
+            &ctx.accounts.insurance_vault,
+            &ctx.accounts.user_token_account,
+            &ctx.accounts.insurance_vault_authority,
+            ctx.accounts.state.insurance_vault_nonce,
+            amount,
+        )?;
+
+        Ok(())
+    }
+
+    #[allow(unused_must_use)]
+    #[access_control(
+        market_initialized(&ctx.accounts.market) &&
+        exchange_not_paused(&ctx.accounts.state)
+    )]
+    pub fn settle_market_fees_to_insurance_fund(
+        ctx: Context<SettleMarketFeesToInsuranceFund>,
+    ) -> Result<()> {
+        let market = &mut load_mut!(ctx.accounts.market)?;
+        let bank = &mut load_mut!(ctx.accounts.bank)?;
+
+        let max_withdraw = amm::calculate_max_fee_withdraw(&market.amm)?;
+        let amm_fee_pool_token_amount =
+            get_token_amount(market.amm.fee_pool.balance, bank, &BankBalanceType::Deposit)?;
+
+        let amount = cast_to_u64(min(max_withdraw, amm_fee_pool_token_amount))?;
+        if amount == 0 {
+            return Ok(());
+        }
+
+        controller::token::send_from_bank_vault(
+            &ctx.accounts.token_program,
+            &ctx.accounts.bank_vault,
+            &ctx.accounts.insurance_vault,
+            &ctx.accounts.bank_vault_authority,
+            0,
+            bank.vault_authority_nonce,
+            amount,
+        )?;
+
+        controller::bank_balance::update_bank_balances(
+            cast_to_u128(amount)?,
+            &BankBalanceType::Borrow,
+            bank,
+            &mut market.amm.fee_pool,
+        )?;
+
+        market.amm.total_fee_withdrawn = market
+            .amm
+            .total_fee_withdrawn
+            .checked_add(cast(amount)?)
+            .ok_or_else(math_error!())?;
+
+        Ok(())
+    }
+
     pub fn initialize_user_stats(ctx: Context<InitializeUserStats>) -> Result<()> {
         let clock = Clock::get()?;
 
@@ -2034,6 +2579,18 @@ pub mod clearing_house {
         Ok(())
     }
 
+    #[access_control(
+        exchange_not_paused(&ctx.accounts.state)
+    )]
+    pub fn rebalance_k(ctx: Context<RebalanceK>) -> Result<()> {
+        let market = &mut load_mut!(ctx.accounts.market)?;
+        let now = Clock::get()?.unix_timestamp;
+
+        controller::amm::rebalance_k(market, now)?;
+
+        Ok(())
+    }
+
     #[allow(unused_must_use)]
     #[access_control(
         market_initialized(&ctx.accounts.market) &&
@@ -2230,6 +2787,54 @@ pub mod clearing_house {
         Ok(())
     }
 
+    pub fn update_bank_interest_rate_model(
+        ctx: Context<AdminUpdateBank>,
+        interest_rate_model: InterestRateModel,
+        optimal_utilization: u128,
+        optimal_borrow_rate: u128,
+        second_kink_utilization: u128,
+        second_kink_borrow_rate: u128,
+        max_borrow_rate: u128,
+        fixed_borrow_rate: u128,
+    ) -> Result<()> {
+        let bank = &mut load_mut!(ctx.accounts.bank)?;
+
+        // accrue interest under the old model before switching
+        let now = Clock::get()?.unix_timestamp;
+        controller::bank_balance::update_bank_cumulative_interest(bank, now)?;
+
+        bank.interest_rate_model = interest_rate_model;
+        bank.optimal_utilization = optimal_utilization;
+        bank.optimal_borrow_rate = optimal_borrow_rate;
+        bank.second_kink_utilization = second_kink_utilization;
+        bank.second_kink_borrow_rate = second_kink_borrow_rate;
+        bank.max_borrow_rate = max_borrow_rate;
+        bank.fixed_borrow_rate = fixed_borrow_rate;
+        bank.validate_interest_rate_model()?;
+
+        Ok(())
+    }
+
+    pub fn update_bank_reserve_factor(
+        ctx: Context<AdminUpdateBank>,
+        reserve_factor: u128,
+    ) -> Result<()> {
+        let bank = &mut load_mut!(ctx.accounts.bank)?;
+
+        let now = Clock::get()?.unix_timestamp;
+        controller::bank_balance::update_bank_cumulative_interest(bank, now)?;
+
+        msg!(
+            "bank.reserve_factor: {:?} -> {:?}",
+            bank.reserve_factor,
+            reserve_factor
+        );
+        bank.reserve_factor = reserve_factor;
+        bank.validate_interest_rate_model()?;
+
+        Ok(())
+    }
+
     pub fn update_bank_withdraw_guard_threshold(
         ctx: Context<AdminUpdateBank>,
         withdraw_guard_threshold: u128,
@@ -2462,6 +3067,104 @@ pub mod clearing_house {
         Ok(())
     }
 
+    #[access_control(
+        market_initialized(&ctx.accounts.market)
+    )]
+    pub fn update_market_spread_scales(
+        ctx: Context<AdminUpdateMarket>,
+        inventory_spread_scale: u32,
+        volatility_spread_scale: u32,
+        confidence_spread_scale: u32,
+    ) -> Result<()> {
+        let market = &mut load_mut!(ctx.accounts.market)?;
+        market.amm.inventory_spread_scale = inventory_spread_scale;
+        market.amm.volatility_spread_scale = volatility_spread_scale;
+        market.amm.confidence_spread_scale = confidence_spread_scale;
+        Ok(())
+    }
+
+    #[access_control(
+        market_initialized(&ctx.accounts.market)
+    )]
+    pub fn update_market_oracle_peg(
+        ctx: Context<AdminUpdateMarket>,
+        oracle_peg_enabled: bool,
+        oracle_peg_slot_budget_pct: u32,
+    ) -> Result<()> {
+        validate!(
+            oracle_peg_slot_budget_pct as u128 <= PEG_BPS_UPDATE_SCALE,
+            ErrorCode::DefaultError,
+            "invalid oracle_peg_slot_budget_pct",
+        )?;
+
+        let market = &mut load_mut!(ctx.accounts.market)?;
+        market.amm.oracle_peg_enabled = oracle_peg_enabled;
+        market.amm.oracle_peg_slot_budget_pct = oracle_peg_slot_budget_pct;
+        // budget only accrues from when the mode is turned on
+        market.amm.last_oracle_peg_slot = Clock::get()?.slot;
+        Ok(())
+    }
+
+    #[access_control(
+        market_initialized(&ctx.accounts.market)
+    )]
+    pub fn update_amm_jit_intensity(
+        ctx: Context<AdminUpdateMarket>,
+        amm_jit_intensity: u8,
+    ) -> Result<()> {
+        validate!(
+            amm_jit_intensity as u128 <= AMM_JIT_INTENSITY_PRECISION,
+            ErrorCode::DefaultError,
+            "invalid amm_jit_intensity",
+        )?;
+
+        let market = &mut load_mut!(ctx.accounts.market)?;
+        market.amm.amm_jit_intensity = amm_jit_intensity;
+        Ok(())
+    }
+
+    #[access_control(
+        market_initialized(&ctx.accounts.market)
+    )]
+    pub fn update_market_max_net_base_asset_amount(
+        ctx: Context<AdminUpdateMarket>,
+        max_net_base_asset_amount: u128,
+    ) -> Result<()> {
+        let market = &mut load_mut!(ctx.accounts.market)?;
+        market.amm.max_net_base_asset_amount = max_net_base_asset_amount;
+        Ok(())
+    }
+
+    #[access_control(
+        market_initialized(&ctx.accounts.market)
+    )]
+    pub fn update_market_order_tick_size(
+        ctx: Context<AdminUpdateMarket>,
+        order_tick_size: u128,
+    ) -> Result<()> {
+        validate!(
+            order_tick_size > 0,
+            ErrorCode::InvalidOrderTickSize,
+            "order tick size must be positive",
+        )?;
+
+        let market = &mut load_mut!(ctx.accounts.market)?;
+        market.amm.order_tick_size = order_tick_size;
+        Ok(())
+    }
+
+    #[access_control(
+        market_initialized(&ctx.accounts.market)
+    )]
+    pub fn update_market_auction_price_curve(
+        ctx: Context<AdminUpdateMarket>,
+        auction_price_curve: AuctionPriceCurve,
+    ) -> Result<()> {
+        let market = &mut load_mut!(ctx.accounts.market)?;
+        market.auction_price_curve = auction_price_curve;
+        Ok(())
+    }
+
     #[access_control(
         market_initialized(&ctx.accounts.market)
     )]
@@ -2561,6 +3264,34 @@ pub mod clearing_house {
         ctx.accounts.state.max_auction_duration = max_auction_duration;
         Ok(())
     }
+
+    pub fn update_insurance_fund_unstaking_period(
+        ctx: Context<AdminUpdateState>,
+        insurance_fund_unstaking_period: i64,
+    ) -> Result<()> {
+        validate!(
+            insurance_fund_unstaking_period >= 0,
+            ErrorCode::InvalidInsuranceFundUnstakingConfig,
+            "insurance fund unstaking period must be non-negative",
+        )?;
+
+        ctx.accounts.state.insurance_fund_unstaking_period = insurance_fund_unstaking_period;
+        Ok(())
+    }
+
+    pub fn update_insurance_fund_unstaking_window(
+        ctx: Context<AdminUpdateState>,
+        insurance_fund_unstaking_window: i64,
+    ) -> Result<()> {
+        validate!(
+            insurance_fund_unstaking_window > 0,
+            ErrorCode::InvalidInsuranceFundUnstakingConfig,
+            "insurance fund unstaking window must be positive",
+        )?;
+
+        ctx.accounts.state.insurance_fund_unstaking_window = insurance_fund_unstaking_window;
+        Ok(())
+    }
 }
 
 fn market_initialized(market: &AccountLoader<Market>) -> Result<()> {
@@ -7,9 +7,11 @@ use crate::math::casting::{cast, cast_to_i128, cast_to_u128, cast_to_u64};
 use crate::math::constants::{
     AMM_RESERVE_PRECISION, AMM_TIMES_PEG_TO_QUOTE_PRECISION_RATIO_I128,
     AMM_TO_QUOTE_PRECISION_RATIO_I128, BID_ASK_SPREAD_PRECISION, BID_ASK_SPREAD_PRECISION_I128,
-    K_BPS_DECREASE_MAX, K_BPS_INCREASE_MAX, K_BPS_UPDATE_SCALE, MARK_PRICE_PRECISION,
-    MARK_PRICE_PRECISION_I128, MAX_BID_ASK_INVENTORY_SKEW_FACTOR, ONE_HOUR_I128, PEG_PRECISION,
-    PRICE_TO_PEG_PRECISION_RATIO, QUOTE_PRECISION,
+    K_BPS_DAILY_DECREASE_MAX, K_BPS_DAILY_INCREASE_MAX, K_BPS_DECREASE_MAX, K_BPS_INCREASE_MAX,
+    K_BPS_UPDATE_SCALE, MARK_PRICE_PRECISION, MARK_PRICE_PRECISION_I128,
+    MAX_BID_ASK_INVENTORY_SKEW_FACTOR, ONE_HOUR_I128, PEG_PRECISION, PRICE_TO_PEG_PRECISION_RATIO,
+    QUOTE_PRECISION, SHARE_OF_FEES_ALLOCATED_TO_CLEARING_HOUSE_DENOMINATOR,
+    SHARE_OF_FEES_ALLOCATED_TO_CLEARING_HOUSE_NUMERATOR,
 };
 use crate::math::orders::standardize_base_asset_amount;
 use crate::math::position::{_calculate_base_asset_value_and_pnl, calculate_base_asset_value};
@@ -92,6 +94,11 @@ pub fn calculate_spread(
     net_base_asset_amount: i128,
     mark_price: u128,
     total_fee_minus_distributions: i128,
+    sqrt_k: u128,
+    mark_std: u64,
+    inventory_spread_scale: u32,
+    volatility_spread_scale: u32,
+    confidence_spread_scale: u32,
 ) -> ClearingHouseResult<(u128, u128)> {
     let mut long_spread = (base_spread / 2) as u128;
     let mut short_spread = (base_spread / 2) as u128;
@@ -173,6 +180,33 @@ pub fn calculate_spread(
             .checked_div(BID_ASK_SPREAD_PRECISION)
             .ok_or_else(math_error!())?;
     }
+
+    let (dynamic_long_spread, dynamic_short_spread) = calculate_dynamic_spread(
+        net_base_asset_amount,
+        sqrt_k,
+        mark_std,
+        mark_price,
+        last_oracle_conf_pct,
+        inventory_spread_scale,
+        volatility_spread_scale,
+        confidence_spread_scale,
+    )?;
+    long_spread = long_spread
+        .checked_add(dynamic_long_spread)
+        .ok_or_else(math_error!())?;
+    short_spread = short_spread
+        .checked_add(dynamic_short_spread)
+        .ok_or_else(math_error!())?;
+
+    cap_spread_to_max_spread(long_spread, short_spread, max_spread)
+}
+
+/// Shrinks the spreads to max_spread in total, keeping as much of the wider side as possible
+pub fn cap_spread_to_max_spread(
+    mut long_spread: u128,
+    mut short_spread: u128,
+    max_spread: u32,
+) -> ClearingHouseResult<(u128, u128)> {
     let total_spread = long_spread
         .checked_add(short_spread)
         .ok_or_else(math_error!())?;
@@ -196,6 +230,75 @@ pub fn calculate_spread(
     Ok((long_spread, short_spread))
 }
 
+/// Widens the spread with inventory skew (on the side that adds to the amm's exposure),
+/// mark volatility and oracle confidence (on both sides), each scaled by a per market coefficient
+pub fn calculate_dynamic_spread(
+    net_base_asset_amount: i128,
+    sqrt_k: u128,
+    mark_std: u64,
+    mark_price: u128,
+    last_oracle_conf_pct: u64,
+    inventory_spread_scale: u32,
+    volatility_spread_scale: u32,
+    confidence_spread_scale: u32,
+) -> ClearingHouseResult<(u128, u128)> {
+    let inventory_spread = if inventory_spread_scale > 0 && sqrt_k > 0 {
+        let inventory_skew_pct = net_base_asset_amount
+            .unsigned_abs()
+            .checked_mul(BID_ASK_SPREAD_PRECISION)
+            .ok_or_else(math_error!())?
+            .checked_div(sqrt_k)
+            .ok_or_else(math_error!())?;
+
+        scale_spread(inventory_skew_pct, inventory_spread_scale)?
+    } else {
+        0
+    };
+
+    let volatility_spread = if volatility_spread_scale > 0 && mark_price > 0 {
+        let volatility_pct = cast_to_u128(mark_std)?
+            .checked_mul(BID_ASK_SPREAD_PRECISION)
+            .ok_or_else(math_error!())?
+            .checked_div(mark_price)
+            .ok_or_else(math_error!())?;
+
+        scale_spread(volatility_pct, volatility_spread_scale)?
+    } else {
+        0
+    };
+
+    let confidence_spread =
+        scale_spread(cast_to_u128(last_oracle_conf_pct)?, confidence_spread_scale)?;
+
+    let symmetric_spread = volatility_spread
+        .checked_add(confidence_spread)
+        .ok_or_else(math_error!())?;
+
+    // users long means the amm is short, so longs pay for the added inventory
+    let (long_inventory_spread, short_inventory_spread) = if net_base_asset_amount > 0 {
+        (inventory_spread, 0)
+    } else {
+        (0, inventory_spread)
+    };
+
+    Ok((
+        symmetric_spread
+            .checked_add(long_inventory_spread)
+            .ok_or_else(math_error!())?,
+        symmetric_spread
+            .checked_add(short_inventory_spread)
+            .ok_or_else(math_error!())?,
+    ))
+}
+
+fn scale_spread(spread_pct: u128, scale: u32) -> ClearingHouseResult<u128> {
+    spread_pct
+        .checked_mul(cast_to_u128(scale)?)
+        .ok_or_else(math_error!())?
+        .checked_div(BID_ASK_SPREAD_PRECISION)
+        .ok_or_else(math_error!())
+}
+
 pub fn update_mark_twap(
     amm: &mut AMM,
     now: i64,
@@ -1283,12 +1386,95 @@ pub fn calculate_max_base_asset_amount_fillable(amm: &AMM) -> ClearingHouseResul
     )
 }
 
+/// Half of the fee revenue since the last rebalance goes to deepening k while users are in a
+/// position. Funding, repeg and k costs paid from the fee pool don't count against the budget
+pub fn calculate_rebalance_k_budget(market: &Market) -> ClearingHouseResult<i128> {
+    if market.open_interest == 0 {
+        return Ok(0);
+    }
+
+    let fee_revenue = market
+        .amm
+        .total_fee
+        .checked_sub(market.amm.last_rebalance_k_total_fee)
+        .ok_or_else(math_error!())?;
+
+    cast_to_i128(fee_revenue.checked_div(2).ok_or_else(math_error!())?)
+}
+
+pub fn bound_sqrt_k_to_daily_change(
+    new_sqrt_k: u128,
+    day_start_sqrt_k: u128,
+) -> ClearingHouseResult<u128> {
+    let k_bps_update_scale = cast_to_u128(K_BPS_UPDATE_SCALE)?;
+
+    let max_sqrt_k = day_start_sqrt_k
+        .checked_mul(
+            k_bps_update_scale
+                .checked_add(cast_to_u128(K_BPS_DAILY_INCREASE_MAX)?)
+                .ok_or_else(math_error!())?,
+        )
+        .ok_or_else(math_error!())?
+        .checked_div(k_bps_update_scale)
+        .ok_or_else(math_error!())?;
+
+    let min_sqrt_k = day_start_sqrt_k
+        .checked_mul(
+            k_bps_update_scale
+                .checked_sub(cast_to_u128(K_BPS_DAILY_DECREASE_MAX)?)
+                .ok_or_else(math_error!())?,
+        )
+        .ok_or_else(math_error!())?
+        .checked_div(k_bps_update_scale)
+        .ok_or_else(math_error!())?;
+
+    Ok(new_sqrt_k.clamp(min_sqrt_k, max_sqrt_k))
+}
+
+/// How much more the users can trade in direction before the amm's net inventory hits its cap
+pub fn calculate_max_base_asset_amount_for_inventory(
+    amm: &AMM,
+    direction: PositionDirection,
+) -> ClearingHouseResult<u128> {
+    if amm.max_net_base_asset_amount == 0 {
+        return Ok(u128::MAX);
+    }
+
+    let max_net_base_asset_amount = cast_to_i128(amm.max_net_base_asset_amount)?;
+    let max_base_asset_amount = match direction {
+        PositionDirection::Long => max_net_base_asset_amount
+            .checked_sub(amm.net_base_asset_amount)
+            .ok_or_else(math_error!())?,
+        PositionDirection::Short => max_net_base_asset_amount
+            .checked_add(amm.net_base_asset_amount)
+            .ok_or_else(math_error!())?,
+    };
+
+    standardize_base_asset_amount(
+        max_base_asset_amount.max(0).unsigned_abs(),
+        amm.base_asset_amount_step_size,
+    )
+}
+
+/// A portion of fees must always remain in protocol to be used to keep markets optimal
+pub fn calculate_max_fee_withdraw(amm: &AMM) -> ClearingHouseResult<u128> {
+    amm.total_exchange_fee
+        .checked_mul(SHARE_OF_FEES_ALLOCATED_TO_CLEARING_HOUSE_NUMERATOR)
+        .ok_or_else(math_error!())?
+        .checked_div(SHARE_OF_FEES_ALLOCATED_TO_CLEARING_HOUSE_DENOMINATOR)
+        .ok_or_else(math_error!())?
+        .checked_sub(amm.total_fee_withdrawn)
+        .ok_or_else(math_error!())
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
     use crate::controller::amm::update_spreads;
     use crate::controller::lp::settle_lp_position;
-    use crate::math::constants::{MARK_PRICE_PRECISION, QUOTE_PRECISION_I128};
+    use crate::math::constants::{
+        AMM_RESERVE_PRECISION_I128, MARK_PRICE_PRECISION, QUOTE_PRECISION_I128,
+    };
     use crate::state::user::MarketPosition;
 
     #[test]
@@ -1317,6 +1503,11 @@ mod test {
             net_base_asset_amount,
             mark_price,
             total_fee_minus_distributions,
+            0,
+            0,
+            0,
+            0,
+            0,
         )
         .unwrap();
         assert_eq!(long_spread1, (base_spread * 5 / 2) as u128);
@@ -1336,6 +1527,11 @@ mod test {
             net_base_asset_amount,
             mark_price,
             total_fee_minus_distributions,
+            0,
+            0,
+            0,
+            0,
+            0,
         )
         .unwrap();
         assert_eq!(long_spread2, (base_spread * 5 / 2) as u128);
@@ -1356,6 +1552,11 @@ mod test {
             net_base_asset_amount,
             mark_price,
             total_fee_minus_distributions,
+            0,
+            0,
+            0,
+            0,
+            0,
         )
         .unwrap();
         assert!(short_spread3 > long_spread3);
@@ -1380,6 +1581,11 @@ mod test {
             net_base_asset_amount,
             mark_price,
             total_fee_minus_distributions,
+            0,
+            0,
+            0,
+            0,
+            0,
         )
         .unwrap();
         assert!(short_spread4 < long_spread4);
@@ -1400,6 +1606,11 @@ mod test {
             net_base_asset_amount,
             mark_price,
             total_fee_minus_distributions * 2,
+            0,
+            0,
+            0,
+            0,
+            0,
         )
         .unwrap();
 
@@ -1439,6 +1650,11 @@ mod test {
             -1931600000000,
             219277638717000,
             50457675,
+            0,
+            0,
+            0,
+            0,
+            0,
         )
         .unwrap();
 
@@ -1456,6 +1672,11 @@ mod test {
             -1930600000000,
             216710715732581,
             4876326,
+            0,
+            0,
+            0,
+            0,
+            0,
         )
         .unwrap();
 
@@ -1464,6 +1685,160 @@ mod test {
         assert_eq!(short_spread_btc1, 197668); // max spread
     }
 
+    #[test]
+    fn calculate_dynamic_spread_tests() {
+        let sqrt_k = 100 * AMM_RESERVE_PRECISION;
+        let mark_price = 100 * MARK_PRICE_PRECISION;
+        let mark_std = MARK_PRICE_PRECISION as u64; // 1%
+        let last_oracle_conf_pct = (BID_ASK_SPREAD_PRECISION / 1000) as u64; // .1%
+
+        // no coefficients, no widening
+        let (long_spread, short_spread) = calculate_dynamic_spread(
+            10 * AMM_RESERVE_PRECISION_I128,
+            sqrt_k,
+            mark_std,
+            mark_price,
+            last_oracle_conf_pct,
+            0,
+            0,
+            0,
+        )
+        .unwrap();
+        assert_eq!(long_spread, 0);
+        assert_eq!(short_spread, 0);
+
+        // 10% skew * .5 + 1% vol * 2 + .1% conf * 1
+        let (long_spread, short_spread) = calculate_dynamic_spread(
+            10 * AMM_RESERVE_PRECISION_I128,
+            sqrt_k,
+            mark_std,
+            mark_price,
+            last_oracle_conf_pct,
+            (BID_ASK_SPREAD_PRECISION / 2) as u32,
+            (2 * BID_ASK_SPREAD_PRECISION) as u32,
+            BID_ASK_SPREAD_PRECISION as u32,
+        )
+        .unwrap();
+        assert_eq!(long_spread, 50000 + 20000 + 1000);
+        assert_eq!(short_spread, 20000 + 1000);
+
+        // short skew widens the short side
+        let (long_spread, short_spread) = calculate_dynamic_spread(
+            -10 * AMM_RESERVE_PRECISION_I128,
+            sqrt_k,
+            mark_std,
+            mark_price,
+            last_oracle_conf_pct,
+            (BID_ASK_SPREAD_PRECISION / 2) as u32,
+            (2 * BID_ASK_SPREAD_PRECISION) as u32,
+            BID_ASK_SPREAD_PRECISION as u32,
+        )
+        .unwrap();
+        assert_eq!(long_spread, 20000 + 1000);
+        assert_eq!(short_spread, 50000 + 20000 + 1000);
+
+        // widened spreads are still capped at max spread
+        let (long_spread, short_spread) = calculate_spread(
+            1000,
+            0,
+            last_oracle_conf_pct,
+            50000,
+            100 * AMM_RESERVE_PRECISION,
+            100 * AMM_RESERVE_PRECISION,
+            100 * PEG_PRECISION,
+            10 * AMM_RESERVE_PRECISION_I128,
+            mark_price,
+            QUOTE_PRECISION_I128,
+            sqrt_k,
+            mark_std,
+            (BID_ASK_SPREAD_PRECISION / 2) as u32,
+            (2 * BID_ASK_SPREAD_PRECISION) as u32,
+            BID_ASK_SPREAD_PRECISION as u32,
+        )
+        .unwrap();
+        assert_eq!(long_spread + short_spread, 50000);
+        assert!(long_spread > short_spread);
+    }
+
+    #[test]
+    fn calculate_rebalance_k_budget_tests() {
+        let mut market = Market {
+            amm: AMM {
+                total_fee: 100 * QUOTE_PRECISION,
+                last_rebalance_k_total_fee: 60 * QUOTE_PRECISION,
+                total_fee_minus_distributions: -10 * QUOTE_PRECISION_I128,
+                ..AMM::default()
+            },
+            open_interest: 1,
+            ..Market::default()
+        };
+
+        // half the fee revenue since the last rebalance, regardless of what the fee pool paid out
+        assert_eq!(
+            calculate_rebalance_k_budget(&market).unwrap(),
+            20 * QUOTE_PRECISION_I128
+        );
+
+        // nobody to deepen the market for
+        market.open_interest = 0;
+        assert_eq!(calculate_rebalance_k_budget(&market).unwrap(), 0);
+    }
+
+    #[test]
+    fn bound_sqrt_k_to_daily_change_tests() {
+        let day_start_sqrt_k = 100 * AMM_RESERVE_PRECISION;
+
+        assert_eq!(
+            bound_sqrt_k_to_daily_change(1005 * AMM_RESERVE_PRECISION / 10, day_start_sqrt_k)
+                .unwrap(),
+            1005 * AMM_RESERVE_PRECISION / 10
+        );
+        assert_eq!(
+            bound_sqrt_k_to_daily_change(102 * AMM_RESERVE_PRECISION, day_start_sqrt_k).unwrap(),
+            101 * AMM_RESERVE_PRECISION
+        );
+        assert_eq!(
+            bound_sqrt_k_to_daily_change(90 * AMM_RESERVE_PRECISION, day_start_sqrt_k).unwrap(),
+            95 * AMM_RESERVE_PRECISION
+        );
+    }
+
+    #[test]
+    fn calculate_max_base_asset_amount_for_inventory_tests() {
+        let mut amm = AMM {
+            net_base_asset_amount: 3 * AMM_RESERVE_PRECISION_I128,
+            base_asset_amount_step_size: AMM_RESERVE_PRECISION / 10,
+            ..AMM::default()
+        };
+
+        // no cap
+        assert_eq!(
+            calculate_max_base_asset_amount_for_inventory(&amm, PositionDirection::Long).unwrap(),
+            u128::MAX
+        );
+
+        amm.max_net_base_asset_amount = 5 * AMM_RESERVE_PRECISION;
+        assert_eq!(
+            calculate_max_base_asset_amount_for_inventory(&amm, PositionDirection::Long).unwrap(),
+            2 * AMM_RESERVE_PRECISION
+        );
+        assert_eq!(
+            calculate_max_base_asset_amount_for_inventory(&amm, PositionDirection::Short).unwrap(),
+            8 * AMM_RESERVE_PRECISION
+        );
+
+        // already past the cap, only trades that reduce inventory are allowed
+        amm.net_base_asset_amount = 6 * AMM_RESERVE_PRECISION_I128 + 1;
+        assert_eq!(
+            calculate_max_base_asset_amount_for_inventory(&amm, PositionDirection::Long).unwrap(),
+            0
+        );
+        assert_eq!(
+            calculate_max_base_asset_amount_for_inventory(&amm, PositionDirection::Short).unwrap(),
+            11 * AMM_RESERVE_PRECISION
+        );
+    }
+
     #[test]
     fn k_update_results_bound_flag() {
         let init_reserves = 100 * AMM_RESERVE_PRECISION;
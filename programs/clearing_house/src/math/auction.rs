@@ -1,15 +1,18 @@
 use crate::controller::amm::{calculate_base_swap_output_with_spread, SwapDirection};
 use crate::controller::position::PositionDirection;
-use crate::error::ClearingHouseResult;
+use crate::error::{ClearingHouseResult, ErrorCode};
 use crate::math::amm::calculate_price;
-use crate::math::casting::cast;
-use crate::math::constants::MARK_PRICE_TIMES_AMM_TO_QUOTE_PRECISION_RATIO;
+use crate::math::casting::{cast, cast_to_i128};
+use crate::math::constants::{
+    AUCTION_EXPONENTIAL_DECAY_HALF_LIVES, AUCTION_PRICE_CURVE_STEPS,
+    MARK_PRICE_TIMES_AMM_TO_QUOTE_PRECISION_RATIO,
+};
 use crate::math::position::calculate_entry_price;
 use crate::math_error;
 use crate::state::market::Market;
-use crate::state::user::Order;
+use crate::state::user::{AuctionPriceCurve, Order};
 use solana_program::msg;
-use std::cmp::min;
+use std::cmp::{max, min};
 
 /// for bid (direction == Long), the auction start price is based on the bid reserves
 /// for ask (direction == Short), the auction start price is based on the ask reserves
@@ -55,33 +58,55 @@ pub fn calculate_auction_end_price(
     Ok(auction_end_price)
 }
 
-pub fn calculate_auction_price(order: &Order, slot: u64) -> ClearingHouseResult<u128> {
+pub fn calculate_auction_price(
+    order: &Order,
+    slot: u64,
+    valid_oracle_price: Option<i128>,
+) -> ClearingHouseResult<u128> {
     let slots_elapsed = slot.checked_sub(order.slot).ok_or_else(math_error!())?;
 
-    let delta_numerator = min(slots_elapsed, cast(order.auction_duration)?);
-    let delta_denominator = order.auction_duration;
+    let delta_numerator: u128 = cast(min(slots_elapsed, cast(order.auction_duration)?))?;
+    let delta_denominator: u128 = cast(order.auction_duration)?;
 
     if delta_denominator == 0 {
         return Ok(order.auction_end_price);
     }
 
-    let price_delta = match order.direction {
+    let price_range = match order.direction {
         PositionDirection::Long => order
             .auction_end_price
             .checked_sub(order.auction_start_price)
-            .ok_or_else(math_error!())?
-            .checked_mul(cast(delta_numerator)?)
-            .ok_or_else(math_error!())?
-            .checked_div(cast(delta_denominator)?)
             .ok_or_else(math_error!())?,
         PositionDirection::Short => order
             .auction_start_price
             .checked_sub(order.auction_end_price)
+            .ok_or_else(math_error!())?,
+    };
+
+    let price_delta = match order.auction_price_curve {
+        AuctionPriceCurve::Linear | AuctionPriceCurve::OracleRelative => price_range
+            .checked_mul(delta_numerator)
             .ok_or_else(math_error!())?
-            .checked_mul(cast(delta_numerator)?)
-            .ok_or_else(math_error!())?
-            .checked_div(cast(delta_denominator)?)
+            .checked_div(delta_denominator)
             .ok_or_else(math_error!())?,
+        AuctionPriceCurve::ExponentialDecay => calculate_exponential_decay_price_delta(
+            price_range,
+            delta_numerator,
+            delta_denominator,
+        )?,
+        AuctionPriceCurve::Step => {
+            let steps_elapsed = delta_numerator
+                .checked_mul(AUCTION_PRICE_CURVE_STEPS)
+                .ok_or_else(math_error!())?
+                .checked_div(delta_denominator)
+                .ok_or_else(math_error!())?;
+
+            price_range
+                .checked_mul(steps_elapsed)
+                .ok_or_else(math_error!())?
+                .checked_div(AUCTION_PRICE_CURVE_STEPS)
+                .ok_or_else(math_error!())?
+        }
     };
 
     let price = match order.direction {
@@ -95,9 +120,95 @@ pub fn calculate_auction_price(order: &Order, slot: u64) -> ClearingHouseResult<
             .ok_or_else(math_error!())?,
     };
 
+    if order.auction_price_curve == AuctionPriceCurve::OracleRelative {
+        if let Some(oracle_price) = valid_oracle_price {
+            return shift_auction_price_by_oracle_move(price, order, oracle_price);
+        }
+    }
+
     Ok(price)
 }
 
+/// The remaining price range halves every auction_duration / AUCTION_EXPONENTIAL_DECAY_HALF_LIVES slots,
+/// interpolating linearly within each half life. The last slot of the auction reaches the end price.
+fn calculate_exponential_decay_price_delta(
+    price_range: u128,
+    slots_elapsed: u128,
+    auction_duration: u128,
+) -> ClearingHouseResult<u128> {
+    if slots_elapsed >= auction_duration {
+        return Ok(price_range);
+    }
+
+    let half_life_numerator = slots_elapsed
+        .checked_mul(AUCTION_EXPONENTIAL_DECAY_HALF_LIVES)
+        .ok_or_else(math_error!())?;
+    let half_lives_elapsed = half_life_numerator
+        .checked_div(auction_duration)
+        .ok_or_else(math_error!())?;
+    let half_life_remainder = half_life_numerator
+        .checked_rem(auction_duration)
+        .ok_or_else(math_error!())?;
+
+    let range_remaining = price_range >> half_lives_elapsed;
+    let range_remaining_next = range_remaining >> 1;
+    let range_remaining = range_remaining
+        .checked_sub(
+            range_remaining
+                .checked_sub(range_remaining_next)
+                .ok_or_else(math_error!())?
+                .checked_mul(half_life_remainder)
+                .ok_or_else(math_error!())?
+                .checked_div(auction_duration)
+                .ok_or_else(math_error!())?,
+        )
+        .ok_or_else(math_error!())?;
+
+    price_range
+        .checked_sub(range_remaining)
+        .ok_or_else(math_error!())
+}
+
+/// Moves the auction price by however much the oracle has moved since the auction started, never
+/// past the limit price of orders that have one
+fn shift_auction_price_by_oracle_move(
+    price: u128,
+    order: &Order,
+    oracle_price: i128,
+) -> ClearingHouseResult<u128> {
+    if order.auction_start_oracle_price == 0 {
+        return Ok(price);
+    }
+
+    let oracle_price_delta = oracle_price
+        .checked_sub(order.auction_start_oracle_price)
+        .ok_or_else(math_error!())?;
+
+    let shifted_price = cast_to_i128(price)?
+        .checked_add(oracle_price_delta)
+        .ok_or_else(math_error!())?;
+
+    if shifted_price <= 0 {
+        msg!(
+            "Oracle relative auction price below zero: {}",
+            shifted_price
+        );
+        return Err(ErrorCode::InvalidOracleOffset);
+    }
+
+    let shifted_price = shifted_price.unsigned_abs();
+
+    // auction_end_price is the user's limit price when one was given
+    if order.price == 0 {
+        return Ok(shifted_price);
+    }
+
+    Ok(match order.direction {
+        PositionDirection::Long => min(shifted_price, order.auction_end_price),
+        PositionDirection::Short => max(shifted_price, order.auction_end_price),
+    })
+}
+
 pub fn does_auction_satisfy_maker_order(
     maker_order: &Order,
     taker_order: &Order,
@@ -235,4 +346,172 @@ mod test {
         let expected_quote_asset_amount = 10 * QUOTE_PRECISION;
         assert_eq!(quote_asset_amount, expected_quote_asset_amount);
     }
+
+    pub mod calculate_auction_price {
+        use crate::controller::position::PositionDirection;
+        use crate::math::auction::calculate_auction_price;
+        use crate::math::constants::MARK_PRICE_PRECISION;
+        use crate::state::user::{AuctionPriceCurve, Order};
+
+        fn auction_order(auction_price_curve: AuctionPriceCurve) -> Order {
+            Order {
+                direction: PositionDirection::Long,
+                slot: 0,
+                auction_duration: 8,
+                auction_start_price: 100 * MARK_PRICE_PRECISION,
+                auction_end_price: 108 * MARK_PRICE_PRECISION,
+                auction_price_curve,
+                auction_start_oracle_price: 100 * MARK_PRICE_PRECISION as i128,
+                ..Order::default()
+            }
+        }
+
+        #[test]
+        fn linear() {
+            let order = auction_order(AuctionPriceCurve::Linear);
+
+            assert_eq!(
+                calculate_auction_price(&order, 0, None).unwrap(),
+                100 * MARK_PRICE_PRECISION
+            );
+            assert_eq!(
+                calculate_auction_price(&order, 2, None).unwrap(),
+                102 * MARK_PRICE_PRECISION
+            );
+            assert_eq!(
+                calculate_auction_price(&order, 8, None).unwrap(),
+                108 * MARK_PRICE_PRECISION
+            );
+        }
+
+        #[test]
+        fn exponential_decay() {
+            let order = auction_order(AuctionPriceCurve::ExponentialDecay);
+
+            assert_eq!(
+                calculate_auction_price(&order, 0, None).unwrap(),
+                100 * MARK_PRICE_PRECISION
+            );
+            assert_eq!(
+                calculate_auction_price(&order, 1, None).unwrap(),
+                102 * MARK_PRICE_PRECISION
+            );
+            assert_eq!(
+                calculate_auction_price(&order, 2, None).unwrap(),
+                104 * MARK_PRICE_PRECISION
+            );
+            assert_eq!(
+                calculate_auction_price(&order, 4, None).unwrap(),
+                106 * MARK_PRICE_PRECISION
+            );
+            assert_eq!(
+                calculate_auction_price(&order, 7, None).unwrap(),
+                107 * MARK_PRICE_PRECISION + MARK_PRICE_PRECISION / 4
+            );
+            assert_eq!(
+                calculate_auction_price(&order, 8, None).unwrap(),
+                108 * MARK_PRICE_PRECISION
+            );
+
+            let order = Order {
+                direction: PositionDirection::Short,
+                auction_start_price: 108 * MARK_PRICE_PRECISION,
+                auction_end_price: 100 * MARK_PRICE_PRECISION,
+                ..order
+            };
+            assert_eq!(
+                calculate_auction_price(&order, 2, None).unwrap(),
+                104 * MARK_PRICE_PRECISION
+            );
+        }
+
+        #[test]
+        fn step() {
+            let order = auction_order(AuctionPriceCurve::Step);
+
+            assert_eq!(
+                calculate_auction_price(&order, 1, None).unwrap(),
+                100 * MARK_PRICE_PRECISION
+            );
+            assert_eq!(
+                calculate_auction_price(&order, 2, None).unwrap(),
+                102 * MARK_PRICE_PRECISION
+            );
+            assert_eq!(
+                calculate_auction_price(&order, 5, None).unwrap(),
+                104 * MARK_PRICE_PRECISION
+            );
+            assert_eq!(
+                calculate_auction_price(&order, 8, None).unwrap(),
+                108 * MARK_PRICE_PRECISION
+            );
+        }
+
+        #[test]
+        fn oracle_relative() {
+            let order = auction_order(AuctionPriceCurve::OracleRelative);
+
+            let oracle_price = Some(110 * MARK_PRICE_PRECISION as i128);
+            assert_eq!(
+                calculate_auction_price(&order, 4, oracle_price).unwrap(),
+                114 * MARK_PRICE_PRECISION
+            );
+
+            let oracle_price = Some(95 * MARK_PRICE_PRECISION as i128);
+            assert_eq!(
+                calculate_auction_price(&order, 4, oracle_price).unwrap(),
+                99 * MARK_PRICE_PRECISION
+            );
+
+            // falls back to linear without a valid oracle
+            assert_eq!(
+                calculate_auction_price(&order, 4, None).unwrap(),
+                104 * MARK_PRICE_PRECISION
+            );
+
+            let oracle_price = Some(-(10 * MARK_PRICE_PRECISION as i128));
+            assert!(calculate_auction_price(&order, 4, oracle_price).is_err());
+        }
+
+        #[test]
+        fn oracle_relative_never_crosses_limit_price() {
+            let order = Order {
+                price: 108 * MARK_PRICE_PRECISION,
+                ..auction_order(AuctionPriceCurve::OracleRelative)
+            };
+
+            let oracle_price = Some(110 * MARK_PRICE_PRECISION as i128);
+            assert_eq!(
+                calculate_auction_price(&order, 4, oracle_price).unwrap(),
+                108 * MARK_PRICE_PRECISION
+            );
+
+            // moves freely below the limit
+            let oracle_price = Some(95 * MARK_PRICE_PRECISION as i128);
+            assert_eq!(
+                calculate_auction_price(&order, 4, oracle_price).unwrap(),
+                99 * MARK_PRICE_PRECISION
+            );
+
+            let order = Order {
+                direction: PositionDirection::Short,
+                price: 100 * MARK_PRICE_PRECISION,
+                auction_start_price: 108 * MARK_PRICE_PRECISION,
+                auction_end_price: 100 * MARK_PRICE_PRECISION,
+                ..order
+            };
+
+            let oracle_price = Some(90 * MARK_PRICE_PRECISION as i128);
+            assert_eq!(
+                calculate_auction_price(&order, 4, oracle_price).unwrap(),
+                100 * MARK_PRICE_PRECISION
+            );
+
+            let oracle_price = Some(105 * MARK_PRICE_PRECISION as i128);
+            assert_eq!(
+                calculate_auction_price(&order, 4, oracle_price).unwrap(),
+                109 * MARK_PRICE_PRECISION
+            );
+        }
+    }
 }
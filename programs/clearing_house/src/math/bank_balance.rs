@@ -2,9 +2,11 @@ use solana_program::msg;
 
 use crate::error::{ClearingHouseResult, ErrorCode};
 use crate::math::casting::{cast, cast_to_u64};
-use crate::math::constants::{BANK_INTEREST_PRECISION, BANK_UTILIZATION_PRECISION, ONE_YEAR};
+use crate::math::constants::{
+    BANK_INTEREST_PRECISION, BANK_RESERVE_FACTOR_PRECISION, BANK_UTILIZATION_PRECISION, ONE_YEAR,
+};
 use crate::math_error;
-use crate::state::bank::{Bank, BankBalanceType};
+use crate::state::bank::{Bank, BankBalanceType, InterestRateModel};
 use crate::state::oracle::OraclePriceData;
 use crate::state::user::UserBankBalance;
 
@@ -101,47 +103,7 @@ pub fn calculate_accumulated_interest(
         });
     }
 
-    let borrow_rate = if utilization > bank.optimal_utilization {
-        let surplus_utilization = utilization
-            .checked_sub(bank.optimal_utilization)
-            .ok_or_else(math_error!())?;
-
-        let borrow_rate_slope = bank
-            .max_borrow_rate
-            .checked_sub(bank.optimal_borrow_rate)
-            .ok_or_else(math_error!())?
-            .checked_mul(BANK_UTILIZATION_PRECISION)
-            .ok_or_else(math_error!())?
-            .checked_div(
-                BANK_UTILIZATION_PRECISION
-                    .checked_sub(bank.optimal_utilization)
-                    .ok_or_else(math_error!())?,
-            )
-            .ok_or_else(math_error!())?;
-
-        bank.optimal_borrow_rate
-            .checked_add(
-                surplus_utilization
-                    .checked_mul(borrow_rate_slope)
-                    .ok_or_else(math_error!())?
-                    .checked_div(BANK_UTILIZATION_PRECISION)
-                    .ok_or_else(math_error!())?,
-            )
-            .ok_or_else(math_error!())?
-    } else {
-        let borrow_rate_slope = bank
-            .optimal_borrow_rate
-            .checked_mul(BANK_UTILIZATION_PRECISION)
-            .ok_or_else(math_error!())?
-            .checked_div(bank.optimal_utilization)
-            .ok_or_else(math_error!())?;
-
-        utilization
-            .checked_mul(borrow_rate_slope)
-            .ok_or_else(math_error!())?
-            .checked_div(BANK_UTILIZATION_PRECISION)
-            .ok_or_else(math_error!())?
-    };
+    let borrow_rate = calculate_borrow_rate(bank, utilization)?;
 
     let time_since_last_update = cast_to_u64(now)
         .or(Err(ErrorCode::UnableToCastUnixTime))?
@@ -154,10 +116,19 @@ pub fn calculate_accumulated_interest(
         .checked_mul(time_since_last_update as u128)
         .ok_or_else(math_error!())?;
 
+    // the reserve factor's share of borrow interest is not passed through to depositors
     let modified_deposit_rate = modified_borrow_rate
         .checked_mul(utilization)
         .ok_or_else(math_error!())?
         .checked_div(BANK_UTILIZATION_PRECISION)
+        .ok_or_else(math_error!())?
+        .checked_mul(
+            BANK_RESERVE_FACTOR_PRECISION
+                .checked_sub(bank.reserve_factor)
+                .ok_or_else(math_error!())?,
+        )
+        .ok_or_else(math_error!())?
+        .checked_div(BANK_RESERVE_FACTOR_PRECISION)
         .ok_or_else(math_error!())?;
 
     let borrow_interest = bank
@@ -187,6 +158,68 @@ pub fn calculate_accumulated_interest(
     })
 }
 
+pub fn calculate_borrow_rate(bank: &Bank, utilization: u128) -> ClearingHouseResult<u128> {
+    match bank.interest_rate_model {
+        InterestRateModel::Kinked => calculate_piecewise_borrow_rate(
+            utilization,
+            &[
+                (bank.optimal_utilization, bank.optimal_borrow_rate),
+                (BANK_UTILIZATION_PRECISION, bank.max_borrow_rate),
+            ],
+        ),
+        InterestRateModel::MultiKink => calculate_piecewise_borrow_rate(
+            utilization,
+            &[
+                (bank.optimal_utilization, bank.optimal_borrow_rate),
+                (bank.second_kink_utilization, bank.second_kink_borrow_rate),
+                (BANK_UTILIZATION_PRECISION, bank.max_borrow_rate),
+            ],
+        ),
+        InterestRateModel::Fixed => Ok(bank.fixed_borrow_rate),
+    }
+}
+
+/// Linearly interpolates the borrow rate between (utilization, borrow rate) kinks, starting from (0, 0)
+fn calculate_piecewise_borrow_rate(
+    utilization: u128,
+    kinks: &[(u128, u128)],
+) -> ClearingHouseResult<u128> {
+    let mut previous_kink = (0_u128, 0_u128);
+    for kink in kinks.iter() {
+        let (kink_utilization, kink_borrow_rate) = *kink;
+        if utilization <= kink_utilization {
+            let (previous_utilization, previous_borrow_rate) = previous_kink;
+
+            let borrow_rate_slope = kink_borrow_rate
+                .checked_sub(previous_borrow_rate)
+                .ok_or_else(math_error!())?
+                .checked_mul(BANK_UTILIZATION_PRECISION)
+                .ok_or_else(math_error!())?
+                .checked_div(
+                    kink_utilization
+                        .checked_sub(previous_utilization)
+                        .ok_or_else(math_error!())?,
+                )
+                .ok_or_else(math_error!())?;
+
+            return previous_borrow_rate
+                .checked_add(
+                    utilization
+                        .checked_sub(previous_utilization)
+                        .ok_or_else(math_error!())?
+                        .checked_mul(borrow_rate_slope)
+                        .ok_or_else(math_error!())?
+                        .checked_div(BANK_UTILIZATION_PRECISION)
+                        .ok_or_else(math_error!())?,
+                )
+                .ok_or_else(math_error!());
+        }
+        previous_kink = *kink;
+    }
+
+    Ok(previous_kink.1)
+}
+
 pub fn get_balance_value_and_token_amount(
     bank_balance: &UserBankBalance,
     bank: &Bank,
@@ -259,3 +292,103 @@ pub fn check_withdraw_limits(bank: &Bank) -> ClearingHouseResult<bool> {
 
     Ok(valid_withdrawal)
 }
+
+#[cfg(test)]
+mod test {
+    use crate::math::bank_balance::{calculate_accumulated_interest, calculate_borrow_rate};
+    use crate::math::constants::{
+        BANK_CUMULATIVE_INTEREST_PRECISION, BANK_INTEREST_PRECISION, BANK_RESERVE_FACTOR_PRECISION,
+        BANK_UTILIZATION_PRECISION, ONE_YEAR,
+    };
+    use crate::state::bank::{Bank, InterestRateModel};
+
+    #[test]
+    fn kinked_borrow_rate() {
+        let bank = Bank {
+            optimal_utilization: BANK_UTILIZATION_PRECISION / 2,
+            optimal_borrow_rate: BANK_INTEREST_PRECISION / 5, // 20%
+            max_borrow_rate: BANK_INTEREST_PRECISION,         // 100%
+            ..Bank::default()
+        };
+
+        let borrow_rate = calculate_borrow_rate(&bank, BANK_UTILIZATION_PRECISION / 4).unwrap();
+        assert_eq!(borrow_rate, BANK_INTEREST_PRECISION / 10);
+
+        let borrow_rate = calculate_borrow_rate(&bank, BANK_UTILIZATION_PRECISION / 2).unwrap();
+        assert_eq!(borrow_rate, BANK_INTEREST_PRECISION / 5);
+
+        let borrow_rate = calculate_borrow_rate(&bank, BANK_UTILIZATION_PRECISION * 3 / 4).unwrap();
+        assert_eq!(borrow_rate, BANK_INTEREST_PRECISION * 3 / 5);
+
+        let borrow_rate = calculate_borrow_rate(&bank, BANK_UTILIZATION_PRECISION).unwrap();
+        assert_eq!(borrow_rate, BANK_INTEREST_PRECISION);
+    }
+
+    #[test]
+    fn multi_kink_borrow_rate() {
+        let bank = Bank {
+            interest_rate_model: InterestRateModel::MultiKink,
+            optimal_utilization: BANK_UTILIZATION_PRECISION / 2,
+            optimal_borrow_rate: BANK_INTEREST_PRECISION / 10, // 10%
+            second_kink_utilization: BANK_UTILIZATION_PRECISION * 9 / 10,
+            second_kink_borrow_rate: BANK_INTEREST_PRECISION / 5, // 20%
+            max_borrow_rate: BANK_INTEREST_PRECISION * 3,         // 300%
+            ..Bank::default()
+        };
+        bank.validate_interest_rate_model().unwrap();
+
+        let borrow_rate =
+            calculate_borrow_rate(&bank, BANK_UTILIZATION_PRECISION * 7 / 10).unwrap();
+        assert_eq!(borrow_rate, BANK_INTEREST_PRECISION * 15 / 100);
+
+        // jump past the second kink
+        let borrow_rate =
+            calculate_borrow_rate(&bank, BANK_UTILIZATION_PRECISION * 95 / 100).unwrap();
+        assert_eq!(borrow_rate, BANK_INTEREST_PRECISION * 160 / 100);
+    }
+
+    #[test]
+    fn fixed_borrow_rate() {
+        let bank = Bank {
+            interest_rate_model: InterestRateModel::Fixed,
+            fixed_borrow_rate: BANK_INTEREST_PRECISION / 20, // 5%
+            ..Bank::default()
+        };
+
+        let borrow_rate = calculate_borrow_rate(&bank, BANK_UTILIZATION_PRECISION / 10).unwrap();
+        assert_eq!(borrow_rate, BANK_INTEREST_PRECISION / 20);
+
+        let borrow_rate = calculate_borrow_rate(&bank, BANK_UTILIZATION_PRECISION).unwrap();
+        assert_eq!(borrow_rate, BANK_INTEREST_PRECISION / 20);
+    }
+
+    #[test]
+    fn reserve_factor_reduces_deposit_interest() {
+        let mut bank = Bank {
+            decimals: 6,
+            optimal_utilization: BANK_UTILIZATION_PRECISION / 2,
+            optimal_borrow_rate: BANK_INTEREST_PRECISION / 5,
+            max_borrow_rate: BANK_INTEREST_PRECISION,
+            deposit_balance: 100 * BANK_INTEREST_PRECISION,
+            borrow_balance: 50 * BANK_INTEREST_PRECISION,
+            cumulative_deposit_interest: BANK_CUMULATIVE_INTEREST_PRECISION,
+            cumulative_borrow_interest: BANK_CUMULATIVE_INTEREST_PRECISION,
+            ..Bank::default()
+        };
+        let now = ONE_YEAR as i64;
+
+        let interest = calculate_accumulated_interest(&bank, now).unwrap();
+
+        bank.reserve_factor = BANK_RESERVE_FACTOR_PRECISION / 10;
+        let interest_with_reserve = calculate_accumulated_interest(&bank, now).unwrap();
+
+        assert_eq!(
+            interest_with_reserve.borrow_interest,
+            interest.borrow_interest
+        );
+        assert_eq!(
+            interest_with_reserve.deposit_interest,
+            interest.deposit_interest * 9 / 10
+        );
+    }
+}
@@ -21,6 +21,8 @@ pub const BANK_UTILIZATION_PRECISION: u128 = 1_000_000; // expo = -6
 pub const LIQUIDATION_FEE_PRECISION: u128 = 1_000_000; // expo = -6
 pub const BANK_WEIGHT_PRECISION: u128 = 100; // expo = -2
 pub const BANK_IMF_PRECISION: u128 = 1_000_000; // expo = -6
+pub const BANK_RESERVE_FACTOR_PRECISION: u128 = 1_000_000; // expo = -6
+pub const TRAILING_STOP_PERCENTAGE_PRECISION: u128 = 1_000_000; // expo = -6
 
 // PRECISION CONVERSIONS
 pub const PRICE_TO_PEG_PRECISION_RATIO: u128 = MARK_PRICE_PRECISION / PEG_PRECISION; // expo: 7
@@ -60,12 +62,20 @@ pub const SHARE_OF_FEES_ALLOCATED_TO_CLEARING_HOUSE_NUMERATOR: u128 = 1;
 pub const SHARE_OF_FEES_ALLOCATED_TO_CLEARING_HOUSE_DENOMINATOR: u128 = 2;
 pub const UPDATE_K_ALLOWED_PRICE_CHANGE: u128 = MARK_PRICE_PRECISION / 10_000; //.0001
 
+// ORDERS
+pub const DEFAULT_ORDER_TICK_SIZE_DENOMINATOR: u128 = 10_000; // 1bp of the initial mark price
+pub const AUCTION_EXPONENTIAL_DECAY_HALF_LIVES: u128 = 4;
+pub const AUCTION_PRICE_CURVE_STEPS: u128 = 4;
+pub const AMM_JIT_INTENSITY_PRECISION: u128 = 100; // expo = -2
+
 // TIME PERIODS
 // pub const ONE_HOUR: i64 = 3600;
 pub const ONE_HOUR: i128 = 3600;
 pub const ONE_HOUR_I128: i128 = ONE_HOUR as i128;
 pub const TWENTY_FOUR_HOUR: i64 = 3600 * 24;
 pub const THIRTY_DAY_I128: i128 = (TWENTY_FOUR_HOUR * 30) as i128;
+pub const DEFAULT_INSURANCE_FUND_UNSTAKING_PERIOD: i64 = TWENTY_FOUR_HOUR * 13;
+pub const DEFAULT_INSURANCE_FUND_UNSTAKING_WINDOW: i64 = TWENTY_FOUR_HOUR * 2;
 pub const ONE_YEAR: u128 = 31536000;
 
 // FEES
@@ -101,6 +111,9 @@ pub const K_BPS_UPDATE_SCALE: i128 = 1_000_000; // expo = -6 (represents 100%)
                                                 // hardcoded scale bounds for a single k update (.1% increase and .09% decrease). scaled by market curve_update_intensity
 pub const K_BPS_DECREASE_MAX: i128 = 22000; // 2.2% decrease (25000/K_BPS_UPDATE_SCALE)
 pub const K_BPS_INCREASE_MAX: i128 = 1000; // 10 bps increase
+pub const K_BPS_DAILY_DECREASE_MAX: i128 = 50000; // 5% total rebalance_k decrease per day
+pub const K_BPS_DAILY_INCREASE_MAX: i128 = 10000; // 1% total rebalance_k increase per day
+pub const REBALANCE_K_PERIOD: i64 = 3600; // min seconds between rebalance_k updates
 
 pub const PEG_BPS_UPDATE_SCALE: u128 = 1_000_000; // expo = -6 (represents 100%)
                                                   // hardcoded scale bounds for a single repeg update. scaled by market curve_update_intensity
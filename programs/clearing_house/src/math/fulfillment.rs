@@ -1,22 +1,253 @@
+use crate::controller::position::PositionDirection;
 use crate::error::ClearingHouseResult;
 use crate::math::auction::is_auction_complete;
 use crate::state::fulfillment::FulfillmentMethod;
 use crate::state::user::Order;
 
+/// Interleaves the makers (maker index, maker price) and the amm so the taker fills against the
+/// best available price first. Ties go to the maker. While the auction is in progress the amm can
+/// only jit a share of what the makers leave, and only if the auction price is at or better than
+/// the amm's own price.
 pub fn determine_fulfillment_methods(
     taker_order: &Order,
-    maker_available: bool,
+    mut maker_prices: Vec<(usize, u128)>,
+    amm_price: u128,
+    auction_price: u128,
+    amm_jit_enabled: bool,
     slot: u64,
 ) -> ClearingHouseResult<Vec<FulfillmentMethod>> {
     let mut fulfillment_methods = vec![];
 
-    if maker_available {
-        fulfillment_methods.push(FulfillmentMethod::Match)
+    let can_fill_with_amm =
+        is_auction_complete(taker_order.slot, taker_order.auction_duration, slot)?;
+
+    match taker_order.direction {
+        PositionDirection::Long => maker_prices.sort_by(|a, b| a.1.cmp(&b.1)),
+        PositionDirection::Short => maker_prices.sort_by(|a, b| b.1.cmp(&a.1)),
     }
 
-    if is_auction_complete(taker_order.slot, taker_order.auction_duration, slot)? {
-        fulfillment_methods.push(FulfillmentMethod::AMM)
+    let mut amm_price = amm_price;
+    for (maker_index, maker_price) in maker_prices {
+        let amm_is_better = match taker_order.direction {
+            PositionDirection::Long => amm_price < maker_price,
+            PositionDirection::Short => amm_price > maker_price,
+        };
+
+        if can_fill_with_amm && amm_is_better {
+            fulfillment_methods.push(FulfillmentMethod::AMM(Some(maker_price)));
+            amm_price = maker_price;
+        }
+
+        fulfillment_methods.push(FulfillmentMethod::Match(maker_index));
+    }
+
+    if can_fill_with_amm {
+        fulfillment_methods.push(FulfillmentMethod::AMM(None));
+    } else if amm_jit_enabled {
+        let auction_price_better_for_amm = match taker_order.direction {
+            PositionDirection::Long => auction_price >= amm_price,
+            PositionDirection::Short => auction_price <= amm_price,
+        };
+
+        if auction_price_better_for_amm {
+            fulfillment_methods.push(FulfillmentMethod::AMMJit);
+        }
     }
 
     Ok(fulfillment_methods)
 }
+
+#[cfg(test)]
+mod test {
+    use crate::controller::position::PositionDirection;
+    use crate::math::constants::MARK_PRICE_PRECISION;
+    use crate::math::fulfillment::determine_fulfillment_methods;
+    use crate::state::fulfillment::FulfillmentMethod;
+    use crate::state::user::Order;
+
+    #[test]
+    fn no_makers_and_auction_in_progress() {
+        let taker_order = Order {
+            direction: PositionDirection::Long,
+            slot: 0,
+            auction_duration: 10,
+            ..Order::default()
+        };
+
+        let methods = determine_fulfillment_methods(
+            &taker_order,
+            vec![],
+            100 * MARK_PRICE_PRECISION,
+            100 * MARK_PRICE_PRECISION,
+            false,
+            5,
+        )
+        .unwrap();
+        assert_eq!(methods, vec![]);
+
+        let methods = determine_fulfillment_methods(
+            &taker_order,
+            vec![],
+            100 * MARK_PRICE_PRECISION,
+            100 * MARK_PRICE_PRECISION,
+            false,
+            11,
+        )
+        .unwrap();
+        assert_eq!(methods, vec![FulfillmentMethod::AMM(None)]);
+    }
+
+    #[test]
+    fn long_taker_interleaves_makers_and_amm() {
+        let taker_order = Order {
+            direction: PositionDirection::Long,
+            ..Order::default()
+        };
+
+        let maker_prices = vec![
+            (0, 103 * MARK_PRICE_PRECISION),
+            (1, 99 * MARK_PRICE_PRECISION),
+            (2, 101 * MARK_PRICE_PRECISION),
+        ];
+
+        let methods = determine_fulfillment_methods(
+            &taker_order,
+            maker_prices,
+            100 * MARK_PRICE_PRECISION,
+            100 * MARK_PRICE_PRECISION,
+            false,
+            0,
+        )
+        .unwrap();
+
+        assert_eq!(
+            methods,
+            vec![
+                FulfillmentMethod::Match(1),
+                FulfillmentMethod::AMM(Some(101 * MARK_PRICE_PRECISION)),
+                FulfillmentMethod::Match(2),
+                FulfillmentMethod::AMM(Some(103 * MARK_PRICE_PRECISION)),
+                FulfillmentMethod::Match(0),
+                FulfillmentMethod::AMM(None),
+            ]
+        );
+    }
+
+    #[test]
+    fn short_taker_during_auction_only_matches_makers() {
+        let taker_order = Order {
+            direction: PositionDirection::Short,
+            slot: 0,
+            auction_duration: 10,
+            ..Order::default()
+        };
+
+        let maker_prices = vec![
+            (0, 99 * MARK_PRICE_PRECISION),
+            (1, 101 * MARK_PRICE_PRECISION),
+        ];
+
+        let methods = determine_fulfillment_methods(
+            &taker_order,
+            maker_prices,
+            100 * MARK_PRICE_PRECISION,
+            100 * MARK_PRICE_PRECISION,
+            false,
+            5,
+        )
+        .unwrap();
+
+        assert_eq!(
+            methods,
+            vec![FulfillmentMethod::Match(1), FulfillmentMethod::Match(0)]
+        );
+    }
+
+    #[test]
+    fn amm_jits_behind_makers_during_auction() {
+        let taker_order = Order {
+            direction: PositionDirection::Long,
+            slot: 0,
+            auction_duration: 10,
+            ..Order::default()
+        };
+
+        let maker_prices = vec![(0, 101 * MARK_PRICE_PRECISION)];
+
+        let methods = determine_fulfillment_methods(
+            &taker_order,
+            maker_prices,
+            100 * MARK_PRICE_PRECISION,
+            102 * MARK_PRICE_PRECISION,
+            true,
+            5,
+        )
+        .unwrap();
+
+        assert_eq!(
+            methods,
+            vec![FulfillmentMethod::Match(0), FulfillmentMethod::AMMJit]
+        );
+
+        // auction complete, amm fills normally
+        let methods = determine_fulfillment_methods(
+            &taker_order,
+            vec![],
+            100 * MARK_PRICE_PRECISION,
+            102 * MARK_PRICE_PRECISION,
+            true,
+            11,
+        )
+        .unwrap();
+
+        assert_eq!(methods, vec![FulfillmentMethod::AMM(None)]);
+    }
+
+    #[test]
+    fn amm_doesnt_jit_when_auction_price_worse_than_amm() {
+        let long_taker_order = Order {
+            direction: PositionDirection::Long,
+            slot: 0,
+            auction_duration: 10,
+            ..Order::default()
+        };
+
+        let methods = determine_fulfillment_methods(
+            &long_taker_order,
+            vec![],
+            100 * MARK_PRICE_PRECISION,
+            99 * MARK_PRICE_PRECISION,
+            true,
+            5,
+        )
+        .unwrap();
+        assert_eq!(methods, vec![]);
+
+        let short_taker_order = Order {
+            direction: PositionDirection::Short,
+            ..long_taker_order
+        };
+
+        let methods = determine_fulfillment_methods(
+            &short_taker_order,
+            vec![],
+            100 * MARK_PRICE_PRECISION,
+            101 * MARK_PRICE_PRECISION,
+            true,
+            5,
+        )
+        .unwrap();
+        assert_eq!(methods, vec![]);
+
+        let methods = determine_fulfillment_methods(
+            &short_taker_order,
+            vec![],
+            100 * MARK_PRICE_PRECISION,
+            99 * MARK_PRICE_PRECISION,
+            true,
+            5,
+        )
+        .unwrap();
+        assert_eq!(methods, vec![FulfillmentMethod::AMMJit]);
+    }
+}
@@ -0,0 +1,119 @@
+use crate::error::ClearingHouseResult;
+use crate::math::casting::{cast_to_u128, cast_to_u64};
+use crate::math_error;
+use solana_program::msg;
+use std::cmp::min;
+
+pub fn vault_amount_to_if_shares(
+    amount: u64,
+    total_if_shares: u128,
+    insurance_fund_vault_balance: u64,
+) -> ClearingHouseResult<u128> {
+    // relies on vault + total shares being non-zero, shares are 1:1 with tokens otherwise
+    if total_if_shares == 0 || insurance_fund_vault_balance == 0 {
+        return cast_to_u128(amount);
+    }
+
+    cast_to_u128(amount)?
+        .checked_mul(total_if_shares)
+        .ok_or_else(math_error!())?
+        .checked_div(cast_to_u128(insurance_fund_vault_balance)?)
+        .ok_or_else(math_error!())
+}
+
+pub fn if_shares_to_vault_amount(
+    if_shares: u128,
+    total_if_shares: u128,
+    insurance_fund_vault_balance: u64,
+) -> ClearingHouseResult<u64> {
+    if total_if_shares == 0 {
+        return Ok(0);
+    }
+
+    cast_to_u64(
+        if_shares
+            .checked_mul(cast_to_u128(insurance_fund_vault_balance)?)
+            .ok_or_else(math_error!())?
+            .checked_div(total_if_shares)
+            .ok_or_else(math_error!())?,
+    )
+}
+
+pub fn calculate_if_shares_to_burn_for_withdrawal(
+    amount: u64,
+    total_if_shares: u128,
+    insurance_fund_vault_balance: u64,
+) -> ClearingHouseResult<u128> {
+    if total_if_shares == 0 || insurance_fund_vault_balance == 0 {
+        return Ok(0);
+    }
+
+    // round up so withdrawals cant take more than their shares are worth
+    let vault_balance = cast_to_u128(insurance_fund_vault_balance)?;
+    cast_to_u128(amount)?
+        .checked_mul(total_if_shares)
+        .ok_or_else(math_error!())?
+        .checked_add(vault_balance)
+        .ok_or_else(math_error!())?
+        .checked_sub(1)
+        .ok_or_else(math_error!())?
+        .checked_div(vault_balance)
+        .ok_or_else(math_error!())
+}
+
+pub fn calculate_if_bankruptcy_payment(
+    loss: u128,
+    insurance_fund_vault_balance: u64,
+) -> ClearingHouseResult<u128> {
+    Ok(min(loss, cast_to_u128(insurance_fund_vault_balance)?))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::math::insurance::{
+        calculate_if_bankruptcy_payment, calculate_if_shares_to_burn_for_withdrawal,
+        if_shares_to_vault_amount, vault_amount_to_if_shares,
+    };
+
+    #[test]
+    fn first_stake_is_one_to_one() {
+        let shares = vault_amount_to_if_shares(100, 0, 0).unwrap();
+        assert_eq!(shares, 100);
+
+        let amount = if_shares_to_vault_amount(shares, shares, 100).unwrap();
+        assert_eq!(amount, 100);
+    }
+
+    #[test]
+    fn shares_accrue_revenue() {
+        // 100 shares backed by 150 tokens after revenue
+        let shares = vault_amount_to_if_shares(300, 100, 150).unwrap();
+        assert_eq!(shares, 200);
+
+        let amount = if_shares_to_vault_amount(100, 300, 450).unwrap();
+        assert_eq!(amount, 150);
+    }
+
+    #[test]
+    fn shares_absorb_losses() {
+        // 200 shares backed by 100 tokens after bankruptcy payment
+        let amount = if_shares_to_vault_amount(50, 200, 100).unwrap();
+        assert_eq!(amount, 25);
+    }
+
+    #[test]
+    fn withdrawal_burns_rounded_up_shares() {
+        let shares = calculate_if_shares_to_burn_for_withdrawal(10, 100, 30).unwrap();
+        assert_eq!(shares, 34);
+
+        let shares = calculate_if_shares_to_burn_for_withdrawal(10, 100, 0).unwrap();
+        assert_eq!(shares, 0);
+    }
+
+    #[test]
+    fn bankruptcy_payment_capped_by_vault() {
+        assert_eq!(calculate_if_bankruptcy_payment(100, 1000).unwrap(), 100);
+        assert_eq!(calculate_if_bankruptcy_payment(1000, 100).unwrap(), 100);
+        assert_eq!(calculate_if_bankruptcy_payment(1000, 0).unwrap(), 0);
+    }
+}
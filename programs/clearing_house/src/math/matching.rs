@@ -2,13 +2,19 @@ use crate::controller::position::PositionDirection;
 use crate::error::{ClearingHouseResult, ErrorCode};
 use crate::math::constants::MARK_PRICE_TIMES_AMM_TO_QUOTE_PRECISION_RATIO;
 use crate::math_error;
-use crate::state::user::Order;
+use crate::state::user::{Order, SelfTradePrevention};
 use solana_program::msg;
 use std::cmp::min;
 
-pub fn is_maker_for_taker(maker_order: &Order, taker_order: &Order) -> ClearingHouseResult<bool> {
+pub fn is_maker_for_taker(
+    maker_order: &Order,
+    taker_order: &Order,
+    is_self_trade: bool,
+) -> ClearingHouseResult<bool> {
     if taker_order.post_only {
         Err(ErrorCode::CantMatchTwoPostOnlys)
+    } else if is_self_trade && taker_order.self_trade_prevention != SelfTradePrevention::None {
+        Ok(false)
     } else if maker_order.post_only && !taker_order.post_only {
         Ok(true)
     } else {
@@ -16,6 +22,35 @@ pub fn is_maker_for_taker(maker_order: &Order, taker_order: &Order) -> ClearingH
     }
 }
 
+/// Returns the base asset amount to remove from the maker and taker orders.
+/// Removing an order's entire unfilled amount cancels it.
+pub fn calculate_self_trade_prevention_decrements(
+    maker_order: &Order,
+    taker_order: &Order,
+) -> ClearingHouseResult<(u128, u128)> {
+    let maker_base_asset_amount_unfilled = maker_order.get_base_asset_amount_unfilled()?;
+    let taker_base_asset_amount_unfilled = taker_order.get_base_asset_amount_unfilled()?;
+
+    let decrements = match taker_order.self_trade_prevention {
+        SelfTradePrevention::None => (0, 0),
+        SelfTradePrevention::CancelTaker => (0, taker_base_asset_amount_unfilled),
+        SelfTradePrevention::CancelMaker => (maker_base_asset_amount_unfilled, 0),
+        SelfTradePrevention::CancelBoth => (
+            maker_base_asset_amount_unfilled,
+            taker_base_asset_amount_unfilled,
+        ),
+        SelfTradePrevention::DecrementAndCancel => {
+            let decrement = min(
+                maker_base_asset_amount_unfilled,
+                taker_base_asset_amount_unfilled,
+            );
+            (decrement, decrement)
+        }
+    };
+
+    Ok(decrements)
+}
+
 pub fn are_orders_same_market_but_different_sides(
     maker_order: &Order,
     taker_order: &Order,
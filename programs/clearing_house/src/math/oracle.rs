@@ -1,8 +1,13 @@
 use crate::error::ClearingHouseResult;
 use crate::math::amm;
+use crate::math::casting::cast_to_u128;
+use crate::math::constants::BID_ASK_SPREAD_PRECISION;
+use crate::math_error;
 use crate::state::market::AMM;
 use crate::state::oracle::OraclePriceData;
-use crate::state::state::OracleGuardRails;
+use crate::state::state::{OracleGuardRails, ValidityGuardRails};
+use solana_program::msg;
+use std::cmp::max;
 
 pub fn block_operation(
     amm: &AMM,
@@ -49,6 +54,48 @@ pub fn get_oracle_status<'a>(
     })
 }
 
+/// Validity for an oracle that doesn't back the order's market, so there's no amm twap to compare to
+pub fn is_trigger_oracle_valid(
+    oracle_price_data: &OraclePriceData,
+    valid_oracle_guard_rails: &ValidityGuardRails,
+) -> ClearingHouseResult<bool> {
+    let OraclePriceData {
+        price: oracle_price,
+        confidence: oracle_conf,
+        delay: oracle_delay,
+        has_sufficient_number_of_data_points,
+    } = *oracle_price_data;
+
+    if oracle_price <= 0 {
+        msg!("Invalid Trigger Oracle: Non-positive (oracle_price <=0)");
+        return Ok(false);
+    }
+
+    let conf_pct_of_price = max(1, oracle_conf)
+        .checked_mul(BID_ASK_SPREAD_PRECISION)
+        .ok_or_else(math_error!())?
+        .checked_div(cast_to_u128(oracle_price)?)
+        .ok_or_else(math_error!())?;
+    let is_conf_too_large =
+        conf_pct_of_price > valid_oracle_guard_rails.confidence_interval_max_size;
+    if is_conf_too_large {
+        msg!(
+            "Invalid Trigger Oracle: Confidence Too Large (is_conf_too_large={:?})",
+            conf_pct_of_price
+        );
+    }
+
+    let is_stale = oracle_delay > valid_oracle_guard_rails.slots_before_stale;
+    if is_stale {
+        msg!(
+            "Invalid Trigger Oracle: Stale (oracle_delay={:?})",
+            oracle_delay
+        );
+    }
+
+    Ok(!(is_stale || is_conf_too_large || !has_sufficient_number_of_data_points))
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -143,4 +190,43 @@ mod test {
         assert!(oracle_status.mark_too_divergent);
         assert!(!oracle_status.is_valid);
     }
+
+    #[test]
+    fn trigger_oracle_valid() {
+        let state = State {
+            oracle_guard_rails: OracleGuardRails {
+                validity: ValidityGuardRails {
+                    slots_before_stale: 10,
+                    confidence_interval_max_size: 4000,
+                    too_volatile_ratio: 5,
+                },
+                ..OracleGuardRails::default()
+            },
+            ..State::default()
+        };
+
+        let mut oracle_price_data = OraclePriceData {
+            price: (20000 * MARK_PRICE_PRECISION) as i128,
+            confidence: MARK_PRICE_PRECISION,
+            delay: 1,
+            has_sufficient_number_of_data_points: true,
+        };
+        assert!(
+            is_trigger_oracle_valid(&oracle_price_data, &state.oracle_guard_rails.validity)
+                .unwrap()
+        );
+
+        oracle_price_data.delay = 11;
+        assert!(
+            !is_trigger_oracle_valid(&oracle_price_data, &state.oracle_guard_rails.validity)
+                .unwrap()
+        );
+
+        oracle_price_data.delay = 1;
+        oracle_price_data.confidence = 100 * MARK_PRICE_PRECISION;
+        assert!(
+            !is_trigger_oracle_valid(&oracle_price_data, &state.oracle_guard_rails.validity)
+                .unwrap()
+        );
+    }
 }
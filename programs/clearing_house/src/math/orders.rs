@@ -1,4 +1,4 @@
-use std::cmp::min;
+use std::cmp::{max, min};
 use std::ops::Div;
 
 use solana_program::msg;
@@ -8,31 +8,93 @@ use crate::controller::position::PositionDelta;
 use crate::controller::position::PositionDirection;
 use crate::error::ClearingHouseResult;
 use crate::math;
-use crate::math::amm::calculate_max_base_asset_amount_fillable;
+use crate::math::amm::{
+    calculate_max_base_asset_amount_fillable, calculate_max_base_asset_amount_for_inventory,
+    calculate_swap_output, get_spread_reserves,
+};
 use crate::math::auction::is_auction_complete;
 use crate::math::casting::{cast, cast_to_i128};
-use crate::math::constants::{MARGIN_PRECISION, MARK_PRICE_TIMES_AMM_TO_QUOTE_PRECISION_RATIO};
+use crate::math::constants::{
+    AMM_JIT_INTENSITY_PRECISION, MARGIN_PRECISION, MARK_PRICE_TIMES_AMM_TO_QUOTE_PRECISION_RATIO,
+    TRAILING_STOP_PERCENTAGE_PRECISION,
+};
 use crate::math::position::calculate_entry_price;
+use crate::math::quote_asset::asset_to_reserve_amount;
 use crate::math_error;
-use crate::state::market::Market;
-use crate::state::user::{Order, OrderStatus, OrderTriggerCondition, OrderType, User};
+use crate::state::market::{Market, AMM};
+use crate::state::user::{
+    Order, OrderStatus, OrderTriggerCondition, OrderType, TrailingStopOffsetType, User,
+};
 
 pub fn calculate_base_asset_amount_for_amm_to_fulfill(
     order: &Order,
     market: &Market,
     valid_oracle_price: Option<i128>,
     slot: u64,
+    override_limit_price: Option<u128>,
 ) -> ClearingHouseResult<u128> {
     if order.must_be_triggered() && !order.triggered {
         return Ok(0);
     }
 
     let limit_price = order.get_limit_price(&market.amm, valid_oracle_price, slot)?;
+    // stop the amm at the next maker's price when filling against multiple makers
+    let limit_price = match (override_limit_price, order.direction) {
+        (Some(override_limit_price), PositionDirection::Long) => {
+            min(limit_price, override_limit_price)
+        }
+        (Some(override_limit_price), PositionDirection::Short) => {
+            max(limit_price, override_limit_price)
+        }
+        (None, _) => limit_price,
+    };
     let base_asset_amount =
         calculate_base_asset_amount_to_fill_up_to_limit_price(order, market, limit_price)?;
+
+    cap_base_asset_amount_for_amm(base_asset_amount, order.direction, market)
+}
+
+/// The amm only jits a share (amm_jit_intensity) of the order's unfilled size and never past the
+/// point where its own price would be worse than the auction price
+pub fn calculate_base_asset_amount_for_amm_jit(
+    order: &Order,
+    market: &Market,
+    valid_oracle_price: Option<i128>,
+    slot: u64,
+) -> ClearingHouseResult<u128> {
+    if market.amm.amm_jit_intensity == 0 || (order.must_be_triggered() && !order.triggered) {
+        return Ok(0);
+    }
+
+    let auction_price = order.get_limit_price(&market.amm, valid_oracle_price, slot)?;
+    let base_asset_amount =
+        calculate_base_asset_amount_to_fill_up_to_limit_price(order, market, auction_price)?;
+
+    let max_jit_base_asset_amount = standardize_base_asset_amount(
+        order
+            .get_base_asset_amount_unfilled()?
+            .checked_mul(market.amm.amm_jit_intensity as u128)
+            .ok_or_else(math_error!())?
+            .checked_div(AMM_JIT_INTENSITY_PRECISION)
+            .ok_or_else(math_error!())?,
+        market.amm.base_asset_amount_step_size,
+    )?;
+
+    cap_base_asset_amount_for_amm(
+        min(base_asset_amount, max_jit_base_asset_amount),
+        order.direction,
+        market,
+    )
+}
+
+fn cap_base_asset_amount_for_amm(
+    base_asset_amount: u128,
+    direction: PositionDirection,
+    market: &Market,
+) -> ClearingHouseResult<u128> {
     let max_base_asset_amount = calculate_max_base_asset_amount_fillable(&market.amm)?;
 
-    let max_base_asset_amount_on_side = match order.direction {
+    let max_base_asset_amount_on_side = match direction {
         PositionDirection::Long => market
             .amm
             .base_asset_reserve
@@ -43,8 +105,14 @@ pub fn calculate_base_asset_amount_for_amm_to_fulfill(
             .saturating_sub(market.amm.base_asset_reserve),
     };
 
+    let max_base_asset_amount_for_inventory =
+        calculate_max_base_asset_amount_for_inventory(&market.amm, direction)?;
+
     let base_asset_amount = min(
-        max_base_asset_amount_on_side,
+        min(
+            max_base_asset_amount_on_side,
+            max_base_asset_amount_for_inventory,
+        ),
         min(base_asset_amount, max_base_asset_amount),
     );
 
@@ -56,7 +124,7 @@ pub fn calculate_base_asset_amount_to_fill_up_to_limit_price(
     market: &Market,
     limit_price: u128,
 ) -> ClearingHouseResult<u128> {
-    let base_asset_amount_unfilled = order.get_base_asset_amount_unfilled()?;
+    let base_asset_amount_unfilled = order.get_visible_base_asset_amount_unfilled()?;
 
     let (max_trade_base_asset_amount, max_trade_direction) =
         math::amm::calculate_base_asset_amount_to_trade_to_price(
@@ -285,9 +353,422 @@ pub fn order_satisfies_trigger_condition(order: &Order, oracle_price: u128) -> b
     }
 }
 
+/// Trailing stop trigger price only ratchets in the position's favor:
+/// up for a short (closing a long), down for a long (closing a short)
+pub fn calculate_trailing_stop_trigger_price(
+    order: &Order,
+    oracle_price: u128,
+) -> ClearingHouseResult<u128> {
+    let offset = match order.trailing_stop_offset_type {
+        TrailingStopOffsetType::Price => order.trailing_stop_offset,
+        TrailingStopOffsetType::Percentage => oracle_price
+            .checked_mul(order.trailing_stop_offset)
+            .ok_or_else(math_error!())?
+            .checked_div(TRAILING_STOP_PERCENTAGE_PRECISION)
+            .ok_or_else(math_error!())?,
+    };
+
+    let trigger_price = match order.direction {
+        PositionDirection::Short => max(order.trigger_price, oracle_price.saturating_sub(offset)),
+        PositionDirection::Long => {
+            let trigger_price = oracle_price.checked_add(offset).ok_or_else(math_error!())?;
+            if order.trigger_price == 0 {
+                trigger_price
+            } else {
+                min(order.trigger_price, trigger_price)
+            }
+        }
+    };
+
+    Ok(trigger_price)
+}
+
+/// Twap orders release an equal slice of the parent order every twap_slot_interval slots
+pub fn calculate_twap_slices_released(order: &Order, slot: u64) -> ClearingHouseResult<u64> {
+    let slots_elapsed = slot
+        .checked_sub(order.twap_start_slot)
+        .ok_or_else(math_error!())?;
+
+    let slices_released = slots_elapsed
+        .checked_div(order.twap_slot_interval)
+        .ok_or_else(math_error!())?
+        .checked_add(1)
+        .ok_or_else(math_error!())?;
+
+    Ok(min(slices_released, order.twap_slices as u64))
+}
+
+pub fn calculate_twap_base_asset_amount_released(
+    order: &Order,
+    slot: u64,
+) -> ClearingHouseResult<u128> {
+    let slices_released = calculate_twap_slices_released(order, slot)?;
+
+    order
+        .base_asset_amount
+        .checked_mul(slices_released as u128)
+        .ok_or_else(math_error!())?
+        .checked_div(order.twap_slices as u128)
+        .ok_or_else(math_error!())
+}
+
+/// Converts a quote asset amount into the standardized base asset amount the amm would swap for it
+/// at its bid/ask, the same reserves a fill executes against
+pub fn calculate_base_asset_amount_for_quote_asset_amount(
+    quote_asset_amount: u128,
+    direction: PositionDirection,
+    amm: &AMM,
+) -> ClearingHouseResult<u128> {
+    let quote_asset_reserve_amount =
+        asset_to_reserve_amount(quote_asset_amount, amm.peg_multiplier)?;
+
+    let swap_direction = match direction {
+        PositionDirection::Long => SwapDirection::Add,
+        PositionDirection::Short => SwapDirection::Remove,
+    };
+
+    let (base_asset_reserve_with_spread, quote_asset_reserve_with_spread) =
+        get_spread_reserves(amm, direction)?;
+
+    let (new_base_asset_reserve, _) = calculate_swap_output(
+        quote_asset_reserve_amount,
+        quote_asset_reserve_with_spread,
+        swap_direction,
+        amm.sqrt_k,
+    )?;
+
+    let base_asset_amount = cast_to_i128(base_asset_reserve_with_spread)?
+        .checked_sub(cast_to_i128(new_base_asset_reserve)?)
+        .ok_or_else(math_error!())?
+        .unsigned_abs();
+
+    standardize_base_asset_amount(base_asset_amount, amm.base_asset_amount_step_size)
+}
+
+/// Converts a quote asset amount into the standardized base asset amount it buys at price, which is
+/// how limit orders fill
+pub fn calculate_base_asset_amount_for_quote_asset_amount_at_price(
+    quote_asset_amount: u128,
+    price: u128,
+    base_asset_amount_step_size: u128,
+) -> ClearingHouseResult<u128> {
+    let base_asset_amount = quote_asset_amount
+        .checked_mul(MARK_PRICE_TIMES_AMM_TO_QUOTE_PRECISION_RATIO)
+        .ok_or_else(math_error!())?
+        .checked_div(price)
+        .ok_or_else(math_error!())?;
+
+    standardize_base_asset_amount(base_asset_amount, base_asset_amount_step_size)
+}
+
+/// Returns the first price on the market's tick grid inside the amm bid/ask if a post-only order at
+/// limit_price would cross it
+pub fn calculate_post_only_slide_price(
+    direction: PositionDirection,
+    limit_price: u128,
+    amm: &AMM,
+) -> ClearingHouseResult<Option<u128>> {
+    let mark_price = amm.mark_price()?;
+    let tick_size = amm.get_order_tick_size()?;
+
+    let slide_price = match direction {
+        PositionDirection::Long => {
+            let ask_price = amm.ask_price(mark_price)?;
+            if limit_price < ask_price {
+                return Ok(None);
+            }
+
+            ask_price
+                .checked_sub(1)
+                .ok_or_else(math_error!())?
+                .checked_div(tick_size)
+                .ok_or_else(math_error!())?
+                .checked_mul(tick_size)
+                .ok_or_else(math_error!())?
+        }
+        PositionDirection::Short => {
+            let bid_price = amm.bid_price(mark_price)?;
+            if limit_price > bid_price {
+                return Ok(None);
+            }
+
+            bid_price
+                .checked_div(tick_size)
+                .ok_or_else(math_error!())?
+                .checked_add(1)
+                .ok_or_else(math_error!())?
+                .checked_mul(tick_size)
+                .ok_or_else(math_error!())?
+        }
+    };
+
+    Ok(Some(slide_price))
+}
+
 #[cfg(test)]
 mod test {
 
+    pub mod calculate_base_asset_amount_for_quote_asset_amount {
+        use crate::controller::amm::update_spreads;
+        use crate::controller::position::PositionDirection;
+        use crate::math::constants::{
+            AMM_RESERVE_PRECISION, BASE_PRECISION, PEG_PRECISION, QUOTE_PRECISION,
+        };
+        use crate::math::orders::calculate_base_asset_amount_for_quote_asset_amount;
+        use crate::state::market::AMM;
+
+        #[test]
+        fn converts_quote_at_amm_price() {
+            let mut amm = AMM {
+                base_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                quote_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                sqrt_k: 100 * AMM_RESERVE_PRECISION,
+                peg_multiplier: 100 * PEG_PRECISION,
+                base_asset_amount_step_size: BASE_PRECISION / 1000,
+                ..AMM::default()
+            };
+            let mark_price = amm.mark_price().unwrap();
+            update_spreads(&mut amm, mark_price).unwrap();
+
+            // $100 buys slightly less than 1 base at a price of $100 because of slippage
+            let base_asset_amount = calculate_base_asset_amount_for_quote_asset_amount(
+                100 * QUOTE_PRECISION,
+                PositionDirection::Long,
+                &amm,
+            )
+            .unwrap();
+            assert_eq!(base_asset_amount, 990 * BASE_PRECISION / 1000);
+
+            let base_asset_amount = calculate_base_asset_amount_for_quote_asset_amount(
+                100 * QUOTE_PRECISION,
+                PositionDirection::Short,
+                &amm,
+            )
+            .unwrap();
+            assert_eq!(base_asset_amount, 1010 * BASE_PRECISION / 1000);
+        }
+
+        #[test]
+        fn converts_quote_at_amm_bid_ask() {
+            let mut amm = AMM {
+                base_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                quote_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                sqrt_k: 100 * AMM_RESERVE_PRECISION,
+                peg_multiplier: 100 * PEG_PRECISION,
+                base_asset_amount_step_size: BASE_PRECISION / 1000,
+                base_spread: 20_000,
+                ..AMM::default()
+            };
+            let mark_price = amm.mark_price().unwrap();
+            update_spreads(&mut amm, mark_price).unwrap();
+
+            // $100 buys less base at the ask than at mark
+            let base_asset_amount = calculate_base_asset_amount_for_quote_asset_amount(
+                100 * QUOTE_PRECISION,
+                PositionDirection::Long,
+                &amm,
+            )
+            .unwrap();
+            assert_eq!(base_asset_amount, 980 * BASE_PRECISION / 1000);
+
+            // and takes more base to raise at the bid
+            let base_asset_amount = calculate_base_asset_amount_for_quote_asset_amount(
+                100 * QUOTE_PRECISION,
+                PositionDirection::Short,
+                &amm,
+            )
+            .unwrap();
+            assert_eq!(base_asset_amount, 1020 * BASE_PRECISION / 1000);
+        }
+    }
+
+    pub mod calculate_post_only_slide_price {
+        use crate::controller::position::PositionDirection;
+        use crate::math::constants::{AMM_RESERVE_PRECISION, MARK_PRICE_PRECISION, PEG_PRECISION};
+        use crate::math::orders::calculate_post_only_slide_price;
+        use crate::state::market::AMM;
+
+        fn get_amm() -> AMM {
+            AMM {
+                base_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                quote_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                sqrt_k: 100 * AMM_RESERVE_PRECISION,
+                peg_multiplier: 100 * PEG_PRECISION,
+                long_spread: 1000,
+                short_spread: 1000,
+                order_tick_size: MARK_PRICE_PRECISION / 100,
+                ..AMM::default()
+            }
+        }
+
+        #[test]
+        fn crossing_orders_slide_inside_amm() {
+            let amm = get_amm();
+
+            let slide_price = calculate_post_only_slide_price(
+                PositionDirection::Long,
+                101 * MARK_PRICE_PRECISION,
+                &amm,
+            )
+            .unwrap();
+            assert_eq!(
+                slide_price,
+                Some(1001 * MARK_PRICE_PRECISION / 10 - MARK_PRICE_PRECISION / 100)
+            );
+
+            let slide_price = calculate_post_only_slide_price(
+                PositionDirection::Short,
+                99 * MARK_PRICE_PRECISION,
+                &amm,
+            )
+            .unwrap();
+            assert_eq!(
+                slide_price,
+                Some(999 * MARK_PRICE_PRECISION / 10 + MARK_PRICE_PRECISION / 100)
+            );
+        }
+
+        #[test]
+        fn slide_uses_each_markets_tick_size() {
+            // $0.001 market with a 1bp tick, a global $0.0001 tick would slide below the bid
+            let cheap_amm = AMM {
+                peg_multiplier: PEG_PRECISION / 1000,
+                order_tick_size: MARK_PRICE_PRECISION / 10_000_000,
+                ..get_amm()
+            };
+            let cheap_ask = 1001 * MARK_PRICE_PRECISION / 1_000_000;
+            let cheap_bid = 999 * MARK_PRICE_PRECISION / 1_000_000;
+
+            let slide_price =
+                calculate_post_only_slide_price(PositionDirection::Long, cheap_ask, &cheap_amm)
+                    .unwrap()
+                    .unwrap();
+            assert_eq!(slide_price, cheap_ask - cheap_amm.order_tick_size);
+            assert!(slide_price > cheap_bid);
+
+            // $50,000 market with a $1 tick, the slide stays on the tick grid strictly inside
+            let expensive_amm = AMM {
+                peg_multiplier: 50_000 * PEG_PRECISION,
+                long_spread: 1234,
+                short_spread: 1234,
+                order_tick_size: MARK_PRICE_PRECISION,
+                ..get_amm()
+            };
+            let mark_price = expensive_amm.mark_price().unwrap();
+            let expensive_ask = expensive_amm.ask_price(mark_price).unwrap();
+            let expensive_bid = expensive_amm.bid_price(mark_price).unwrap();
+
+            let slide_price = calculate_post_only_slide_price(
+                PositionDirection::Long,
+                expensive_ask,
+                &expensive_amm,
+            )
+            .unwrap()
+            .unwrap();
+            assert_eq!(slide_price, 50_061 * MARK_PRICE_PRECISION);
+            assert!(slide_price < expensive_ask);
+
+            let slide_price = calculate_post_only_slide_price(
+                PositionDirection::Short,
+                expensive_bid,
+                &expensive_amm,
+            )
+            .unwrap()
+            .unwrap();
+            assert_eq!(slide_price, 49_939 * MARK_PRICE_PRECISION);
+            assert!(slide_price > expensive_bid);
+        }
+
+        #[test]
+        fn unset_tick_size_defaults_to_one_bp_of_peg() {
+            let amm = AMM {
+                order_tick_size: 0,
+                ..get_amm()
+            };
+            assert_eq!(
+                amm.get_order_tick_size().unwrap(),
+                MARK_PRICE_PRECISION / 100
+            );
+
+            let slide_price = calculate_post_only_slide_price(
+                PositionDirection::Long,
+                101 * MARK_PRICE_PRECISION,
+                &amm,
+            )
+            .unwrap();
+            assert_eq!(
+                slide_price,
+                Some(1001 * MARK_PRICE_PRECISION / 10 - MARK_PRICE_PRECISION / 100)
+            );
+        }
+
+        #[test]
+        fn resting_orders_dont_slide() {
+            let amm = get_amm();
+
+            let slide_price = calculate_post_only_slide_price(
+                PositionDirection::Long,
+                100 * MARK_PRICE_PRECISION,
+                &amm,
+            )
+            .unwrap();
+            assert_eq!(slide_price, None);
+
+            let slide_price = calculate_post_only_slide_price(
+                PositionDirection::Short,
+                100 * MARK_PRICE_PRECISION,
+                &amm,
+            )
+            .unwrap();
+            assert_eq!(slide_price, None);
+        }
+    }
+
+    pub mod calculate_twap_base_asset_amount_released {
+        use crate::math::constants::BASE_PRECISION;
+        use crate::math::orders::{
+            calculate_twap_base_asset_amount_released, calculate_twap_slices_released,
+        };
+        use crate::state::user::{Order, OrderType};
+
+        #[test]
+        fn releases_one_slice_per_interval() {
+            let order = Order {
+                order_type: OrderType::Twap,
+                base_asset_amount: 4 * BASE_PRECISION,
+                twap_slices: 4,
+                twap_slot_interval: 10,
+                twap_start_slot: 5,
+                ..Order::default()
+            };
+
+            assert_eq!(calculate_twap_slices_released(&order, 5).unwrap(), 1);
+            assert_eq!(calculate_twap_slices_released(&order, 14).unwrap(), 1);
+            assert_eq!(calculate_twap_slices_released(&order, 15).unwrap(), 2);
+            assert_eq!(
+                calculate_twap_base_asset_amount_released(&order, 25).unwrap(),
+                3 * BASE_PRECISION
+            );
+        }
+
+        #[test]
+        fn caps_at_parent_order_size() {
+            let order = Order {
+                order_type: OrderType::Twap,
+                base_asset_amount: 4 * BASE_PRECISION,
+                twap_slices: 4,
+                twap_slot_interval: 10,
+                ..Order::default()
+            };
+
+            assert_eq!(calculate_twap_slices_released(&order, 1000).unwrap(), 4);
+            assert_eq!(
+                calculate_twap_base_asset_amount_released(&order, 1000).unwrap(),
+                4 * BASE_PRECISION
+            );
+        }
+    }
+
     pub mod standardize_base_asset_amount_with_remainder_i128 {
         use crate::math::orders::standardize_base_asset_amount_with_remainder_i128;
 
@@ -398,4 +879,64 @@ mod test {
             assert_eq!(result, 0);
         }
     }
+
+    pub mod calculate_trailing_stop_trigger_price {
+        use crate::controller::position::PositionDirection;
+        use crate::math::constants::{MARK_PRICE_PRECISION, TRAILING_STOP_PERCENTAGE_PRECISION};
+        use crate::math::orders::calculate_trailing_stop_trigger_price;
+        use crate::state::user::{Order, OrderType, TrailingStopOffsetType};
+
+        #[test]
+        fn short_ratchets_up() {
+            let order = Order {
+                order_type: OrderType::TrailingStop,
+                direction: PositionDirection::Short,
+                trigger_price: 95 * MARK_PRICE_PRECISION,
+                trailing_stop_offset: 5 * MARK_PRICE_PRECISION,
+                ..Order::default()
+            };
+
+            let trigger_price =
+                calculate_trailing_stop_trigger_price(&order, 110 * MARK_PRICE_PRECISION).unwrap();
+            assert_eq!(trigger_price, 105 * MARK_PRICE_PRECISION);
+
+            let trigger_price =
+                calculate_trailing_stop_trigger_price(&order, 90 * MARK_PRICE_PRECISION).unwrap();
+            assert_eq!(trigger_price, 95 * MARK_PRICE_PRECISION);
+        }
+
+        #[test]
+        fn long_ratchets_down() {
+            let order = Order {
+                order_type: OrderType::TrailingStop,
+                direction: PositionDirection::Long,
+                trigger_price: 105 * MARK_PRICE_PRECISION,
+                trailing_stop_offset: 5 * MARK_PRICE_PRECISION,
+                ..Order::default()
+            };
+
+            let trigger_price =
+                calculate_trailing_stop_trigger_price(&order, 90 * MARK_PRICE_PRECISION).unwrap();
+            assert_eq!(trigger_price, 95 * MARK_PRICE_PRECISION);
+
+            let trigger_price =
+                calculate_trailing_stop_trigger_price(&order, 110 * MARK_PRICE_PRECISION).unwrap();
+            assert_eq!(trigger_price, 105 * MARK_PRICE_PRECISION);
+        }
+
+        #[test]
+        fn percentage_offset() {
+            let order = Order {
+                order_type: OrderType::TrailingStop,
+                direction: PositionDirection::Short,
+                trailing_stop_offset: TRAILING_STOP_PERCENTAGE_PRECISION / 10,
+                trailing_stop_offset_type: TrailingStopOffsetType::Percentage,
+                ..Order::default()
+            };
+
+            let trigger_price =
+                calculate_trailing_stop_trigger_price(&order, 100 * MARK_PRICE_PRECISION).unwrap();
+            assert_eq!(trigger_price, 90 * MARK_PRICE_PRECISION);
+        }
+    }
 }
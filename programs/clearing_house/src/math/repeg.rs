@@ -5,8 +5,8 @@ use crate::math::bn;
 use crate::math::casting::{cast_to_i128, cast_to_u128};
 use crate::math::constants::{
     AMM_TIMES_PEG_TO_QUOTE_PRECISION_RATIO_I128, AMM_TO_QUOTE_PRECISION_RATIO_I128,
-    BID_ASK_SPREAD_PRECISION, MARK_PRICE_PRECISION_I128, ONE_HOUR, PRICE_TO_PEG_PRECISION_RATIO,
-    SHARE_OF_FEES_ALLOCATED_TO_CLEARING_HOUSE_DENOMINATOR,
+    BID_ASK_SPREAD_PRECISION, MARK_PRICE_PRECISION_I128, ONE_HOUR, PEG_BPS_UPDATE_SCALE,
+    PRICE_TO_PEG_PRECISION_RATIO, SHARE_OF_FEES_ALLOCATED_TO_CLEARING_HOUSE_DENOMINATOR,
     SHARE_OF_FEES_ALLOCATED_TO_CLEARING_HOUSE_NUMERATOR, TWENTY_FOUR_HOUR,
 };
 use crate::math::position::_calculate_base_asset_value_and_pnl;
@@ -485,6 +485,74 @@ pub fn calculate_optimal_peg_and_budget(
     Ok((optimal_peg, fee_budget, check_lower_bound))
 }
 
+/// Share of the fee pool an oracle pegged market can spend re-centering on the oracle,
+/// accrued per slot since the last peg update
+pub fn calculate_oracle_peg_budget(
+    market: &Market,
+    slots_elapsed: u64,
+) -> ClearingHouseResult<u128> {
+    let fee_pool = calculate_fee_pool(market)?;
+
+    let budget = fee_pool
+        .checked_mul(market.amm.oracle_peg_slot_budget_pct as u128)
+        .ok_or_else(math_error!())?
+        .checked_mul(slots_elapsed as u128)
+        .ok_or_else(math_error!())?
+        .checked_div(PEG_BPS_UPDATE_SCALE)
+        .ok_or_else(math_error!())?;
+
+    Ok(min(budget, fee_pool))
+}
+
+/// Moves the peg toward the oracle price, as far as the budget allows
+pub fn calculate_oracle_peg_and_cost(
+    market: &Market,
+    oracle_price: i128,
+    budget: u128,
+) -> ClearingHouseResult<(u128, i128)> {
+    let target_peg = calculate_peg_from_target_price(
+        market.amm.quote_asset_reserve,
+        market.amm.base_asset_reserve,
+        cast_to_u128(oracle_price)?,
+    )?;
+
+    if target_peg == market.amm.peg_multiplier {
+        return Ok((market.amm.peg_multiplier, 0));
+    }
+
+    let target_peg_cost = calculate_repeg_cost(&market.amm, target_peg)?;
+    if target_peg_cost <= cast_to_i128(budget)? {
+        return Ok((target_peg, target_peg_cost));
+    }
+
+    let per_peg_cost = calculate_per_peg_cost(
+        market.amm.quote_asset_reserve,
+        market.amm.terminal_quote_asset_reserve,
+    )?;
+
+    let budget_delta_peg = budget
+        .checked_div(per_peg_cost.unsigned_abs())
+        .ok_or_else(math_error!())?;
+
+    let new_peg = if target_peg > market.amm.peg_multiplier {
+        market
+            .amm
+            .peg_multiplier
+            .checked_add(budget_delta_peg)
+            .ok_or_else(math_error!())?
+    } else {
+        market
+            .amm
+            .peg_multiplier
+            .checked_sub(budget_delta_peg)
+            .ok_or_else(math_error!())?
+    };
+
+    let cost = calculate_repeg_cost(&market.amm, new_peg)?;
+
+    Ok((new_peg, cost))
+}
+
 pub fn calculate_expected_excess_funding_payment(
     market: &Market,
     oracle_price: i128,
@@ -808,4 +876,57 @@ mod test {
         assert_eq!(new_peg, 34656);
         assert_eq!(_amm_update_cost, 303006);
     }
+
+    #[test]
+    fn calculate_oracle_peg_and_cost_test() {
+        let market = Market {
+            amm: AMM {
+                base_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                quote_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                terminal_quote_asset_reserve: 99 * AMM_RESERVE_PRECISION,
+                sqrt_k: 100 * AMM_RESERVE_PRECISION,
+                peg_multiplier: 100_000,
+                ..AMM::default()
+            },
+            ..Market::default()
+        };
+
+        // moving up to the oracle costs 1 quote
+        let oracle_price = (101 * MARK_PRICE_PRECISION) as i128;
+        let (new_peg, cost) =
+            calculate_oracle_peg_and_cost(&market, oracle_price, 2 * QUOTE_PRECISION).unwrap();
+        assert_eq!(new_peg, 101_000);
+        assert_eq!(cost, QUOTE_PRECISION as i128);
+
+        // budget only covers part of the move
+        let (new_peg, cost) =
+            calculate_oracle_peg_and_cost(&market, oracle_price, QUOTE_PRECISION / 2).unwrap();
+        assert_eq!(new_peg, 100_499);
+        assert_eq!(cost, 499_000);
+
+        // moving down to the oracle is revenue so needs no budget
+        let oracle_price = (99 * MARK_PRICE_PRECISION) as i128;
+        let (new_peg, cost) = calculate_oracle_peg_and_cost(&market, oracle_price, 0).unwrap();
+        assert_eq!(new_peg, 99_000);
+        assert_eq!(cost, -(QUOTE_PRECISION as i128));
+    }
+
+    #[test]
+    fn calculate_oracle_peg_budget_test() {
+        let market = Market {
+            amm: AMM {
+                total_fee_minus_distributions: 10 * QUOTE_PRECISION as i128,
+                oracle_peg_slot_budget_pct: 1000, // .1% per slot
+                ..AMM::default()
+            },
+            ..Market::default()
+        };
+
+        let budget = calculate_oracle_peg_budget(&market, 5).unwrap();
+        assert_eq!(budget, 50_000);
+
+        // never more than the fee pool
+        let budget = calculate_oracle_peg_budget(&market, 10_000).unwrap();
+        assert_eq!(budget, 10 * QUOTE_PRECISION);
+    }
 }
@@ -35,3 +35,17 @@ pub fn get_maker_and_maker_stats<'a>(
 
     Ok((maker, maker_stats))
 }
+
+#[allow(clippy::type_complexity)]
+pub fn get_makers_and_maker_stats<'a>(
+    account_info_iter: &mut Peekable<Iter<AccountInfo<'a>>>,
+    maker_order_ids: &[u64],
+) -> ClearingHouseResult<Vec<(AccountLoader<'a, User>, AccountLoader<'a, UserStats>, u64)>> {
+    let mut makers = Vec::with_capacity(maker_order_ids.len());
+    for maker_order_id in maker_order_ids.iter() {
+        let (maker, maker_stats) = get_maker_and_maker_stats(account_info_iter)?;
+        makers.push((maker, maker_stats, *maker_order_id));
+    }
+
+    Ok(makers)
+}
@@ -2,6 +2,7 @@ use std::ops::Div;
 
 use solana_program::msg;
 
+use crate::context::OrderParams;
 use crate::controller::position::PositionDirection;
 use crate::error::{ClearingHouseResult, ErrorCode};
 use crate::math::constants::*;
@@ -10,7 +11,7 @@ use crate::math::orders::{
 };
 use crate::state::market::Market;
 use crate::state::state::State;
-use crate::state::user::{Order, OrderTriggerCondition, OrderType};
+use crate::state::user::{Order, OrderTriggerCondition, OrderType, TrailingStopOffsetType};
 
 pub fn validate_order(
     order: &Order,
@@ -24,6 +25,132 @@ pub fn validate_order(
         OrderType::Limit => validate_limit_order(order, market, state, valid_oracle_price, slot)?,
         OrderType::TriggerMarket => validate_trigger_market_order(order, market, state)?,
         OrderType::TriggerLimit => validate_trigger_limit_order(order, market, state)?,
+        OrderType::TrailingStop => validate_trailing_stop_order(order, market, state)?,
+        OrderType::Twap => validate_twap_order(order, market)?,
+    }
+
+    if order.is_iceberg() {
+        validate_iceberg_order(order, market)?;
+    }
+
+    if order.fill_or_kill {
+        validate_fill_or_kill_order(order)?;
+    }
+
+    if order.has_cross_market_trigger()
+        && !matches!(
+            order.order_type,
+            OrderType::TriggerMarket | OrderType::TriggerLimit
+        )
+    {
+        msg!("Only trigger market and trigger limit orders can use a cross market trigger oracle");
+        return Err(ErrorCode::InvalidOrder);
+    }
+
+    Ok(())
+}
+
+fn validate_fill_or_kill_order(order: &Order) -> ClearingHouseResult {
+    if order.post_only {
+        msg!("Fill or kill order can not be post only");
+        return Err(ErrorCode::InvalidOrder);
+    }
+
+    if order.is_iceberg() || order.order_type == OrderType::Twap {
+        msg!("Fill or kill order can not be an iceberg or twap order");
+        return Err(ErrorCode::InvalidOrder);
+    }
+
+    Ok(())
+}
+
+fn validate_iceberg_order(order: &Order, market: &Market) -> ClearingHouseResult {
+    if order.order_type != OrderType::Limit {
+        msg!("Iceberg order must be a limit order");
+        return Err(ErrorCode::InvalidOrder);
+    }
+
+    if order.iceberg_display_amount < market.amm.base_asset_amount_step_size
+        || order.iceberg_display_amount % market.amm.base_asset_amount_step_size != 0
+    {
+        msg!(
+            "Iceberg display amount ({}) must be a multiple of step size ({})",
+            order.iceberg_display_amount,
+            market.amm.base_asset_amount_step_size
+        );
+        return Err(ErrorCode::InvalidOrder);
+    }
+
+    if order.iceberg_display_amount >= order.base_asset_amount {
+        msg!(
+            "Iceberg display amount ({}) must be less than base asset amount ({})",
+            order.iceberg_display_amount,
+            order.base_asset_amount
+        );
+        return Err(ErrorCode::InvalidOrder);
+    }
+
+    Ok(())
+}
+
+pub fn validate_oco_order_params(
+    take_profit_params: &OrderParams,
+    stop_loss_params: &OrderParams,
+) -> ClearingHouseResult {
+    if take_profit_params.order_type != OrderType::TriggerLimit {
+        msg!("Take profit order must be a trigger limit order");
+        return Err(ErrorCode::InvalidOrder);
+    }
+
+    if stop_loss_params.order_type != OrderType::TriggerMarket {
+        msg!("Stop loss order must be a trigger market order");
+        return Err(ErrorCode::InvalidOrder);
+    }
+
+    if take_profit_params.market_index != stop_loss_params.market_index {
+        msg!("Oco orders must be for the same market");
+        return Err(ErrorCode::InvalidOrder);
+    }
+
+    if take_profit_params.direction != stop_loss_params.direction {
+        msg!("Oco orders must have the same direction");
+        return Err(ErrorCode::InvalidOrder);
+    }
+
+    if !take_profit_params.reduce_only || !stop_loss_params.reduce_only {
+        msg!("Oco orders must be reduce only");
+        return Err(ErrorCode::InvalidOrder);
+    }
+
+    // closing a long (short direction), take profit is above and stop loss below. vice versa for closing a short
+    let (take_profit_condition, stop_loss_condition, prices_ordered) =
+        match take_profit_params.direction {
+            PositionDirection::Short => (
+                OrderTriggerCondition::Above,
+                OrderTriggerCondition::Below,
+                take_profit_params.trigger_price > stop_loss_params.trigger_price,
+            ),
+            PositionDirection::Long => (
+                OrderTriggerCondition::Below,
+                OrderTriggerCondition::Above,
+                take_profit_params.trigger_price < stop_loss_params.trigger_price,
+            ),
+        };
+
+    if take_profit_params.trigger_condition != take_profit_condition
+        || stop_loss_params.trigger_condition != stop_loss_condition
+    {
+        msg!("Oco orders have invalid trigger conditions");
+        return Err(ErrorCode::InvalidOrder);
+    }
+
+    if !prices_ordered {
+        msg!(
+            "Take profit trigger price ({}) on wrong side of stop loss trigger price ({})",
+            take_profit_params.trigger_price,
+            stop_loss_params.trigger_price
+        );
+        return Err(ErrorCode::InvalidOrder);
     }
 
     Ok(())
@@ -182,7 +309,9 @@ fn validate_trigger_limit_order(
         return Err(ErrorCode::InvalidOrder);
     }
 
+    // trigger price is in another market's units for cross market triggers
     match order.trigger_condition {
+        _ if order.has_cross_market_trigger() => {}
         OrderTriggerCondition::Above => {
             if order.direction == PositionDirection::Long && order.price < order.trigger_price {
                 msg!("If trigger condition is above and direction is long, limit price must be above trigger price");
@@ -255,6 +384,98 @@ fn validate_trigger_market_order(
     Ok(())
 }
 
+fn validate_twap_order(order: &Order, market: &Market) -> ClearingHouseResult {
+    validate_market_order(order, market)?;
+
+    if order.twap_slices < 2 {
+        msg!("Twap order must have at least 2 slices");
+        return Err(ErrorCode::InvalidOrder);
+    }
+
+    if order.twap_slot_interval == 0 {
+        msg!("Twap order slot interval == 0");
+        return Err(ErrorCode::InvalidOrder);
+    }
+
+    let slice_base_asset_amount = order.base_asset_amount / order.twap_slices as u128;
+    if slice_base_asset_amount < market.amm.base_asset_amount_step_size {
+        msg!(
+            "Twap slice base asset amount ({}) smaller than step size ({})",
+            slice_base_asset_amount,
+            market.amm.base_asset_amount_step_size
+        );
+        return Err(ErrorCode::InvalidOrder);
+    }
+
+    Ok(())
+}
+
+fn validate_trailing_stop_order(
+    order: &Order,
+    market: &Market,
+    state: &State,
+) -> ClearingHouseResult {
+    validate_base_asset_amount(order, market)?;
+
+    if order.price > 0 {
+        msg!("Trailing stop order should not have price");
+        return Err(ErrorCode::InvalidOrder);
+    }
+
+    if order.trailing_stop_offset == 0 {
+        msg!("Trailing stop offset == 0");
+        return Err(ErrorCode::InvalidOrder);
+    }
+
+    if order.trailing_stop_offset_type == TrailingStopOffsetType::Percentage
+        && order.trailing_stop_offset >= TRAILING_STOP_PERCENTAGE_PRECISION
+    {
+        msg!(
+            "Trailing stop percentage offset ({}) must be less than 100%",
+            order.trailing_stop_offset
+        );
+        return Err(ErrorCode::InvalidOrder);
+    }
+
+    if order.trigger_price == 0 {
+        msg!("Trailing stop order trigger_price == 0");
+        return Err(ErrorCode::InvalidOrder);
+    }
+
+    match (order.direction, order.trigger_condition) {
+        (PositionDirection::Long, OrderTriggerCondition::Above)
+        | (PositionDirection::Short, OrderTriggerCondition::Below) => {}
+        _ => {
+            msg!("Trailing stop must trigger above for longs and below for shorts");
+            return Err(ErrorCode::InvalidOrder);
+        }
+    }
+
+    if order.post_only {
+        msg!("Trailing stop order can not be post only");
+        return Err(ErrorCode::InvalidOrder);
+    }
+
+    if order.has_oracle_price_offset() {
+        msg!("Trailing stop order can not have oracle offset");
+        return Err(ErrorCode::InvalidOrder);
+    }
+
+    let approximate_market_value = order
+        .trigger_price
+        .checked_mul(order.base_asset_amount)
+        .unwrap_or(u128::MAX)
+        .div(AMM_RESERVE_PRECISION)
+        .div(MARK_PRICE_PRECISION / QUOTE_PRECISION);
+
+    if approximate_market_value < state.min_order_quote_asset_amount {
+        msg!("Order value < $0.50 ({:?})", approximate_market_value);
+        return Err(ErrorCode::InvalidOrder);
+    }
+
+    Ok(())
+}
+
 fn validate_base_asset_amount(order: &Order, market: &Market) -> ClearingHouseResult {
     if order.base_asset_amount == 0 {
         msg!("Order base_asset_amount cant be 0");
@@ -0,0 +1,969 @@
+//! Off-chain simulator for the clearing house.
+//!
+//! The simulator owns plain copies of the program accounts and drives the same
+//! controller functions the instructions in lib.rs call. Each call serializes the
+//! accounts into in-memory account infos, loads them through the production
+//! OracleMap/BankMap/MarketMap loaders and only writes the results back if the
+//! call succeeds, so a failed call leaves the simulator untouched just like a
+//! failed transaction. The clock and the pyth oracles are mocked.
+
+use std::collections::BTreeMap;
+
+use anchor_lang::prelude::{AccountInfo, AccountLoader, Clock, Pubkey};
+use anchor_lang::{Owner, ZeroCopy};
+use solana_program::msg;
+
+use crate::context::{ModifyOrderParams, OrderParams};
+use crate::controller;
+use crate::controller::position::PositionDirection;
+use crate::error::{ClearingHouseResult, ErrorCode};
+use crate::ids::pyth_program;
+use crate::load;
+use crate::load_mut;
+use crate::print_error;
+use crate::state::bank::Bank;
+use crate::state::bank_map::{BankMap, WritableBanks};
+use crate::state::market::Market;
+use crate::state::market_map::{MarketMap, MarketSet};
+use crate::state::oracle_map::OracleMap;
+use crate::state::state::State;
+use crate::state::user::{User, UserStats};
+use crate::validate;
+
+#[cfg(test)]
+mod tests;
+
+/// Exponent used for simulated pyth prices, i.e. prices are in MARK_PRICE_PRECISION
+pub const SIM_ORACLE_EXPO: i32 = -10;
+
+#[derive(Clone, Copy, Default, Debug, Eq, PartialEq)]
+pub struct SimOracle {
+    pub price: i64,
+    pub confidence: u64,
+    pub twap: i64,
+    pub valid_slot: u64,
+}
+
+pub struct Simulator {
+    pub state: State,
+    pub clock: Clock,
+    pub markets: BTreeMap<u64, Market>,
+    pub banks: BTreeMap<u64, Bank>,
+    pub oracles: BTreeMap<Pubkey, SimOracle>,
+    pub users: BTreeMap<Pubkey, User>,
+    /// keyed by authority
+    pub user_stats: BTreeMap<Pubkey, UserStats>,
+    /// token balance of the insurance vault
+    pub insurance_vault_amount: u64,
+}
+
+pub struct SimAccounts<'a> {
+    pub oracle_map: OracleMap<'a>,
+    pub bank_map: BankMap<'a>,
+    pub market_map: MarketMap<'a>,
+    pub users: BTreeMap<Pubkey, AccountLoader<'a, User>>,
+    pub user_stats: BTreeMap<Pubkey, AccountLoader<'a, UserStats>>,
+}
+
+impl<'a> SimAccounts<'a> {
+    pub fn user(&self, user_key: &Pubkey) -> ClearingHouseResult<AccountLoader<'a, User>> {
+        self.users
+            .get(user_key)
+            .cloned()
+            .ok_or_else(print_error!(ErrorCode::UnableToLoadAccountLoader))
+    }
+
+    /// User stats are keyed by authority, so look them up through the user
+    pub fn user_stats_for(
+        &self,
+        user_key: &Pubkey,
+    ) -> ClearingHouseResult<AccountLoader<'a, UserStats>> {
+        let authority = load!(self.user(user_key)?)?.authority;
+        self.user_stats
+            .get(&authority)
+            .cloned()
+            .ok_or_else(print_error!(ErrorCode::UnableToLoadAccountLoader))
+    }
+}
+
+impl Simulator {
+    pub fn new(state: State, clock: Clock) -> Self {
+        Simulator {
+            state,
+            clock,
+            markets: BTreeMap::new(),
+            banks: BTreeMap::new(),
+            oracles: BTreeMap::new(),
+            users: BTreeMap::new(),
+            user_stats: BTreeMap::new(),
+            insurance_vault_amount: 0,
+        }
+    }
+
+    pub fn add_market(&mut self, market: Market) {
+        self.markets.insert(market.market_index, market);
+    }
+
+    pub fn add_bank(&mut self, bank: Bank) {
+        self.banks.insert(bank.bank_index, bank);
+    }
+
+    /// Adds a user and creates the user stats for its authority if they dont exist yet
+    pub fn add_user(&mut self, user_key: Pubkey, user: User) {
+        let authority = user.authority;
+        self.user_stats
+            .entry(authority)
+            .or_insert_with(|| UserStats {
+                authority,
+                ..UserStats::default()
+            })
+            .number_of_users += 1;
+        self.users.insert(user_key, user);
+    }
+
+    /// Publishes a new oracle price (in MARK_PRICE_PRECISION) at the current slot
+    pub fn set_oracle_price(&mut self, oracle: Pubkey, price: i64, confidence: u64) {
+        let twap = self.oracles.get(&oracle).map_or(price, |o| o.twap);
+        self.set_oracle(
+            oracle,
+            SimOracle {
+                price,
+                confidence,
+                twap,
+                valid_slot: self.clock.slot,
+            },
+        );
+    }
+
+    pub fn set_oracle(&mut self, oracle: Pubkey, sim_oracle: SimOracle) {
+        self.oracles.insert(oracle, sim_oracle);
+    }
+
+    /// Moves the clock forward. Oracles are not refreshed, so they go stale unless updated.
+    pub fn advance(&mut self, slots: u64, seconds: i64) {
+        self.clock.slot += slots;
+        self.clock.unix_timestamp += seconds;
+    }
+
+    pub fn update_amm(&mut self, market_index: u64) -> ClearingHouseResult<i128> {
+        self.execute(|accounts, state, clock| {
+            controller::repeg::update_amm(
+                market_index,
+                &accounts.market_map,
+                &mut accounts.oracle_map,
+                state,
+                clock,
+            )
+        })
+    }
+
+    pub fn update_funding_rate(&mut self, market_index: u64) -> ClearingHouseResult<bool> {
+        self.execute(|accounts, state, clock| {
+            controller::repeg::update_amm(
+                market_index,
+                &accounts.market_map,
+                &mut accounts.oracle_map,
+                state,
+                clock,
+            )?;
+
+            let market = &mut accounts.market_map.get_ref_mut(&market_index)?;
+            controller::funding::update_funding_rate(
+                market_index,
+                market,
+                &mut accounts.oracle_map,
+                clock.unix_timestamp,
+                &state.oracle_guard_rails,
+                state.funding_paused,
+                None,
+            )
+        })
+    }
+
+    pub fn update_bank_cumulative_interest(&mut self, bank_index: u64) -> ClearingHouseResult {
+        self.execute(|accounts, _state, clock| {
+            let bank = &mut accounts.bank_map.get_ref_mut(&bank_index)?;
+            controller::bank_balance::update_bank_cumulative_interest(bank, clock.unix_timestamp)
+        })
+    }
+
+    pub fn place_order(&mut self, user_key: &Pubkey, params: OrderParams) -> ClearingHouseResult {
+        self.execute(|accounts, state, clock| {
+            controller::repeg::update_amm(
+                params.market_index,
+                &accounts.market_map,
+                &mut accounts.oracle_map,
+                state,
+                clock,
+            )?;
+
+            let user = accounts.user(user_key)?;
+
+            controller::orders::place_order(
+                state,
+                &user,
+                &accounts.market_map,
+                &accounts.bank_map,
+                &mut accounts.oracle_map,
+                clock,
+                params,
+            )
+        })
+    }
+
+    pub fn place_oco_orders(
+        &mut self,
+        user_key: &Pubkey,
+        take_profit_params: OrderParams,
+        stop_loss_params: OrderParams,
+    ) -> ClearingHouseResult {
+        self.execute(|accounts, state, clock| {
+            controller::repeg::update_amm(
+                take_profit_params.market_index,
+                &accounts.market_map,
+                &mut accounts.oracle_map,
+                state,
+                clock,
+            )?;
+
+            let user = accounts.user(user_key)?;
+
+            controller::orders::place_oco_orders(
+                state,
+                &user,
+                &accounts.market_map,
+                &accounts.bank_map,
+                &mut accounts.oracle_map,
+                clock,
+                take_profit_params,
+                stop_loss_params,
+            )
+        })
+    }
+
+    pub fn modify_order(
+        &mut self,
+        user_key: &Pubkey,
+        order_id: u64,
+        params: ModifyOrderParams,
+    ) -> ClearingHouseResult {
+        self.execute(|accounts, state, clock| {
+            let market_index = load!(accounts.user(user_key)?)?
+                .get_order(order_id)
+                .map(|order| order.market_index)
+                .ok_or(ErrorCode::OrderDoesNotExist)?;
+
+            controller::repeg::update_amm(
+                market_index,
+                &accounts.market_map,
+                &mut accounts.oracle_map,
+                state,
+                clock,
+            )?;
+
+            let user = accounts.user(user_key)?;
+
+            controller::orders::modify_order(
+                order_id,
+                state,
+                &user,
+                &accounts.market_map,
+                &accounts.bank_map,
+                &mut accounts.oracle_map,
+                clock,
+                params,
+            )
+        })
+    }
+
+    pub fn cancel_order(&mut self, user_key: &Pubkey, order_id: u64) -> ClearingHouseResult {
+        self.execute(|accounts, _state, clock| {
+            let user = accounts.user(user_key)?;
+
+            controller::orders::cancel_order_by_order_id(
+                order_id,
+                &user,
+                &accounts.market_map,
+                &mut accounts.oracle_map,
+                clock,
+            )
+        })
+    }
+
+    pub fn place_orders(
+        &mut self,
+        user_key: &Pubkey,
+        params: Vec<OrderParams>,
+    ) -> ClearingHouseResult {
+        self.execute(|accounts, state, clock| {
+            for market_index in params.iter().map(|params| params.market_index) {
+                controller::repeg::update_amm(
+                    market_index,
+                    &accounts.market_map,
+                    &mut accounts.oracle_map,
+                    state,
+                    clock,
+                )?;
+            }
+
+            let user = accounts.user(user_key)?;
+
+            controller::orders::place_orders(
+                state,
+                &user,
+                &accounts.market_map,
+                &accounts.bank_map,
+                &mut accounts.oracle_map,
+                clock,
+                params,
+            )
+        })
+    }
+
+    pub fn cancel_orders(
+        &mut self,
+        user_key: &Pubkey,
+        market_index: Option<u64>,
+        direction: Option<PositionDirection>,
+    ) -> ClearingHouseResult {
+        self.execute(|accounts, _state, clock| {
+            let user = accounts.user(user_key)?;
+
+            controller::orders::cancel_orders(
+                &user,
+                &accounts.market_map,
+                &mut accounts.oracle_map,
+                clock,
+                market_index,
+                direction,
+            )
+        })
+    }
+
+    pub fn cancel_and_place(
+        &mut self,
+        user_key: &Pubkey,
+        cancel_market_index: Option<u64>,
+        cancel_direction: Option<PositionDirection>,
+        params: Vec<OrderParams>,
+    ) -> ClearingHouseResult {
+        self.execute(|accounts, state, clock| {
+            for market_index in params.iter().map(|params| params.market_index) {
+                controller::repeg::update_amm(
+                    market_index,
+                    &accounts.market_map,
+                    &mut accounts.oracle_map,
+                    state,
+                    clock,
+                )?;
+            }
+
+            let user = accounts.user(user_key)?;
+
+            controller::orders::cancel_and_place(
+                state,
+                &user,
+                &accounts.market_map,
+                &accounts.bank_map,
+                &mut accounts.oracle_map,
+                clock,
+                cancel_market_index,
+                cancel_direction,
+                params,
+            )
+        })
+    }
+
+    /// Returns the base asset amount filled and whether the user state was updated
+    pub fn fill_order(
+        &mut self,
+        user_key: &Pubkey,
+        order_id: u64,
+        filler_key: &Pubkey,
+        makers: &[(&Pubkey, u64)],
+    ) -> ClearingHouseResult<(u128, bool)> {
+        self.execute(|accounts, state, clock| {
+            let market_index = load!(accounts.user(user_key)?)?
+                .get_order(order_id)
+                .map(|order| order.market_index)
+                .ok_or(ErrorCode::OrderDoesNotExist)?;
+
+            controller::repeg::update_amm(
+                market_index,
+                &accounts.market_map,
+                &mut accounts.oracle_map,
+                state,
+                clock,
+            )?;
+
+            let user = accounts.user(user_key)?;
+            let user_stats = accounts.user_stats_for(user_key)?;
+            let filler = accounts.user(filler_key)?;
+            let filler_stats = accounts.user_stats_for(filler_key)?;
+            let mut maker_accounts = Vec::with_capacity(makers.len());
+            for (maker_key, maker_order_id) in makers.iter() {
+                maker_accounts.push((
+                    accounts.user(maker_key)?,
+                    accounts.user_stats_for(maker_key)?,
+                    *maker_order_id,
+                ));
+            }
+
+            controller::orders::fill_order(
+                order_id,
+                state,
+                &user,
+                &user_stats,
+                &accounts.bank_map,
+                &accounts.market_map,
+                &mut accounts.oracle_map,
+                &filler,
+                &filler_stats,
+                &maker_accounts,
+                clock,
+            )
+        })
+    }
+
+    /// Places the order and fills it in the same step, returns the base asset amount filled
+    pub fn place_and_take(
+        &mut self,
+        user_key: &Pubkey,
+        params: OrderParams,
+        makers: &[(&Pubkey, u64)],
+    ) -> ClearingHouseResult<u128> {
+        self.execute(|accounts, state, clock| {
+            controller::repeg::update_amm(
+                params.market_index,
+                &accounts.market_map,
+                &mut accounts.oracle_map,
+                state,
+                clock,
+            )?;
+
+            let user = accounts.user(user_key)?;
+            let user_stats = accounts.user_stats_for(user_key)?;
+            let mut maker_accounts = Vec::with_capacity(makers.len());
+            for (maker_key, maker_order_id) in makers.iter() {
+                maker_accounts.push((
+                    accounts.user(maker_key)?,
+                    accounts.user_stats_for(maker_key)?,
+                    *maker_order_id,
+                ));
+            }
+
+            let is_immediate_or_cancel = params.immediate_or_cancel;
+            let base_asset_amount_to_fill = params.base_asset_amount;
+
+            controller::orders::place_order(
+                state,
+                &user,
+                &accounts.market_map,
+                &accounts.bank_map,
+                &mut accounts.oracle_map,
+                clock,
+                params,
+            )?;
+
+            let order_id = load!(user)?.get_last_order_id();
+
+            let (base_asset_amount_filled, _) = controller::orders::fill_order(
+                order_id,
+                state,
+                &user,
+                &user_stats,
+                &accounts.bank_map,
+                &accounts.market_map,
+                &mut accounts.oracle_map,
+                &user,
+                &user_stats,
+                &maker_accounts,
+                clock,
+            )?;
+
+            if is_immediate_or_cancel && base_asset_amount_to_fill != base_asset_amount_filled {
+                controller::orders::cancel_order_by_order_id(
+                    order_id,
+                    &user,
+                    &accounts.market_map,
+                    &mut accounts.oracle_map,
+                    clock,
+                )?;
+            }
+
+            Ok(base_asset_amount_filled)
+        })
+    }
+
+    /// Returns the base asset amount matched
+    pub fn match_orders(
+        &mut self,
+        user_key: &Pubkey,
+        order_id: u64,
+        counterparty_key: &Pubkey,
+        counterparty_order_id: u64,
+        filler_key: &Pubkey,
+    ) -> ClearingHouseResult<u128> {
+        self.execute(|accounts, state, clock| {
+            let user = accounts.user(user_key)?;
+            let user_stats = accounts.user_stats_for(user_key)?;
+            let counterparty = accounts.user(counterparty_key)?;
+            let counterparty_stats = accounts.user_stats_for(counterparty_key)?;
+            let filler = accounts.user(filler_key)?;
+            let filler_stats = accounts.user_stats_for(filler_key)?;
+
+            controller::orders::match_orders(
+                order_id,
+                counterparty_order_id,
+                state,
+                &user,
+                &user_stats,
+                &counterparty,
+                &counterparty_stats,
+                &accounts.bank_map,
+                &accounts.market_map,
+                &mut accounts.oracle_map,
+                &filler,
+                &filler_stats,
+                clock,
+            )
+        })
+    }
+
+    pub fn trigger_order(
+        &mut self,
+        user_key: &Pubkey,
+        order_id: u64,
+        filler_key: &Pubkey,
+    ) -> ClearingHouseResult {
+        self.execute(|accounts, state, clock| {
+            let market_index = load!(accounts.user(user_key)?)?
+                .get_order(order_id)
+                .map(|order| order.market_index)
+                .ok_or(ErrorCode::OrderDoesNotExist)?;
+
+            controller::repeg::update_amm(
+                market_index,
+                &accounts.market_map,
+                &mut accounts.oracle_map,
+                state,
+                clock,
+            )?;
+
+            let user = accounts.user(user_key)?;
+            let filler = accounts.user(filler_key)?;
+
+            controller::orders::trigger_order(
+                order_id,
+                state,
+                &user,
+                &accounts.market_map,
+                &mut accounts.oracle_map,
+                &filler,
+                clock,
+            )
+        })
+    }
+
+    pub fn expire_order(
+        &mut self,
+        user_key: &Pubkey,
+        order_id: u64,
+        filler_key: &Pubkey,
+    ) -> ClearingHouseResult {
+        self.execute(|accounts, state, clock| {
+            let user = accounts.user(user_key)?;
+            let filler = accounts.user(filler_key)?;
+
+            controller::orders::expire_order(
+                order_id,
+                state,
+                &user,
+                &accounts.market_map,
+                &mut accounts.oracle_map,
+                &filler,
+                clock,
+            )
+        })
+    }
+
+    pub fn settle_pnl(&mut self, user_key: &Pubkey, market_index: u64) -> ClearingHouseResult {
+        self.execute(|accounts, state, clock| {
+            controller::repeg::update_amm(
+                market_index,
+                &accounts.market_map,
+                &mut accounts.oracle_map,
+                state,
+                clock,
+            )?;
+
+            let user_loader = accounts.user(user_key)?;
+            let user = &mut load_mut!(user_loader)?;
+            let authority = user.authority;
+
+            controller::pnl::settle_pnl(
+                market_index,
+                user,
+                &authority,
+                user_key,
+                &accounts.market_map,
+                &accounts.bank_map,
+                &mut accounts.oracle_map,
+                clock.unix_timestamp,
+            )
+        })
+    }
+
+    pub fn liquidate_perp(
+        &mut self,
+        liquidator_key: &Pubkey,
+        user_key: &Pubkey,
+        market_index: u64,
+        liquidator_max_base_asset_amount: u128,
+    ) -> ClearingHouseResult {
+        self.execute(|accounts, state, clock| {
+            validate!(
+                user_key != liquidator_key,
+                ErrorCode::UserCantLiquidateThemself
+            )?;
+
+            let user_loader = accounts.user(user_key)?;
+            let user_stats_loader = accounts.user_stats_for(user_key)?;
+            let liquidator_loader = accounts.user(liquidator_key)?;
+            let liquidator_stats_loader = accounts.user_stats_for(liquidator_key)?;
+
+            let user = &mut load_mut!(user_loader)?;
+            let user_stats = &mut load_mut!(user_stats_loader)?;
+            let liquidator = &mut load_mut!(liquidator_loader)?;
+            let liquidator_stats = &mut load_mut!(liquidator_stats_loader)?;
+
+            controller::liquidation::liquidate_perp(
+                market_index,
+                liquidator_max_base_asset_amount,
+                user,
+                user_key,
+                user_stats,
+                liquidator,
+                liquidator_key,
+                liquidator_stats,
+                &accounts.market_map,
+                &accounts.bank_map,
+                &mut accounts.oracle_map,
+                clock.slot,
+                clock.unix_timestamp,
+                state.liquidation_margin_buffer_ratio,
+                state.fee_structure.cancel_order_fee,
+            )
+        })
+    }
+
+    /// Resolves the bankruptcy and moves the insurance fund payment out of the insurance vault
+    pub fn resolve_perp_bankruptcy(
+        &mut self,
+        liquidator_key: &Pubkey,
+        user_key: &Pubkey,
+        market_index: u64,
+    ) -> ClearingHouseResult<u64> {
+        let insurance_vault_amount = self.insurance_vault_amount;
+        let if_payment = self.execute(|accounts, _state, clock| {
+            validate!(
+                user_key != liquidator_key,
+                ErrorCode::UserCantLiquidateThemself
+            )?;
+
+            let user_loader = accounts.user(user_key)?;
+            let liquidator_loader = accounts.user(liquidator_key)?;
+
+            let user = &mut load_mut!(user_loader)?;
+            let liquidator = &mut load_mut!(liquidator_loader)?;
+
+            controller::liquidation::resolve_perp_bankruptcy(
+                market_index,
+                user,
+                user_key,
+                liquidator,
+                liquidator_key,
+                &accounts.market_map,
+                &accounts.bank_map,
+                &mut accounts.oracle_map,
+                clock.unix_timestamp,
+                insurance_vault_amount,
+            )
+        })?;
+
+        self.insurance_vault_amount -= if_payment;
+        Ok(if_payment)
+    }
+
+    pub fn transfer_position(
+        &mut self,
+        from_user_key: &Pubkey,
+        to_user_key: &Pubkey,
+        market_index: u64,
+    ) -> ClearingHouseResult {
+        self.execute(|accounts, _state, clock| {
+            let from_user_loader = accounts.user(from_user_key)?;
+            let to_user_loader = accounts.user(to_user_key)?;
+
+            let from_user = &mut load_mut!(from_user_loader)?;
+            let to_user = &mut load_mut!(to_user_loader)?;
+
+            controller::position::transfer_position(
+                market_index,
+                from_user,
+                from_user_key,
+                to_user,
+                to_user_key,
+                &accounts.market_map,
+                &accounts.bank_map,
+                &mut accounts.oracle_map,
+                clock.unix_timestamp,
+            )
+        })
+    }
+
+    pub fn add_insurance_fund_stake(
+        &mut self,
+        authority: &Pubkey,
+        amount: u64,
+    ) -> ClearingHouseResult {
+        let mut state = self.state.clone();
+        let mut user_stats = self.get_user_stats(authority)?;
+
+        controller::insurance::add_insurance_fund_stake(
+            amount,
+            self.insurance_vault_amount,
+            &mut user_stats,
+            &mut state,
+        )?;
+
+        self.state = state;
+        self.user_stats.insert(*authority, user_stats);
+        self.insurance_vault_amount += amount;
+        Ok(())
+    }
+
+    pub fn request_remove_insurance_fund_stake(
+        &mut self,
+        authority: &Pubkey,
+        shares: u128,
+    ) -> ClearingHouseResult {
+        let mut user_stats = self.get_user_stats(authority)?;
+
+        controller::insurance::request_remove_insurance_fund_stake(
+            shares,
+            self.insurance_vault_amount,
+            &mut user_stats,
+            &self.state,
+            self.clock.unix_timestamp,
+        )?;
+
+        self.user_stats.insert(*authority, user_stats);
+        Ok(())
+    }
+
+    /// Returns the amount sent from the insurance vault to the staker
+    pub fn remove_insurance_fund_stake(&mut self, authority: &Pubkey) -> ClearingHouseResult<u64> {
+        let mut state = self.state.clone();
+        let mut user_stats = self.get_user_stats(authority)?;
+
+        let amount = controller::insurance::remove_insurance_fund_stake(
+            self.insurance_vault_amount,
+            &mut user_stats,
+            &mut state,
+            self.clock.unix_timestamp,
+        )?;
+
+        self.state = state;
+        self.user_stats.insert(*authority, user_stats);
+        self.insurance_vault_amount -= amount;
+        Ok(amount)
+    }
+
+    fn get_user_stats(&self, authority: &Pubkey) -> ClearingHouseResult<UserStats> {
+        self.user_stats
+            .get(authority)
+            .copied()
+            .ok_or_else(print_error!(ErrorCode::UnableToLoadAccountLoader))
+    }
+
+    /// Runs `f` against account infos built from the simulator's accounts. The accounts are only
+    /// written back if `f` succeeds.
+    pub fn execute<T, F>(&mut self, f: F) -> ClearingHouseResult<T>
+    where
+        F: for<'a> FnOnce(&mut SimAccounts<'a>, &State, &Clock) -> ClearingHouseResult<T>,
+    {
+        let mut oracle_accounts: Vec<SimAccount> = self
+            .oracles
+            .iter()
+            .map(|(key, oracle)| SimAccount::pyth(*key, oracle))
+            .collect();
+        let mut bank_accounts: Vec<SimAccount> = self
+            .banks
+            .values()
+            .map(|bank| SimAccount::anchor(Pubkey::default(), bank))
+            .collect();
+        let mut market_accounts: Vec<SimAccount> = self
+            .markets
+            .values()
+            .map(|market| SimAccount::anchor(market.pubkey, market))
+            .collect();
+        let mut user_accounts: Vec<SimAccount> = self
+            .users
+            .iter()
+            .map(|(key, user)| SimAccount::anchor(*key, user))
+            .collect();
+        let mut user_stats_accounts: Vec<SimAccount> = self
+            .user_stats
+            .iter()
+            .map(|(authority, user_stats)| SimAccount::anchor(*authority, user_stats))
+            .collect();
+
+        let result = {
+            let remaining_accounts: Vec<AccountInfo> = oracle_accounts
+                .iter_mut()
+                .chain(bank_accounts.iter_mut())
+                .chain(market_accounts.iter_mut())
+                .map(SimAccount::account_info)
+                .collect();
+            let remaining_accounts_iter = &mut remaining_accounts.iter().peekable();
+            let oracle_map = OracleMap::load(remaining_accounts_iter, self.clock.slot)?;
+            let bank_map = BankMap::load(&WritableBanks::new(), remaining_accounts_iter)?;
+            let market_map = MarketMap::load(
+                &MarketSet::new(),
+                &MarketSet::new(),
+                remaining_accounts_iter,
+            )?;
+
+            let user_account_infos: Vec<AccountInfo> = user_accounts
+                .iter_mut()
+                .map(SimAccount::account_info)
+                .collect();
+            let users = user_account_infos
+                .iter()
+                .map(|account_info| {
+                    AccountLoader::try_from(account_info)
+                        .map(|loader| (*account_info.key, loader))
+                        .or(Err(ErrorCode::UnableToLoadAccountLoader))
+                })
+                .collect::<ClearingHouseResult<BTreeMap<_, _>>>()?;
+
+            let user_stats_account_infos: Vec<AccountInfo> = user_stats_accounts
+                .iter_mut()
+                .map(SimAccount::account_info)
+                .collect();
+            let user_stats = user_stats_account_infos
+                .iter()
+                .map(|account_info| {
+                    AccountLoader::try_from(account_info)
+                        .map(|loader| (*account_info.key, loader))
+                        .or(Err(ErrorCode::UnableToLoadAccountLoader))
+                })
+                .collect::<ClearingHouseResult<BTreeMap<_, _>>>()?;
+
+            let mut accounts = SimAccounts {
+                oracle_map,
+                bank_map,
+                market_map,
+                users,
+                user_stats,
+            };
+
+            f(&mut accounts, &self.state, &self.clock)
+        };
+
+        result.map(|result| {
+            for account in bank_accounts.iter() {
+                let bank: Bank = account.load();
+                self.banks.insert(bank.bank_index, bank);
+            }
+            for account in market_accounts.iter() {
+                let market: Market = account.load();
+                self.markets.insert(market.market_index, market);
+            }
+            for account in user_accounts.iter() {
+                self.users.insert(account.key, account.load());
+            }
+            for account in user_stats_accounts.iter() {
+                self.user_stats.insert(account.key, account.load());
+            }
+            result
+        })
+    }
+}
+
+/// Owned backing storage for an AccountInfo. Data is stored as u64s so it is 8 byte aligned,
+/// which pyth_client::cast relies on.
+struct SimAccount {
+    key: Pubkey,
+    owner: Pubkey,
+    lamports: u64,
+    data: Vec<u64>,
+    len: usize,
+}
+
+impl SimAccount {
+    fn new(key: Pubkey, owner: Pubkey, len: usize) -> Self {
+        SimAccount {
+            key,
+            owner,
+            lamports: 0,
+            data: vec![0; (len + 7) / 8],
+            len,
+        }
+    }
+
+    fn anchor<T: ZeroCopy + Owner>(key: Pubkey, account: &T) -> Self {
+        let mut sim_account = SimAccount::new(key, T::owner(), std::mem::size_of::<T>() + 8);
+        let bytes = sim_account.bytes_mut();
+        bytes[..8].copy_from_slice(&T::discriminator());
+        bytes[8..].copy_from_slice(bytemuck::bytes_of(account));
+        sim_account
+    }
+
+    fn pyth(key: Pubkey, oracle: &SimOracle) -> Self {
+        let mut sim_account = SimAccount::new(
+            key,
+            pyth_program::id(),
+            std::mem::size_of::<pyth_client::Price>(),
+        );
+        let (_, prices, _) =
+            unsafe { sim_account.bytes_mut().align_to_mut::<pyth_client::Price>() };
+        let price = &mut prices[0];
+        price.expo = SIM_ORACLE_EXPO;
+        price.valid_slot = oracle.valid_slot;
+        price.twap.val = oracle.twap;
+        price.agg.price = oracle.price;
+        price.agg.conf = oracle.confidence;
+        sim_account
+    }
+
+    fn bytes_mut(&mut self) -> &mut [u8] {
+        &mut bytemuck::cast_slice_mut(&mut self.data)[..self.len]
+    }
+
+    fn load<T: ZeroCopy>(&self) -> T {
+        let bytes: &[u8] = bytemuck::cast_slice(&self.data);
+        *bytemuck::from_bytes(&bytes[8..std::mem::size_of::<T>() + 8])
+    }
+
+    fn account_info(&mut self) -> AccountInfo {
+        let SimAccount {
+            key,
+            owner,
+            lamports,
+            data,
+            len,
+        } = self;
+        AccountInfo::new(
+            key,
+            false,
+            true,
+            lamports,
+            &mut bytemuck::cast_slice_mut(data)[..*len],
+            owner,
+            false,
+            0,
+        )
+    }
+}
@@ -0,0 +1,2546 @@
+use std::str::FromStr;
+
+use anchor_lang::prelude::{Clock, Pubkey};
+
+use crate::context::{ModifyOrderParams, OrderParams};
+use crate::controller::position::PositionDirection;
+use crate::error::ErrorCode;
+use crate::math::constants::{
+    AMM_RESERVE_PRECISION, BANK_CUMULATIVE_INTEREST_PRECISION, BANK_INTEREST_PRECISION,
+    BANK_WEIGHT_PRECISION, BASE_PRECISION, BASE_PRECISION_I128, LIQUIDATION_FEE_PRECISION,
+    MARK_PRICE_PRECISION, MARK_PRICE_PRECISION_I128, PEG_PRECISION, QUOTE_PRECISION,
+    QUOTE_PRECISION_I128, TWENTY_FOUR_HOUR,
+};
+use crate::sim::Simulator;
+use crate::state::bank::{Bank, BankBalanceType};
+use crate::state::market::{Market, AMM};
+use crate::state::oracle::OracleSource;
+use crate::state::state::State;
+use crate::state::user::{
+    AuctionPriceCurve, MarketPosition, OrderStatus, OrderTriggerCondition, OrderType,
+    SelfTradePrevention, User, UserBankBalance,
+};
+use crate::tests::utils::*;
+
+fn oracle_key() -> Pubkey {
+    Pubkey::from_str("J83w4HKfqxwcq3BEMMkPFSppX3gqekLyLJBexebFVkix").unwrap()
+}
+
+fn user_key() -> Pubkey {
+    Pubkey::from_str("My11111111111111111111111111111111111111111").unwrap()
+}
+
+fn filler_key() -> Pubkey {
+    Pubkey::from_str("My11111111111111111111111111111111111111112").unwrap()
+}
+
+fn get_simulator() -> Simulator {
+    let clock = Clock {
+        slot: 0,
+        epoch_start_timestamp: 0,
+        epoch: 0,
+        leader_schedule_epoch: 0,
+        unix_timestamp: 0,
+    };
+
+    let state = State {
+        min_order_quote_asset_amount: 500_000,
+        min_auction_duration: 1,
+        max_auction_duration: 10,
+        liquidation_margin_buffer_ratio: 10,
+        insurance_fund_unstaking_period: TWENTY_FOUR_HOUR,
+        insurance_fund_unstaking_window: TWENTY_FOUR_HOUR,
+        ..State::default()
+    };
+
+    let mut sim = Simulator::new(state, clock);
+
+    sim.set_oracle_price(oracle_key(), 100 * MARK_PRICE_PRECISION as i64, 0);
+
+    let mut market = Market {
+        amm: AMM {
+            base_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+            quote_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+            bid_base_asset_reserve: 101 * AMM_RESERVE_PRECISION,
+            bid_quote_asset_reserve: 99 * AMM_RESERVE_PRECISION,
+            ask_base_asset_reserve: 99 * AMM_RESERVE_PRECISION,
+            ask_quote_asset_reserve: 101 * AMM_RESERVE_PRECISION,
+            sqrt_k: 100 * AMM_RESERVE_PRECISION,
+            peg_multiplier: 100 * PEG_PRECISION,
+            max_slippage_ratio: 100,
+            max_base_asset_amount_ratio: 100,
+            base_asset_amount_step_size: 10000000,
+            order_tick_size: MARK_PRICE_PRECISION / 100,
+            last_oracle_price_twap: 100 * MARK_PRICE_PRECISION_I128,
+            last_mark_price_twap: 100 * MARK_PRICE_PRECISION,
+            funding_period: 3600,
+            oracle: oracle_key(),
+            ..AMM::default()
+        },
+        margin_ratio_initial: 1000,
+        margin_ratio_maintenance: 500,
+        initialized: true,
+        liquidation_fee: LIQUIDATION_FEE_PRECISION / 100,
+        ..Market::default()
+    };
+    market.amm.max_base_asset_reserve = u128::MAX;
+    market.amm.min_base_asset_reserve = 0;
+    sim.add_market(market);
+
+    sim.add_bank(Bank {
+        bank_index: 0,
+        oracle_source: OracleSource::QuoteAsset,
+        cumulative_deposit_interest: BANK_CUMULATIVE_INTEREST_PRECISION,
+        cumulative_borrow_interest: BANK_CUMULATIVE_INTEREST_PRECISION,
+        decimals: 6,
+        initial_asset_weight: BANK_WEIGHT_PRECISION,
+        maintenance_asset_weight: BANK_WEIGHT_PRECISION,
+        deposit_balance: 200 * BANK_INTEREST_PRECISION,
+        ..Bank::default()
+    });
+
+    sim.add_user(
+        user_key(),
+        User {
+            authority: user_key(),
+            bank_balances: get_bank_balances(UserBankBalance {
+                bank_index: 0,
+                balance_type: BankBalanceType::Deposit,
+                balance: 100 * BANK_INTEREST_PRECISION,
+            }),
+            next_order_id: 1,
+            ..User::default()
+        },
+    );
+
+    sim.add_user(
+        filler_key(),
+        User {
+            authority: filler_key(),
+            bank_balances: get_bank_balances(UserBankBalance {
+                bank_index: 0,
+                balance_type: BankBalanceType::Deposit,
+                balance: 100 * BANK_INTEREST_PRECISION,
+            }),
+            next_order_id: 1,
+            ..User::default()
+        },
+    );
+
+    sim
+}
+
+#[test]
+fn place_and_fill_market_order_against_amm() {
+    let mut sim = get_simulator();
+
+    sim.place_order(
+        &user_key(),
+        OrderParams {
+            order_type: OrderType::Market,
+            direction: PositionDirection::Long,
+            base_asset_amount: BASE_PRECISION,
+            price: 110 * MARK_PRICE_PRECISION,
+            market_index: 0,
+            ..OrderParams::default()
+        },
+    )
+    .unwrap();
+
+    let order_id = sim.users[&user_key()].get_last_order_id();
+    assert_eq!(sim.users[&user_key()].positions[0].open_orders, 1);
+
+    sim.advance(10, 5);
+    sim.set_oracle_price(oracle_key(), 100 * MARK_PRICE_PRECISION as i64, 0);
+
+    let (base_asset_amount, updated_user_state) = sim
+        .fill_order(&user_key(), order_id, &filler_key(), &[])
+        .unwrap();
+
+    assert_eq!(base_asset_amount, BASE_PRECISION);
+    assert!(updated_user_state);
+
+    let user = &sim.users[&user_key()];
+    assert_eq!(user.positions[0].base_asset_amount, BASE_PRECISION_I128);
+    assert_eq!(user.positions[0].open_orders, 0);
+    assert_eq!(user.positions[0].open_bids, 0);
+
+    let market = &sim.markets[&0];
+    assert_eq!(market.amm.net_base_asset_amount, BASE_PRECISION_I128);
+    assert_eq!(market.base_asset_amount_long, BASE_PRECISION_I128);
+}
+
+#[test]
+fn failed_call_does_not_update_accounts() {
+    let mut sim = get_simulator();
+
+    sim.place_order(
+        &user_key(),
+        OrderParams {
+            order_type: OrderType::Limit,
+            direction: PositionDirection::Long,
+            base_asset_amount: BASE_PRECISION,
+            price: 90 * MARK_PRICE_PRECISION,
+            market_index: 0,
+            ..OrderParams::default()
+        },
+    )
+    .unwrap();
+
+    let user_before = sim.users[&user_key()];
+
+    // place order succeeds but the margin check fails after it, so the order must not be kept
+    let result = sim.place_order(
+        &user_key(),
+        OrderParams {
+            order_type: OrderType::Limit,
+            direction: PositionDirection::Long,
+            base_asset_amount: 100 * BASE_PRECISION,
+            price: 90 * MARK_PRICE_PRECISION,
+            market_index: 0,
+            ..OrderParams::default()
+        },
+    );
+
+    assert_eq!(result, Err(ErrorCode::InsufficientCollateral));
+    assert_eq!(sim.users[&user_key()], user_before);
+}
+
+#[test]
+fn update_funding_rate_after_funding_period() {
+    let mut sim = get_simulator();
+
+    assert!(!sim.update_funding_rate(0).unwrap());
+
+    sim.advance(7200, 3600);
+    sim.set_oracle_price(oracle_key(), 99 * MARK_PRICE_PRECISION as i64, 0);
+
+    assert!(sim.update_funding_rate(0).unwrap());
+
+    let market = &sim.markets[&0];
+    assert_eq!(market.amm.last_funding_rate_ts, 3600);
+    assert!(market.amm.last_funding_rate > 0);
+}
+
+#[test]
+fn liquidate_underwater_perp_position() {
+    let mut sim = get_simulator();
+
+    let user = sim.users.get_mut(&user_key()).unwrap();
+    user.bank_balances = [UserBankBalance::default(); 8];
+    user.positions = get_positions(MarketPosition {
+        market_index: 0,
+        base_asset_amount: BASE_PRECISION_I128,
+        quote_asset_amount: -150 * QUOTE_PRECISION_I128,
+        quote_entry_amount: -150 * QUOTE_PRECISION_I128,
+        ..MarketPosition::default()
+    });
+
+    let market = sim.markets.get_mut(&0).unwrap();
+    market.amm.quote_asset_amount_long = -150 * QUOTE_PRECISION_I128;
+    market.amm.net_base_asset_amount = BASE_PRECISION_I128;
+    market.base_asset_amount_long = BASE_PRECISION_I128;
+    market.open_interest = 1;
+
+    assert_eq!(
+        sim.liquidate_perp(&user_key(), &user_key(), 0, BASE_PRECISION),
+        Err(ErrorCode::UserCantLiquidateThemself)
+    );
+
+    sim.liquidate_perp(&filler_key(), &user_key(), 0, BASE_PRECISION)
+        .unwrap();
+
+    let user = &sim.users[&user_key()];
+    assert_eq!(user.positions[0].base_asset_amount, 0);
+    assert!(user.being_liquidated);
+
+    let liquidator = &sim.users[&filler_key()];
+    assert_eq!(
+        liquidator.positions[0].base_asset_amount,
+        BASE_PRECISION_I128
+    );
+}
+
+#[test]
+fn insurance_fund_stake_absorbs_bankruptcy_then_restarts() {
+    let mut sim = get_simulator();
+    let staker = filler_key();
+    let amount = 100 * QUOTE_PRECISION as u64;
+
+    sim.add_insurance_fund_stake(&staker, amount).unwrap();
+    assert_eq!(sim.insurance_vault_amount, amount);
+    assert_eq!(
+        sim.user_stats[&staker].insurance_fund_shares,
+        amount as u128
+    );
+
+    // unstaking waits out the cooldown, the stake stays at risk until then
+    let shares = sim.user_stats[&staker].insurance_fund_shares;
+    sim.request_remove_insurance_fund_stake(&staker, shares / 2)
+        .unwrap();
+    assert_eq!(
+        sim.remove_insurance_fund_stake(&staker),
+        Err(ErrorCode::TryingToRemoveInsuranceFundStakeTooFast)
+    );
+    sim.advance(1, TWENTY_FOUR_HOUR);
+    assert_eq!(sim.remove_insurance_fund_stake(&staker), Ok(amount / 2));
+    assert_eq!(sim.insurance_vault_amount, amount / 2);
+
+    let user = sim.users.get_mut(&user_key()).unwrap();
+    user.bank_balances = [UserBankBalance::default(); 8];
+    user.bankrupt = true;
+    user.being_liquidated = true;
+    user.next_liquidation_id = 2;
+    user.positions = get_positions(MarketPosition {
+        market_index: 0,
+        quote_asset_amount: -150 * QUOTE_PRECISION_I128,
+        ..MarketPosition::default()
+    });
+
+    // the loss is bigger than the vault, so the stake is wiped out
+    let if_payment = sim
+        .resolve_perp_bankruptcy(&filler_key(), &user_key(), 0)
+        .unwrap();
+    assert_eq!(if_payment, amount / 2);
+    assert_eq!(sim.insurance_vault_amount, 0);
+    assert!(!sim.users[&user_key()].bankrupt);
+
+    // a drained vault doesn't block new stakes, the old shares are written off instead
+    let new_staker = user_key();
+    sim.add_insurance_fund_stake(&new_staker, amount).unwrap();
+    assert_eq!(sim.state.insurance_fund_total_shares, amount as u128);
+    assert_eq!(
+        sim.user_stats[&new_staker].insurance_fund_shares,
+        amount as u128
+    );
+
+    assert_eq!(
+        sim.request_remove_insurance_fund_stake(&staker, shares / 2),
+        Err(ErrorCode::InsufficientInsuranceFundShares)
+    );
+
+    let shares = sim.user_stats[&new_staker].insurance_fund_shares;
+    sim.request_remove_insurance_fund_stake(&new_staker, shares)
+        .unwrap();
+    sim.advance(1, TWENTY_FOUR_HOUR);
+    assert_eq!(sim.remove_insurance_fund_stake(&new_staker), Ok(amount));
+    assert_eq!(sim.insurance_vault_amount, 0);
+}
+
+#[test]
+fn stop_loss_fill_cancels_take_profit() {
+    let mut sim = get_simulator();
+
+    sim.place_order(
+        &user_key(),
+        OrderParams {
+            order_type: OrderType::Market,
+            direction: PositionDirection::Long,
+            base_asset_amount: BASE_PRECISION,
+            price: 110 * MARK_PRICE_PRECISION,
+            market_index: 0,
+            ..OrderParams::default()
+        },
+    )
+    .unwrap();
+    let order_id = sim.users[&user_key()].get_last_order_id();
+    sim.advance(10, 5);
+    sim.set_oracle_price(oracle_key(), 100 * MARK_PRICE_PRECISION as i64, 0);
+    sim.fill_order(&user_key(), order_id, &filler_key(), &[])
+        .unwrap();
+
+    sim.place_oco_orders(
+        &user_key(),
+        OrderParams {
+            order_type: OrderType::TriggerLimit,
+            direction: PositionDirection::Short,
+            base_asset_amount: BASE_PRECISION,
+            price: 110 * MARK_PRICE_PRECISION,
+            trigger_price: 110 * MARK_PRICE_PRECISION,
+            trigger_condition: OrderTriggerCondition::Above,
+            reduce_only: true,
+            market_index: 0,
+            ..OrderParams::default()
+        },
+        OrderParams {
+            order_type: OrderType::TriggerMarket,
+            direction: PositionDirection::Short,
+            base_asset_amount: BASE_PRECISION / 4,
+            trigger_price: 95 * MARK_PRICE_PRECISION,
+            trigger_condition: OrderTriggerCondition::Below,
+            reduce_only: true,
+            market_index: 0,
+            ..OrderParams::default()
+        },
+    )
+    .unwrap();
+
+    let stop_loss_order_id = sim.users[&user_key()].get_last_order_id();
+    let take_profit_order_id = stop_loss_order_id - 1;
+    {
+        let user = &sim.users[&user_key()];
+        let take_profit = user.get_order(take_profit_order_id).unwrap();
+        let stop_loss = user.get_order(stop_loss_order_id).unwrap();
+        assert_eq!(take_profit.oco_order_id, stop_loss_order_id);
+        assert_eq!(stop_loss.oco_order_id, take_profit_order_id);
+        assert_eq!(user.positions[0].open_orders, 2);
+    }
+
+    sim.advance(10, 5);
+    sim.set_oracle_price(oracle_key(), 94 * MARK_PRICE_PRECISION as i64, 0);
+    sim.trigger_order(&user_key(), stop_loss_order_id, &filler_key())
+        .unwrap();
+
+    // triggering alone leaves the take profit in place
+    {
+        let user = &sim.users[&user_key()];
+        assert!(user.get_order(take_profit_order_id).is_some());
+        assert!(user.get_order(stop_loss_order_id).unwrap().triggered);
+        assert_eq!(user.positions[0].open_orders, 2);
+        assert_eq!(user.positions[0].open_asks, -BASE_PRECISION_I128 / 4);
+    }
+
+    sim.advance(10, 5);
+    let (base_asset_amount, _) = sim
+        .fill_order(&user_key(), stop_loss_order_id, &filler_key(), &[])
+        .unwrap();
+    assert_eq!(base_asset_amount, BASE_PRECISION / 4);
+
+    let user = &sim.users[&user_key()];
+    assert!(user.get_order(take_profit_order_id).is_none());
+    assert!(user.get_order(stop_loss_order_id).is_none());
+    assert_eq!(
+        user.positions[0].base_asset_amount,
+        BASE_PRECISION_I128 * 3 / 4
+    );
+    assert_eq!(user.positions[0].open_orders, 0);
+    assert_eq!(user.positions[0].open_asks, 0);
+}
+
+#[test]
+fn canceling_oco_order_unlinks_the_other_order() {
+    let mut sim = get_simulator();
+
+    sim.place_order(
+        &user_key(),
+        OrderParams {
+            order_type: OrderType::Market,
+            direction: PositionDirection::Long,
+            base_asset_amount: BASE_PRECISION,
+            price: 110 * MARK_PRICE_PRECISION,
+            market_index: 0,
+            ..OrderParams::default()
+        },
+    )
+    .unwrap();
+    let order_id = sim.users[&user_key()].get_last_order_id();
+    sim.advance(10, 5);
+    sim.fill_order(&user_key(), order_id, &filler_key(), &[])
+        .unwrap();
+
+    sim.place_oco_orders(
+        &user_key(),
+        OrderParams {
+            order_type: OrderType::TriggerLimit,
+            direction: PositionDirection::Short,
+            base_asset_amount: BASE_PRECISION,
+            price: 110 * MARK_PRICE_PRECISION,
+            trigger_price: 110 * MARK_PRICE_PRECISION,
+            trigger_condition: OrderTriggerCondition::Above,
+            reduce_only: true,
+            market_index: 0,
+            ..OrderParams::default()
+        },
+        OrderParams {
+            order_type: OrderType::TriggerMarket,
+            direction: PositionDirection::Short,
+            base_asset_amount: BASE_PRECISION,
+            trigger_price: 95 * MARK_PRICE_PRECISION,
+            trigger_condition: OrderTriggerCondition::Below,
+            reduce_only: true,
+            market_index: 0,
+            ..OrderParams::default()
+        },
+    )
+    .unwrap();
+
+    let stop_loss_order_id = sim.users[&user_key()].get_last_order_id();
+    let take_profit_order_id = stop_loss_order_id - 1;
+
+    sim.cancel_order(&user_key(), take_profit_order_id).unwrap();
+
+    let user = &sim.users[&user_key()];
+    assert!(user.get_order(take_profit_order_id).is_none());
+    let stop_loss = user.get_order(stop_loss_order_id).unwrap();
+    assert_eq!(stop_loss.oco_order_id, 0);
+    assert_eq!(user.positions[0].open_orders, 1);
+}
+
+#[test]
+fn trailing_stop_follows_oracle_then_triggers() {
+    let mut sim = get_simulator();
+
+    sim.place_order(
+        &user_key(),
+        OrderParams {
+            order_type: OrderType::TrailingStop,
+            direction: PositionDirection::Short,
+            base_asset_amount: BASE_PRECISION,
+            trailing_stop_offset: 5 * MARK_PRICE_PRECISION,
+            trigger_condition: OrderTriggerCondition::Below,
+            market_index: 0,
+            ..OrderParams::default()
+        },
+    )
+    .unwrap();
+
+    let order_id = sim.users[&user_key()].get_last_order_id();
+    assert_eq!(
+        sim.users[&user_key()]
+            .get_order(order_id)
+            .unwrap()
+            .trigger_price,
+        95 * MARK_PRICE_PRECISION
+    );
+    assert_eq!(sim.users[&user_key()].positions[0].open_asks, 0);
+
+    sim.advance(10, 5);
+    sim.set_oracle_price(oracle_key(), 110 * MARK_PRICE_PRECISION as i64, 0);
+    sim.trigger_order(&user_key(), order_id, &filler_key())
+        .unwrap();
+
+    {
+        let order = sim.users[&user_key()].get_order(order_id).unwrap();
+        assert_eq!(order.trigger_price, 105 * MARK_PRICE_PRECISION);
+        assert!(!order.triggered);
+
+        // keeper gets the flat reward for moving the trigger price
+        let cancel_order_fee = sim.state.fee_structure.cancel_order_fee as i128;
+        assert_eq!(
+            sim.users[&filler_key()].positions[0].quote_asset_amount,
+            cancel_order_fee
+        );
+        assert_eq!(
+            sim.users[&user_key()].positions[0].quote_asset_amount,
+            -cancel_order_fee
+        );
+    }
+
+    // oracle moved against the stop but not through it, trigger price must not move
+    sim.advance(10, 5);
+    sim.set_oracle_price(oracle_key(), 106 * MARK_PRICE_PRECISION as i64, 0);
+    assert_eq!(
+        sim.trigger_order(&user_key(), order_id, &filler_key()),
+        Err(ErrorCode::OrderDidNotSatisfyTriggerCondition)
+    );
+
+    sim.advance(10, 5);
+    sim.set_oracle_price(oracle_key(), 104 * MARK_PRICE_PRECISION as i64, 0);
+    sim.trigger_order(&user_key(), order_id, &filler_key())
+        .unwrap();
+
+    let user = &sim.users[&user_key()];
+    let order = user.get_order(order_id).unwrap();
+    assert!(order.triggered);
+    assert_eq!(order.trigger_price, 105 * MARK_PRICE_PRECISION);
+    assert_eq!(user.positions[0].open_asks, -BASE_PRECISION_I128);
+}
+
+#[test]
+fn keeper_expires_order_past_max_ts() {
+    let mut sim = get_simulator();
+    sim.state.fee_structure.cancel_order_fee = 10_000;
+
+    assert_eq!(
+        sim.place_order(
+            &user_key(),
+            OrderParams {
+                order_type: OrderType::Limit,
+                direction: PositionDirection::Long,
+                base_asset_amount: BASE_PRECISION,
+                price: 90 * MARK_PRICE_PRECISION,
+                market_index: 0,
+                max_ts: Some(0),
+                ..OrderParams::default()
+            },
+        ),
+        Err(ErrorCode::InvalidOrder)
+    );
+
+    sim.place_order(
+        &user_key(),
+        OrderParams {
+            order_type: OrderType::Limit,
+            direction: PositionDirection::Long,
+            base_asset_amount: BASE_PRECISION,
+            price: 90 * MARK_PRICE_PRECISION,
+            market_index: 0,
+            max_ts: Some(60),
+            ..OrderParams::default()
+        },
+    )
+    .unwrap();
+    let order_id = sim.users[&user_key()].get_last_order_id();
+
+    assert_eq!(
+        sim.expire_order(&user_key(), order_id, &filler_key()),
+        Err(ErrorCode::OrderNotExpired)
+    );
+
+    sim.advance(150, 61);
+    sim.expire_order(&user_key(), order_id, &filler_key())
+        .unwrap();
+
+    let user = &sim.users[&user_key()];
+    assert!(user.get_order(order_id).is_none());
+    assert_eq!(user.positions[0].open_orders, 0);
+    assert_eq!(user.positions[0].open_bids, 0);
+    assert_eq!(user.positions[0].quote_asset_amount, -10_000);
+
+    let filler = &sim.users[&filler_key()];
+    assert_eq!(filler.positions[0].quote_asset_amount, 10_000);
+}
+
+#[test]
+fn fill_order_cancels_order_past_max_ts() {
+    let mut sim = get_simulator();
+
+    sim.place_order(
+        &user_key(),
+        OrderParams {
+            order_type: OrderType::Limit,
+            direction: PositionDirection::Long,
+            base_asset_amount: BASE_PRECISION,
+            price: 110 * MARK_PRICE_PRECISION,
+            market_index: 0,
+            max_ts: Some(60),
+            ..OrderParams::default()
+        },
+    )
+    .unwrap();
+    let order_id = sim.users[&user_key()].get_last_order_id();
+
+    sim.advance(150, 61);
+    sim.set_oracle_price(oracle_key(), 100 * MARK_PRICE_PRECISION as i64, 0);
+
+    let (base_asset_amount, updated_user_state) = sim
+        .fill_order(&user_key(), order_id, &filler_key(), &[])
+        .unwrap();
+
+    assert_eq!(base_asset_amount, 0);
+    assert!(updated_user_state);
+
+    let user = &sim.users[&user_key()];
+    assert!(user.get_order(order_id).is_none());
+    assert_eq!(user.positions[0].base_asset_amount, 0);
+    assert_eq!(user.positions[0].open_orders, 0);
+}
+
+fn get_ladder(direction: PositionDirection, prices: &[u128]) -> Vec<OrderParams> {
+    prices
+        .iter()
+        .map(|price| OrderParams {
+            order_type: OrderType::Limit,
+            direction,
+            base_asset_amount: 2 * BASE_PRECISION,
+            price: price * MARK_PRICE_PRECISION,
+            market_index: 0,
+            ..OrderParams::default()
+        })
+        .collect()
+}
+
+#[test]
+fn place_orders_then_cancel_by_direction() {
+    let mut sim = get_simulator();
+
+    let mut params = get_ladder(PositionDirection::Long, &[98, 97]);
+    params.extend(get_ladder(PositionDirection::Short, &[102, 103]));
+    sim.place_orders(&user_key(), params).unwrap();
+
+    {
+        let position = &sim.users[&user_key()].positions[0];
+        assert_eq!(position.open_orders, 4);
+        assert_eq!(position.open_bids, 4 * BASE_PRECISION_I128);
+        assert_eq!(position.open_asks, -4 * BASE_PRECISION_I128);
+    }
+
+    sim.cancel_orders(&user_key(), Some(0), Some(PositionDirection::Long))
+        .unwrap();
+
+    {
+        let position = &sim.users[&user_key()].positions[0];
+        assert_eq!(position.open_orders, 2);
+        assert_eq!(position.open_bids, 0);
+        assert_eq!(position.open_asks, -4 * BASE_PRECISION_I128);
+    }
+
+    sim.cancel_orders(&user_key(), None, None).unwrap();
+
+    let position = &sim.users[&user_key()].positions[0];
+    assert_eq!(position.open_orders, 0);
+    assert_eq!(position.open_asks, 0);
+}
+
+#[test]
+fn cancel_and_place_checks_margin_after_all_orders() {
+    let mut sim = get_simulator();
+
+    sim.place_orders(
+        &user_key(),
+        get_ladder(PositionDirection::Long, &[90, 89, 88, 87]),
+    )
+    .unwrap();
+
+    let user_before = sim.users[&user_key()];
+    assert_eq!(
+        sim.place_orders(
+            &user_key(),
+            get_ladder(PositionDirection::Long, &[86, 85, 84, 83]),
+        ),
+        Err(ErrorCode::InsufficientCollateral)
+    );
+    assert_eq!(sim.users[&user_key()], user_before);
+
+    // replacing the whole ladder does not increase risk, even though placing it on top would
+    sim.cancel_and_place(
+        &user_key(),
+        Some(0),
+        Some(PositionDirection::Long),
+        get_ladder(PositionDirection::Long, &[91, 90, 89, 88]),
+    )
+    .unwrap();
+
+    let user = &sim.users[&user_key()];
+    assert_eq!(user.positions[0].open_orders, 4);
+    assert_eq!(user.positions[0].open_bids, 8 * BASE_PRECISION_I128);
+    assert!(user
+        .orders
+        .iter()
+        .filter(|order| order.status == OrderStatus::Open)
+        .all(|order| order.price >= 88 * MARK_PRICE_PRECISION));
+}
+
+#[test]
+fn modify_order_keeps_order_id_and_updates_open_bids() {
+    let mut sim = get_simulator();
+
+    sim.place_order(
+        &user_key(),
+        OrderParams {
+            order_type: OrderType::Limit,
+            direction: PositionDirection::Long,
+            base_asset_amount: BASE_PRECISION,
+            price: 90 * MARK_PRICE_PRECISION,
+            market_index: 0,
+            ..OrderParams::default()
+        },
+    )
+    .unwrap();
+    let order_id = sim.users[&user_key()].get_last_order_id();
+    let order_slot = sim.users[&user_key()].get_order(order_id).unwrap().slot;
+
+    sim.advance(10, 5);
+    sim.modify_order(
+        &user_key(),
+        order_id,
+        ModifyOrderParams {
+            base_asset_amount: Some(3 * BASE_PRECISION),
+            price: Some(91 * MARK_PRICE_PRECISION),
+        },
+    )
+    .unwrap();
+
+    {
+        let user = &sim.users[&user_key()];
+        let order = user.get_order(order_id).unwrap();
+        assert_eq!(order.base_asset_amount, 3 * BASE_PRECISION);
+        assert_eq!(order.price, 91 * MARK_PRICE_PRECISION);
+        assert_eq!(order.slot, order_slot);
+        assert_eq!(user.get_last_order_id(), order_id);
+        assert_eq!(user.positions[0].open_orders, 1);
+        assert_eq!(user.positions[0].open_bids, 3 * BASE_PRECISION_I128);
+    }
+
+    let user_before = sim.users[&user_key()];
+    assert_eq!(
+        sim.modify_order(
+            &user_key(),
+            order_id,
+            ModifyOrderParams {
+                base_asset_amount: Some(100 * BASE_PRECISION),
+                price: None,
+            },
+        ),
+        Err(ErrorCode::InsufficientCollateral)
+    );
+    assert_eq!(
+        sim.modify_order(
+            &user_key(),
+            order_id,
+            ModifyOrderParams {
+                base_asset_amount: None,
+                price: Some(0),
+            },
+        ),
+        Err(ErrorCode::InvalidOrder)
+    );
+    assert_eq!(sim.users[&user_key()], user_before);
+}
+
+#[test]
+fn iceberg_order_fills_one_tranche_at_a_time() {
+    let mut sim = get_simulator();
+
+    let result = sim.place_order(
+        &user_key(),
+        OrderParams {
+            order_type: OrderType::Limit,
+            direction: PositionDirection::Long,
+            base_asset_amount: BASE_PRECISION,
+            price: 110 * MARK_PRICE_PRECISION,
+            market_index: 0,
+            iceberg_display_amount: Some(BASE_PRECISION),
+            ..OrderParams::default()
+        },
+    );
+    assert_eq!(result, Err(ErrorCode::InvalidOrder));
+
+    sim.place_order(
+        &user_key(),
+        OrderParams {
+            order_type: OrderType::Limit,
+            direction: PositionDirection::Long,
+            base_asset_amount: 3 * BASE_PRECISION / 10,
+            price: 110 * MARK_PRICE_PRECISION,
+            market_index: 0,
+            iceberg_display_amount: Some(BASE_PRECISION / 10),
+            ..OrderParams::default()
+        },
+    )
+    .unwrap();
+
+    let order_id = sim.users[&user_key()].get_last_order_id();
+
+    // hidden size still counts toward open bids
+    assert_eq!(
+        sim.users[&user_key()].positions[0].open_bids,
+        3 * BASE_PRECISION_I128 / 10
+    );
+
+    sim.advance(10, 5);
+
+    let (base_asset_amount, _) = sim
+        .fill_order(&user_key(), order_id, &filler_key(), &[])
+        .unwrap();
+    assert_eq!(base_asset_amount, BASE_PRECISION / 10);
+
+    let user = &sim.users[&user_key()];
+    let order = user.get_order(order_id).unwrap();
+    assert_eq!(order.status, OrderStatus::Open);
+    assert_eq!(order.base_asset_amount_filled, BASE_PRECISION / 10);
+    assert_eq!(order.iceberg_visible_amount, BASE_PRECISION / 10);
+    assert_eq!(
+        user.positions[0].base_asset_amount,
+        BASE_PRECISION_I128 / 10
+    );
+
+    sim.advance(1, 1);
+    sim.fill_order(&user_key(), order_id, &filler_key(), &[])
+        .unwrap();
+    sim.advance(1, 1);
+    sim.fill_order(&user_key(), order_id, &filler_key(), &[])
+        .unwrap();
+
+    let user = &sim.users[&user_key()];
+    assert_eq!(
+        user.positions[0].base_asset_amount,
+        3 * BASE_PRECISION_I128 / 10
+    );
+    assert_eq!(user.positions[0].open_orders, 0);
+    assert_eq!(user.positions[0].open_bids, 0);
+}
+
+#[test]
+fn fill_order_with_duplicate_makers_and_shared_user_stats() {
+    let mut sim = get_simulator();
+
+    // two sub accounts of the same authority share one user stats account
+    let maker_authority = Pubkey::from_str("My11111111111111111111111111111111111111116").unwrap();
+    let maker_keys = [
+        Pubkey::from_str("My11111111111111111111111111111111111111114").unwrap(),
+        Pubkey::from_str("My11111111111111111111111111111111111111115").unwrap(),
+    ];
+    let maker_prices = [101 * MARK_PRICE_PRECISION, 1005 * MARK_PRICE_PRECISION / 10];
+
+    for (maker_key, maker_price) in maker_keys.iter().zip(maker_prices.iter()) {
+        sim.add_user(
+            *maker_key,
+            User {
+                authority: maker_authority,
+                bank_balances: get_bank_balances(UserBankBalance {
+                    bank_index: 0,
+                    balance_type: BankBalanceType::Deposit,
+                    balance: 100 * BANK_INTEREST_PRECISION,
+                }),
+                next_order_id: 1,
+                ..User::default()
+            },
+        );
+
+        sim.place_order(
+            maker_key,
+            OrderParams {
+                order_type: OrderType::Limit,
+                direction: PositionDirection::Short,
+                base_asset_amount: BASE_PRECISION / 10,
+                price: *maker_price,
+                market_index: 0,
+                post_only: true,
+                ..OrderParams::default()
+            },
+        )
+        .unwrap();
+    }
+
+    sim.advance(1, 1);
+
+    sim.place_order(
+        &user_key(),
+        OrderParams {
+            order_type: OrderType::Limit,
+            direction: PositionDirection::Long,
+            base_asset_amount: 3 * BASE_PRECISION / 10,
+            price: 1015 * MARK_PRICE_PRECISION / 10,
+            market_index: 0,
+            ..OrderParams::default()
+        },
+    )
+    .unwrap();
+    let order_id = sim.users[&user_key()].get_last_order_id();
+
+    // the repeated maker is skipped instead of failing the fill with a double borrow
+    let (base_asset_amount, _) = sim
+        .fill_order(
+            &user_key(),
+            order_id,
+            &filler_key(),
+            &[
+                (&maker_keys[0], 1),
+                (&maker_keys[1], 1),
+                (&maker_keys[0], 1),
+            ],
+        )
+        .unwrap();
+    assert_eq!(base_asset_amount, 2 * BASE_PRECISION / 10);
+
+    for maker_key in maker_keys.iter() {
+        let maker = &sim.users[maker_key];
+        assert_eq!(
+            maker.positions[0].base_asset_amount,
+            -BASE_PRECISION_I128 / 10
+        );
+        assert_eq!(maker.positions[0].open_orders, 0);
+    }
+
+    // both fills count toward the shared maker volume
+    assert!(
+        sim.user_stats[&maker_authority].maker_volume_30d > (101 * QUOTE_PRECISION / 10) as u64
+    );
+}
+
+#[test]
+fn iceberg_taker_fill_capped_at_display_amount() {
+    let mut sim = get_simulator();
+
+    let maker_keys = [
+        Pubkey::from_str("My11111111111111111111111111111111111111114").unwrap(),
+        Pubkey::from_str("My11111111111111111111111111111111111111115").unwrap(),
+    ];
+    let maker_prices = [101 * MARK_PRICE_PRECISION, 1005 * MARK_PRICE_PRECISION / 10];
+
+    for (maker_key, maker_price) in maker_keys.iter().zip(maker_prices.iter()) {
+        sim.add_user(
+            *maker_key,
+            User {
+                authority: *maker_key,
+                bank_balances: get_bank_balances(UserBankBalance {
+                    bank_index: 0,
+                    balance_type: BankBalanceType::Deposit,
+                    balance: 100 * BANK_INTEREST_PRECISION,
+                }),
+                next_order_id: 1,
+                ..User::default()
+            },
+        );
+
+        sim.place_order(
+            maker_key,
+            OrderParams {
+                order_type: OrderType::Limit,
+                direction: PositionDirection::Short,
+                base_asset_amount: BASE_PRECISION / 10,
+                price: *maker_price,
+                market_index: 0,
+                post_only: true,
+                ..OrderParams::default()
+            },
+        )
+        .unwrap();
+    }
+
+    sim.advance(1, 1);
+
+    sim.place_order(
+        &user_key(),
+        OrderParams {
+            order_type: OrderType::Limit,
+            direction: PositionDirection::Long,
+            base_asset_amount: 3 * BASE_PRECISION / 10,
+            price: 1015 * MARK_PRICE_PRECISION / 10,
+            market_index: 0,
+            iceberg_display_amount: Some(BASE_PRECISION / 10),
+            ..OrderParams::default()
+        },
+    )
+    .unwrap();
+    let order_id = sim.users[&user_key()].get_last_order_id();
+
+    // enough maker liquidity for two tranches, but one fill only takes the visible tranche
+    let (base_asset_amount, _) = sim
+        .fill_order(
+            &user_key(),
+            order_id,
+            &filler_key(),
+            &[(&maker_keys[0], 1), (&maker_keys[1], 1)],
+        )
+        .unwrap();
+    assert_eq!(base_asset_amount, BASE_PRECISION / 10);
+
+    let user = &sim.users[&user_key()];
+    let order = user.get_order(order_id).unwrap();
+    assert_eq!(order.base_asset_amount_filled, BASE_PRECISION / 10);
+    assert_eq!(order.iceberg_visible_amount, BASE_PRECISION / 10);
+    assert_eq!(
+        user.positions[0].base_asset_amount,
+        BASE_PRECISION_I128 / 10
+    );
+    assert_eq!(sim.users[&maker_keys[0]].positions[0].base_asset_amount, 0);
+
+    // next tranche fills on the next instruction
+    sim.advance(1, 1);
+    let (base_asset_amount, _) = sim
+        .fill_order(&user_key(), order_id, &filler_key(), &[(&maker_keys[0], 1)])
+        .unwrap();
+    assert_eq!(base_asset_amount, BASE_PRECISION / 10);
+}
+
+#[test]
+fn twap_order_fills_one_slice_per_interval() {
+    let mut sim = get_simulator();
+
+    sim.place_order(
+        &user_key(),
+        OrderParams {
+            order_type: OrderType::Twap,
+            direction: PositionDirection::Long,
+            base_asset_amount: 4 * BASE_PRECISION / 10,
+            market_index: 0,
+            auction_duration: 2,
+            twap_slices: 4,
+            twap_slot_interval: 10,
+            ..OrderParams::default()
+        },
+    )
+    .unwrap();
+
+    let order_id = sim.users[&user_key()].get_last_order_id();
+
+    sim.advance(3, 1);
+    let (base_asset_amount, _) = sim
+        .fill_order(&user_key(), order_id, &filler_key(), &[])
+        .unwrap();
+    assert_eq!(base_asset_amount, BASE_PRECISION / 10);
+
+    // next slice not released yet
+    let (base_asset_amount, _) = sim
+        .fill_order(&user_key(), order_id, &filler_key(), &[])
+        .unwrap();
+    assert_eq!(base_asset_amount, 0);
+
+    // releasing a slice starts a new auction
+    sim.advance(7, 3);
+    let (base_asset_amount, _) = sim
+        .fill_order(&user_key(), order_id, &filler_key(), &[])
+        .unwrap();
+    assert_eq!(base_asset_amount, 0);
+
+    let order = sim.users[&user_key()].get_order(order_id).unwrap();
+    assert_eq!(order.slot, 10);
+    assert_eq!(order.twap_start_slot, 0);
+    assert_eq!(order.status, OrderStatus::Open);
+
+    sim.advance(3, 1);
+    sim.fill_order(&user_key(), order_id, &filler_key(), &[])
+        .unwrap();
+
+    let order = sim.users[&user_key()].get_order(order_id).unwrap();
+    assert_eq!(order.base_asset_amount_filled, 2 * BASE_PRECISION / 10);
+
+    // missed slices are caught up in the next auction
+    sim.advance(30, 10);
+    sim.fill_order(&user_key(), order_id, &filler_key(), &[])
+        .unwrap();
+    sim.advance(3, 1);
+    sim.fill_order(&user_key(), order_id, &filler_key(), &[])
+        .unwrap();
+
+    let user = &sim.users[&user_key()];
+    assert_eq!(
+        user.positions[0].base_asset_amount,
+        4 * BASE_PRECISION_I128 / 10
+    );
+    assert_eq!(user.positions[0].open_orders, 0);
+    assert_eq!(user.positions[0].open_bids, 0);
+}
+
+#[test]
+fn self_trade_prevention_across_user_accounts() {
+    let mut sim = get_simulator();
+
+    let sub_account_key = Pubkey::from_str("My11111111111111111111111111111111111111113").unwrap();
+    sim.add_user(
+        sub_account_key,
+        User {
+            authority: user_key(),
+            bank_balances: get_bank_balances(UserBankBalance {
+                bank_index: 0,
+                balance_type: BankBalanceType::Deposit,
+                balance: 100 * BANK_INTEREST_PRECISION,
+            }),
+            next_order_id: 1,
+            ..User::default()
+        },
+    );
+
+    sim.place_order(
+        &sub_account_key,
+        OrderParams {
+            order_type: OrderType::Limit,
+            direction: PositionDirection::Short,
+            base_asset_amount: 3 * BASE_PRECISION / 10,
+            price: 100 * MARK_PRICE_PRECISION,
+            market_index: 0,
+            post_only: true,
+            ..OrderParams::default()
+        },
+    )
+    .unwrap();
+    let maker_order_id = sim.users[&sub_account_key].get_last_order_id();
+
+    sim.advance(1, 1);
+
+    sim.place_order(
+        &user_key(),
+        OrderParams {
+            order_type: OrderType::Limit,
+            direction: PositionDirection::Long,
+            base_asset_amount: BASE_PRECISION / 10,
+            price: 101 * MARK_PRICE_PRECISION,
+            market_index: 0,
+            self_trade_prevention: SelfTradePrevention::DecrementAndCancel,
+            ..OrderParams::default()
+        },
+    )
+    .unwrap();
+    let taker_order_id = sim.users[&user_key()].get_last_order_id();
+
+    let (base_asset_amount, _) = sim
+        .fill_order(
+            &user_key(),
+            taker_order_id,
+            &filler_key(),
+            &[(&sub_account_key, maker_order_id)],
+        )
+        .unwrap();
+    assert_eq!(base_asset_amount, 0);
+
+    // taker is canceled, maker is reduced by the taker size
+    let user = &sim.users[&user_key()];
+    assert_eq!(user.positions[0].base_asset_amount, 0);
+    assert_eq!(user.positions[0].open_orders, 0);
+    assert_eq!(user.positions[0].open_bids, 0);
+
+    let maker = &sim.users[&sub_account_key];
+    let maker_order = maker.get_order(maker_order_id).unwrap();
+    assert_eq!(maker_order.status, OrderStatus::Open);
+    assert_eq!(maker_order.base_asset_amount, 2 * BASE_PRECISION / 10);
+    assert_eq!(maker.positions[0].open_asks, -2 * BASE_PRECISION_I128 / 10);
+
+    sim.place_order(
+        &user_key(),
+        OrderParams {
+            order_type: OrderType::Limit,
+            direction: PositionDirection::Long,
+            base_asset_amount: BASE_PRECISION / 10,
+            price: 101 * MARK_PRICE_PRECISION,
+            market_index: 0,
+            self_trade_prevention: SelfTradePrevention::CancelMaker,
+            ..OrderParams::default()
+        },
+    )
+    .unwrap();
+    let taker_order_id = sim.users[&user_key()].get_last_order_id();
+
+    sim.fill_order(
+        &user_key(),
+        taker_order_id,
+        &filler_key(),
+        &[(&sub_account_key, maker_order_id)],
+    )
+    .unwrap();
+
+    let maker = &sim.users[&sub_account_key];
+    assert_eq!(maker.positions[0].open_orders, 0);
+    assert_eq!(maker.positions[0].open_asks, 0);
+    let order = sim.users[&user_key()].get_order(taker_order_id).unwrap();
+    assert_eq!(order.status, OrderStatus::Open);
+}
+
+#[test]
+fn self_trade_decrement_cancels_order_left_with_dust() {
+    let mut sim = get_simulator();
+
+    let sub_account_key = Pubkey::from_str("My11111111111111111111111111111111111111113").unwrap();
+    sim.add_user(
+        sub_account_key,
+        User {
+            authority: user_key(),
+            bank_balances: get_bank_balances(UserBankBalance {
+                bank_index: 0,
+                balance_type: BankBalanceType::Deposit,
+                balance: 100 * BANK_INTEREST_PRECISION,
+            }),
+            next_order_id: 1,
+            ..User::default()
+        },
+    );
+
+    let base_asset_amount_step_size = sim.markets[&0].amm.base_asset_amount_step_size;
+    sim.place_order(
+        &sub_account_key,
+        OrderParams {
+            order_type: OrderType::Limit,
+            direction: PositionDirection::Short,
+            base_asset_amount: BASE_PRECISION / 10 + base_asset_amount_step_size,
+            price: 100 * MARK_PRICE_PRECISION,
+            market_index: 0,
+            post_only: true,
+            ..OrderParams::default()
+        },
+    )
+    .unwrap();
+    let maker_order_id = sim.users[&sub_account_key].get_last_order_id();
+
+    // a larger step size leaves the maker remainder unfillable
+    sim.markets
+        .get_mut(&0)
+        .unwrap()
+        .amm
+        .base_asset_amount_step_size = 10 * base_asset_amount_step_size;
+
+    sim.advance(1, 1);
+
+    sim.place_order(
+        &user_key(),
+        OrderParams {
+            order_type: OrderType::Limit,
+            direction: PositionDirection::Long,
+            base_asset_amount: BASE_PRECISION / 10,
+            price: 101 * MARK_PRICE_PRECISION,
+            market_index: 0,
+            self_trade_prevention: SelfTradePrevention::DecrementAndCancel,
+            ..OrderParams::default()
+        },
+    )
+    .unwrap();
+    let taker_order_id = sim.users[&user_key()].get_last_order_id();
+
+    let (base_asset_amount, _) = sim
+        .fill_order(
+            &user_key(),
+            taker_order_id,
+            &filler_key(),
+            &[(&sub_account_key, maker_order_id)],
+        )
+        .unwrap();
+    assert_eq!(base_asset_amount, 0);
+
+    let maker = &sim.users[&sub_account_key];
+    assert!(maker.get_order(maker_order_id).is_none());
+    assert_eq!(maker.positions[0].open_orders, 0);
+    assert_eq!(maker.positions[0].open_asks, 0);
+}
+
+#[test]
+fn self_trade_decrement_cancels_twap_order() {
+    let mut sim = get_simulator();
+
+    let sub_account_key = Pubkey::from_str("My11111111111111111111111111111111111111113").unwrap();
+    sim.add_user(
+        sub_account_key,
+        User {
+            authority: user_key(),
+            bank_balances: get_bank_balances(UserBankBalance {
+                bank_index: 0,
+                balance_type: BankBalanceType::Deposit,
+                balance: 100 * BANK_INTEREST_PRECISION,
+            }),
+            next_order_id: 1,
+            ..User::default()
+        },
+    );
+
+    sim.place_order(
+        &sub_account_key,
+        OrderParams {
+            order_type: OrderType::Limit,
+            direction: PositionDirection::Short,
+            base_asset_amount: BASE_PRECISION / 10,
+            price: 101 * MARK_PRICE_PRECISION,
+            market_index: 0,
+            post_only: true,
+            ..OrderParams::default()
+        },
+    )
+    .unwrap();
+    let maker_order_id = sim.users[&sub_account_key].get_last_order_id();
+
+    sim.place_order(
+        &user_key(),
+        OrderParams {
+            order_type: OrderType::Twap,
+            direction: PositionDirection::Long,
+            base_asset_amount: 4 * BASE_PRECISION / 10,
+            market_index: 0,
+            auction_duration: 2,
+            twap_slices: 4,
+            twap_slot_interval: 10,
+            self_trade_prevention: SelfTradePrevention::DecrementAndCancel,
+            ..OrderParams::default()
+        },
+    )
+    .unwrap();
+    let taker_order_id = sim.users[&user_key()].get_last_order_id();
+
+    sim.advance(3, 1);
+    let (base_asset_amount, _) = sim
+        .fill_order(
+            &user_key(),
+            taker_order_id,
+            &filler_key(),
+            &[(&sub_account_key, maker_order_id)],
+        )
+        .unwrap();
+    assert_eq!(base_asset_amount, 0);
+
+    // shrinking the twap order would change the size of the slices already released
+    let user = &sim.users[&user_key()];
+    assert!(user.get_order(taker_order_id).is_none());
+    assert_eq!(user.positions[0].open_orders, 0);
+    assert_eq!(user.positions[0].open_bids, 0);
+
+    let maker = &sim.users[&sub_account_key];
+    assert!(maker.get_order(maker_order_id).is_none());
+    assert_eq!(maker.positions[0].open_asks, 0);
+}
+
+#[test]
+fn fill_order_sweeps_makers_best_price_first() {
+    let mut sim = get_simulator();
+
+    let maker_keys = [
+        Pubkey::from_str("My11111111111111111111111111111111111111114").unwrap(),
+        Pubkey::from_str("My11111111111111111111111111111111111111115").unwrap(),
+    ];
+    let maker_prices = [101 * MARK_PRICE_PRECISION, 1005 * MARK_PRICE_PRECISION / 10];
+
+    for (maker_key, maker_price) in maker_keys.iter().zip(maker_prices.iter()) {
+        sim.add_user(
+            *maker_key,
+            User {
+                authority: *maker_key,
+                bank_balances: get_bank_balances(UserBankBalance {
+                    bank_index: 0,
+                    balance_type: BankBalanceType::Deposit,
+                    balance: 100 * BANK_INTEREST_PRECISION,
+                }),
+                next_order_id: 1,
+                ..User::default()
+            },
+        );
+
+        sim.place_order(
+            maker_key,
+            OrderParams {
+                order_type: OrderType::Limit,
+                direction: PositionDirection::Short,
+                base_asset_amount: BASE_PRECISION / 10,
+                price: *maker_price,
+                market_index: 0,
+                post_only: true,
+                ..OrderParams::default()
+            },
+        )
+        .unwrap();
+    }
+
+    sim.advance(1, 1);
+
+    sim.place_order(
+        &user_key(),
+        OrderParams {
+            order_type: OrderType::Limit,
+            direction: PositionDirection::Long,
+            base_asset_amount: 15 * BASE_PRECISION / 100,
+            price: 1015 * MARK_PRICE_PRECISION / 10,
+            market_index: 0,
+            ..OrderParams::default()
+        },
+    )
+    .unwrap();
+    let order_id = sim.users[&user_key()].get_last_order_id();
+
+    // makers passed worst price first
+    let (base_asset_amount, _) = sim
+        .fill_order(
+            &user_key(),
+            order_id,
+            &filler_key(),
+            &[(&maker_keys[0], 1), (&maker_keys[1], 1)],
+        )
+        .unwrap();
+    assert_eq!(base_asset_amount, 15 * BASE_PRECISION / 100);
+
+    let user = &sim.users[&user_key()];
+    assert_eq!(
+        user.positions[0].base_asset_amount,
+        15 * BASE_PRECISION_I128 / 100
+    );
+    assert_eq!(user.positions[0].open_orders, 0);
+
+    let expensive_maker = &sim.users[&maker_keys[0]];
+    assert_eq!(
+        expensive_maker.positions[0].base_asset_amount,
+        -5 * BASE_PRECISION_I128 / 100
+    );
+    assert_eq!(expensive_maker.positions[0].open_orders, 1);
+
+    let cheap_maker = &sim.users[&maker_keys[1]];
+    assert_eq!(
+        cheap_maker.positions[0].base_asset_amount,
+        -BASE_PRECISION_I128 / 10
+    );
+    assert_eq!(cheap_maker.positions[0].open_orders, 0);
+}
+
+#[test]
+fn post_only_slide_reprices_inside_amm() {
+    let mut sim = get_simulator();
+
+    let params = OrderParams {
+        order_type: OrderType::Limit,
+        direction: PositionDirection::Long,
+        base_asset_amount: BASE_PRECISION / 10,
+        price: 101 * MARK_PRICE_PRECISION,
+        market_index: 0,
+        post_only: true,
+        ..OrderParams::default()
+    };
+
+    let result = sim.place_order(&user_key(), params.clone());
+    assert_eq!(result, Err(ErrorCode::InvalidOrder));
+
+    sim.place_order(
+        &user_key(),
+        OrderParams {
+            post_only_slide: true,
+            ..params
+        },
+    )
+    .unwrap();
+
+    let order = sim.users[&user_key()].orders[0];
+    assert_eq!(order.status, OrderStatus::Open);
+    assert_eq!(
+        order.price,
+        100 * MARK_PRICE_PRECISION - MARK_PRICE_PRECISION / 100
+    );
+    assert!(order.post_only);
+}
+
+#[test]
+fn fill_or_kill_reverts_partial_fill() {
+    let mut sim = get_simulator();
+
+    let maker_key = Pubkey::from_str("My11111111111111111111111111111111111111114").unwrap();
+    sim.add_user(
+        maker_key,
+        User {
+            authority: maker_key,
+            bank_balances: get_bank_balances(UserBankBalance {
+                bank_index: 0,
+                balance_type: BankBalanceType::Deposit,
+                balance: 100 * BANK_INTEREST_PRECISION,
+            }),
+            next_order_id: 1,
+            ..User::default()
+        },
+    );
+
+    sim.place_order(
+        &maker_key,
+        OrderParams {
+            order_type: OrderType::Limit,
+            direction: PositionDirection::Short,
+            base_asset_amount: BASE_PRECISION / 10,
+            price: 101 * MARK_PRICE_PRECISION,
+            market_index: 0,
+            post_only: true,
+            ..OrderParams::default()
+        },
+    )
+    .unwrap();
+    let maker_order_id = sim.users[&maker_key].get_last_order_id();
+
+    sim.advance(1, 1);
+
+    // the amm can't fill during the auction, so only the maker's size is available
+    let maker_before = sim.users[&maker_key];
+    let base_asset_amount = sim
+        .place_and_take(
+            &user_key(),
+            OrderParams {
+                order_type: OrderType::Limit,
+                direction: PositionDirection::Long,
+                base_asset_amount: 2 * BASE_PRECISION / 10,
+                price: 102 * MARK_PRICE_PRECISION,
+                market_index: 0,
+                fill_or_kill: true,
+                ..OrderParams::default()
+            },
+            &[(&maker_key, maker_order_id)],
+        )
+        .unwrap();
+    assert_eq!(base_asset_amount, 0);
+    assert_eq!(sim.users[&maker_key], maker_before);
+
+    let user = &sim.users[&user_key()];
+    assert_eq!(user.get_order(user.get_last_order_id()), None);
+    assert_eq!(user.positions[0].base_asset_amount, 0);
+    assert_eq!(user.positions[0].open_orders, 0);
+    assert_eq!(user.positions[0].open_bids, 0);
+
+    let base_asset_amount = sim
+        .place_and_take(
+            &user_key(),
+            OrderParams {
+                order_type: OrderType::Limit,
+                direction: PositionDirection::Long,
+                base_asset_amount: BASE_PRECISION / 10,
+                price: 102 * MARK_PRICE_PRECISION,
+                market_index: 0,
+                fill_or_kill: true,
+                ..OrderParams::default()
+            },
+            &[(&maker_key, maker_order_id)],
+        )
+        .unwrap();
+    assert_eq!(base_asset_amount, BASE_PRECISION / 10);
+    assert_eq!(
+        sim.users[&user_key()].positions[0].base_asset_amount,
+        BASE_PRECISION_I128 / 10
+    );
+    assert_eq!(
+        sim.users[&maker_key].positions[0].base_asset_amount,
+        -BASE_PRECISION_I128 / 10
+    );
+}
+
+#[test]
+fn fill_or_kill_canceled_when_nothing_fills() {
+    let mut sim = get_simulator();
+
+    let base_asset_amount = sim
+        .place_and_take(
+            &user_key(),
+            OrderParams {
+                order_type: OrderType::Limit,
+                direction: PositionDirection::Long,
+                base_asset_amount: BASE_PRECISION / 10,
+                price: 110 * MARK_PRICE_PRECISION,
+                market_index: 0,
+                fill_or_kill: true,
+                ..OrderParams::default()
+            },
+            &[],
+        )
+        .unwrap();
+    assert_eq!(base_asset_amount, 0);
+
+    let user = &sim.users[&user_key()];
+    let order_id = user.get_last_order_id();
+    assert_eq!(user.get_order(order_id), None);
+    assert_eq!(user.positions[0].open_orders, 0);
+    assert_eq!(user.positions[0].open_bids, 0);
+
+    // the order can't rest and fill once the auction ends
+    sim.advance(10, 5);
+    assert_eq!(
+        sim.fill_order(&user_key(), order_id, &filler_key(), &[]),
+        Err(ErrorCode::OrderDoesNotExist)
+    );
+}
+
+#[test]
+fn quote_sized_order_resizes_at_fill_time() {
+    let mut sim = get_simulator();
+
+    sim.place_order(
+        &user_key(),
+        OrderParams {
+            order_type: OrderType::Market,
+            direction: PositionDirection::Long,
+            quote_asset_amount: Some(50 * QUOTE_PRECISION),
+            price: 110 * MARK_PRICE_PRECISION,
+            market_index: 0,
+            ..OrderParams::default()
+        },
+    )
+    .unwrap();
+    let order_id = sim.users[&user_key()].get_last_order_id();
+
+    let order = sim.users[&user_key()].get_order(order_id).unwrap();
+    let base_asset_amount_at_placement = order.base_asset_amount;
+    assert_eq!(order.quote_asset_amount, 50 * QUOTE_PRECISION);
+    assert_eq!(base_asset_amount_at_placement, 4975120000000);
+
+    // amm moves up before the fill so the quote buys less base
+    sim.markets.get_mut(&0).unwrap().amm.quote_asset_reserve = 101 * AMM_RESERVE_PRECISION;
+    sim.markets.get_mut(&0).unwrap().amm.base_asset_reserve = 99 * AMM_RESERVE_PRECISION;
+    sim.set_oracle_price(oracle_key(), 1020 * MARK_PRICE_PRECISION as i64 / 10, 0);
+
+    sim.advance(10, 5);
+
+    let (base_asset_amount, _) = sim
+        .fill_order(&user_key(), order_id, &filler_key(), &[])
+        .unwrap();
+    assert!(base_asset_amount < base_asset_amount_at_placement);
+
+    let user = &sim.users[&user_key()];
+    assert_eq!(user.positions[0].open_orders, 0);
+    assert_eq!(user.positions[0].open_bids, 0);
+    assert_eq!(
+        user.positions[0].base_asset_amount,
+        base_asset_amount as i128
+    );
+}
+
+#[test]
+fn quote_sized_order_canceled_once_quote_is_spent() {
+    let mut sim = get_simulator();
+
+    sim.place_order(
+        &user_key(),
+        OrderParams {
+            order_type: OrderType::Limit,
+            direction: PositionDirection::Long,
+            quote_asset_amount: Some(50 * QUOTE_PRECISION),
+            price: 110 * MARK_PRICE_PRECISION,
+            market_index: 0,
+            ..OrderParams::default()
+        },
+    )
+    .unwrap();
+    let order_id = sim.users[&user_key()].get_last_order_id();
+
+    // earlier fills used up all but dust of the quote while base is still left on the order
+    let user = sim.users.get_mut(&user_key()).unwrap();
+    let order_index = user
+        .orders
+        .iter()
+        .position(|order| order.order_id == order_id)
+        .unwrap();
+    let base_asset_amount_filled = user.orders[order_index].base_asset_amount / 2;
+    user.orders[order_index].base_asset_amount_filled = base_asset_amount_filled;
+    user.orders[order_index].quote_asset_amount_filled =
+        user.orders[order_index].quote_asset_amount - 1;
+    user.positions[0].open_bids -= base_asset_amount_filled as i128;
+
+    sim.advance(10, 5);
+
+    let (base_asset_amount, updated_user_state) = sim
+        .fill_order(&user_key(), order_id, &filler_key(), &[])
+        .unwrap();
+    assert_eq!(base_asset_amount, 0);
+    assert!(updated_user_state);
+
+    let user = &sim.users[&user_key()];
+    assert_eq!(user.get_order(order_id), None);
+    assert_eq!(user.positions[0].open_orders, 0);
+    assert_eq!(user.positions[0].open_bids, 0);
+    assert_eq!(user.positions[0].base_asset_amount, 0);
+}
+
+#[test]
+fn quote_sized_maker_order_sized_at_limit_price() {
+    let mut sim = get_simulator();
+
+    let maker_key = Pubkey::from_str("My11111111111111111111111111111111111111114").unwrap();
+    sim.add_user(
+        maker_key,
+        User {
+            authority: maker_key,
+            bank_balances: get_bank_balances(UserBankBalance {
+                bank_index: 0,
+                balance_type: BankBalanceType::Deposit,
+                balance: 100 * BANK_INTEREST_PRECISION,
+            }),
+            next_order_id: 1,
+            ..User::default()
+        },
+    );
+
+    sim.place_order(
+        &maker_key,
+        OrderParams {
+            order_type: OrderType::Limit,
+            direction: PositionDirection::Short,
+            quote_asset_amount: Some(50 * QUOTE_PRECISION),
+            price: 100 * MARK_PRICE_PRECISION,
+            market_index: 0,
+            post_only: true,
+            ..OrderParams::default()
+        },
+    )
+    .unwrap();
+    let maker_order_id = sim.users[&maker_key].get_last_order_id();
+    assert_eq!(
+        sim.users[&maker_key]
+            .get_order(maker_order_id)
+            .unwrap()
+            .base_asset_amount,
+        BASE_PRECISION / 2
+    );
+
+    // the maker fills at its own price, so moving the amm doesn't change its size
+    sim.markets.get_mut(&0).unwrap().amm.quote_asset_reserve = 101 * AMM_RESERVE_PRECISION;
+    sim.markets.get_mut(&0).unwrap().amm.base_asset_reserve = 99 * AMM_RESERVE_PRECISION;
+    sim.set_oracle_price(oracle_key(), 1020 * MARK_PRICE_PRECISION as i64 / 10, 0);
+
+    sim.advance(1, 1);
+
+    sim.place_order(
+        &user_key(),
+        OrderParams {
+            order_type: OrderType::Limit,
+            direction: PositionDirection::Long,
+            base_asset_amount: BASE_PRECISION / 10,
+            price: 100 * MARK_PRICE_PRECISION,
+            market_index: 0,
+            ..OrderParams::default()
+        },
+    )
+    .unwrap();
+    let order_id = sim.users[&user_key()].get_last_order_id();
+
+    sim.fill_order(
+        &user_key(),
+        order_id,
+        &filler_key(),
+        &[(&maker_key, maker_order_id)],
+    )
+    .unwrap();
+
+    let maker = &sim.users[&maker_key];
+    let order = maker.get_order(maker_order_id).unwrap();
+    assert_eq!(order.base_asset_amount, BASE_PRECISION / 2);
+    assert_eq!(order.base_asset_amount_filled, BASE_PRECISION / 10);
+    assert_eq!(order.quote_asset_amount_filled, 10 * QUOTE_PRECISION);
+    assert_eq!(maker.positions[0].open_asks, -4 * BASE_PRECISION_I128 / 10);
+}
+
+#[test]
+fn cross_market_trigger_uses_other_oracle() {
+    let mut sim = get_simulator();
+
+    let btc_oracle_key = Pubkey::from_str("GVXRSBjFk6e6J3NbVPXohDJetcTjaeeuykUpbQF8UoMU").unwrap();
+    sim.set_oracle_price(btc_oracle_key, 21000 * MARK_PRICE_PRECISION as i64, 0);
+
+    sim.place_order(
+        &user_key(),
+        OrderParams {
+            order_type: OrderType::TriggerMarket,
+            direction: PositionDirection::Long,
+            base_asset_amount: BASE_PRECISION / 10,
+            trigger_price: 20000 * MARK_PRICE_PRECISION,
+            trigger_condition: OrderTriggerCondition::Below,
+            trigger_oracle: Some(btc_oracle_key),
+            market_index: 0,
+            ..OrderParams::default()
+        },
+    )
+    .unwrap();
+    let order_id = sim.users[&user_key()].get_last_order_id();
+
+    sim.advance(10, 5);
+
+    // own market oracle (100) is below the trigger price but the btc oracle isn't
+    let result = sim.trigger_order(&user_key(), order_id, &filler_key());
+    assert_eq!(result, Err(ErrorCode::OrderDidNotSatisfyTriggerCondition));
+
+    sim.set_oracle_price(btc_oracle_key, 19000 * MARK_PRICE_PRECISION as i64, 0);
+    sim.trigger_order(&user_key(), order_id, &filler_key())
+        .unwrap();
+
+    let user = &sim.users[&user_key()];
+    assert!(user.get_order(order_id).unwrap().triggered);
+    assert_eq!(user.positions[0].open_bids, BASE_PRECISION_I128 / 10);
+
+    let result = sim.place_order(
+        &user_key(),
+        OrderParams {
+            order_type: OrderType::TriggerMarket,
+            direction: PositionDirection::Long,
+            base_asset_amount: BASE_PRECISION / 10,
+            trigger_price: 20000 * MARK_PRICE_PRECISION,
+            trigger_condition: OrderTriggerCondition::Below,
+            trigger_oracle: Some(user_key()),
+            market_index: 0,
+            ..OrderParams::default()
+        },
+    );
+    assert_eq!(result, Err(ErrorCode::OracleNotFound));
+}
+
+#[test]
+fn oracle_relative_auction_tracks_oracle() {
+    let mut sim = get_simulator();
+    sim.markets.get_mut(&0).unwrap().auction_price_curve = AuctionPriceCurve::OracleRelative;
+
+    let params = OrderParams {
+        order_type: OrderType::Market,
+        direction: PositionDirection::Long,
+        base_asset_amount: BASE_PRECISION / 10,
+        market_index: 0,
+        auction_duration: 10,
+        ..OrderParams::default()
+    };
+
+    sim.place_order(&user_key(), params.clone()).unwrap();
+    sim.place_order(
+        &user_key(),
+        OrderParams {
+            auction_price_curve: Some(AuctionPriceCurve::Linear),
+            ..params
+        },
+    )
+    .unwrap();
+
+    let oracle_relative_order = sim.users[&user_key()].orders[0];
+    assert_eq!(
+        oracle_relative_order.auction_price_curve,
+        AuctionPriceCurve::OracleRelative
+    );
+    assert_eq!(
+        oracle_relative_order.auction_start_oracle_price,
+        100 * MARK_PRICE_PRECISION as i128
+    );
+
+    let linear_order = sim.users[&user_key()].orders[1];
+    assert_eq!(linear_order.auction_price_curve, AuctionPriceCurve::Linear);
+
+    let amm = &sim.markets[&0].amm;
+    let slot = oracle_relative_order.slot + 5;
+    let oracle_price = Some(103 * MARK_PRICE_PRECISION as i128);
+    assert_eq!(
+        oracle_relative_order
+            .get_limit_price(amm, oracle_price, slot)
+            .unwrap(),
+        linear_order
+            .get_limit_price(amm, oracle_price, slot)
+            .unwrap()
+            + 3 * MARK_PRICE_PRECISION
+    );
+}
+
+#[test]
+fn match_orders_fills_resting_orders_at_earlier_price() {
+    let mut sim = get_simulator();
+
+    let counterparty_key = Pubkey::from_str("My11111111111111111111111111111111111111114").unwrap();
+    sim.add_user(
+        counterparty_key,
+        User {
+            authority: counterparty_key,
+            bank_balances: get_bank_balances(UserBankBalance {
+                bank_index: 0,
+                balance_type: BankBalanceType::Deposit,
+                balance: 100 * BANK_INTEREST_PRECISION,
+            }),
+            next_order_id: 1,
+            ..User::default()
+        },
+    );
+
+    sim.place_order(
+        &user_key(),
+        OrderParams {
+            order_type: OrderType::Limit,
+            direction: PositionDirection::Long,
+            base_asset_amount: BASE_PRECISION / 10,
+            price: 99 * MARK_PRICE_PRECISION,
+            market_index: 0,
+            post_only: true,
+            ..OrderParams::default()
+        },
+    )
+    .unwrap();
+    let order_id = sim.users[&user_key()].get_last_order_id();
+
+    sim.advance(1, 1);
+
+    let params = OrderParams {
+        order_type: OrderType::Limit,
+        direction: PositionDirection::Short,
+        base_asset_amount: BASE_PRECISION / 10,
+        price: 100 * MARK_PRICE_PRECISION,
+        market_index: 0,
+        ..OrderParams::default()
+    };
+    sim.place_order(&counterparty_key, params.clone()).unwrap();
+    let result = sim.match_orders(&user_key(), order_id, &counterparty_key, 1, &filler_key());
+    assert_eq!(result, Err(ErrorCode::OrdersDoNotCross));
+
+    sim.cancel_order(&counterparty_key, 1).unwrap();
+    sim.place_order(
+        &counterparty_key,
+        OrderParams {
+            price: 98 * MARK_PRICE_PRECISION,
+            ..params
+        },
+    )
+    .unwrap();
+    let counterparty_order_id = sim.users[&counterparty_key].get_last_order_id();
+
+    // the order passed second is still the maker as it rested first
+    let base_asset_amount = sim
+        .match_orders(
+            &counterparty_key,
+            counterparty_order_id,
+            &user_key(),
+            order_id,
+            &filler_key(),
+        )
+        .unwrap();
+    assert_eq!(base_asset_amount, BASE_PRECISION / 10);
+
+    // maker fills at its own price and earns the rebate
+    let maker = &sim.users[&user_key()];
+    assert_eq!(
+        maker.positions[0].base_asset_amount,
+        BASE_PRECISION_I128 / 10
+    );
+    assert_eq!(maker.positions[0].quote_asset_amount, -9_900_000 + 5_940);
+    assert_eq!(maker.positions[0].open_orders, 0);
+
+    let taker = &sim.users[&counterparty_key];
+    assert_eq!(
+        taker.positions[0].base_asset_amount,
+        -BASE_PRECISION_I128 / 10
+    );
+    assert_eq!(taker.positions[0].quote_asset_amount, 9_900_000 - 9_900);
+    assert_eq!(taker.positions[0].open_orders, 0);
+
+    assert!(sim.users[&filler_key()].positions[0].quote_asset_amount > 0);
+}
+
+#[test]
+fn match_orders_rejects_under_margined_counterparty() {
+    let mut sim = get_simulator();
+
+    let counterparty_key = Pubkey::from_str("My11111111111111111111111111111111111111114").unwrap();
+    sim.add_user(
+        counterparty_key,
+        User {
+            authority: counterparty_key,
+            bank_balances: get_bank_balances(UserBankBalance {
+                bank_index: 0,
+                balance_type: BankBalanceType::Deposit,
+                balance: 100 * BANK_INTEREST_PRECISION,
+            }),
+            next_order_id: 1,
+            ..User::default()
+        },
+    );
+
+    sim.place_order(
+        &user_key(),
+        OrderParams {
+            order_type: OrderType::Limit,
+            direction: PositionDirection::Long,
+            base_asset_amount: BASE_PRECISION / 10,
+            price: 99 * MARK_PRICE_PRECISION,
+            market_index: 0,
+            post_only: true,
+            ..OrderParams::default()
+        },
+    )
+    .unwrap();
+    let order_id = sim.users[&user_key()].get_last_order_id();
+
+    sim.advance(1, 1);
+
+    sim.place_order(
+        &counterparty_key,
+        OrderParams {
+            order_type: OrderType::Limit,
+            direction: PositionDirection::Short,
+            base_asset_amount: BASE_PRECISION / 10,
+            price: 98 * MARK_PRICE_PRECISION,
+            market_index: 0,
+            ..OrderParams::default()
+        },
+    )
+    .unwrap();
+    let counterparty_order_id = sim.users[&counterparty_key].get_last_order_id();
+
+    // collateral drops after the order rested, so filling it would open size below initial margin
+    sim.users.get_mut(&counterparty_key).unwrap().bank_balances[0].balance =
+        BANK_INTEREST_PRECISION / 100;
+
+    let user_before = sim.users[&user_key()];
+    let counterparty_before = sim.users[&counterparty_key];
+    assert_eq!(
+        sim.match_orders(
+            &user_key(),
+            order_id,
+            &counterparty_key,
+            counterparty_order_id,
+            &filler_key(),
+        ),
+        Err(ErrorCode::InsufficientCollateral)
+    );
+    assert_eq!(sim.users[&user_key()], user_before);
+    assert_eq!(sim.users[&counterparty_key], counterparty_before);
+}
+
+#[test]
+fn match_orders_cancels_expired_resting_order() {
+    let mut sim = get_simulator();
+
+    let counterparty_key = Pubkey::from_str("My11111111111111111111111111111111111111114").unwrap();
+    sim.add_user(
+        counterparty_key,
+        User {
+            authority: counterparty_key,
+            bank_balances: get_bank_balances(UserBankBalance {
+                bank_index: 0,
+                balance_type: BankBalanceType::Deposit,
+                balance: 100 * BANK_INTEREST_PRECISION,
+            }),
+            next_order_id: 1,
+            ..User::default()
+        },
+    );
+
+    sim.place_order(
+        &user_key(),
+        OrderParams {
+            order_type: OrderType::Limit,
+            direction: PositionDirection::Long,
+            base_asset_amount: BASE_PRECISION / 10,
+            price: 99 * MARK_PRICE_PRECISION,
+            market_index: 0,
+            post_only: true,
+            max_ts: Some(60),
+            ..OrderParams::default()
+        },
+    )
+    .unwrap();
+    let order_id = sim.users[&user_key()].get_last_order_id();
+
+    sim.advance(1, 1);
+
+    sim.place_order(
+        &counterparty_key,
+        OrderParams {
+            order_type: OrderType::Limit,
+            direction: PositionDirection::Short,
+            base_asset_amount: BASE_PRECISION / 10,
+            price: 98 * MARK_PRICE_PRECISION,
+            market_index: 0,
+            ..OrderParams::default()
+        },
+    )
+    .unwrap();
+    let counterparty_order_id = sim.users[&counterparty_key].get_last_order_id();
+
+    sim.advance(150, 61);
+    sim.set_oracle_price(oracle_key(), 100 * MARK_PRICE_PRECISION as i64, 0);
+
+    // the expired bid is canceled for the keeper instead of matched
+    let base_asset_amount = sim
+        .match_orders(
+            &user_key(),
+            order_id,
+            &counterparty_key,
+            counterparty_order_id,
+            &filler_key(),
+        )
+        .unwrap();
+    assert_eq!(base_asset_amount, 0);
+
+    let user = &sim.users[&user_key()];
+    assert!(user.get_order(order_id).is_none());
+    assert_eq!(user.positions[0].base_asset_amount, 0);
+    assert_eq!(user.positions[0].open_orders, 0);
+
+    let counterparty = &sim.users[&counterparty_key];
+    assert!(counterparty.get_order(counterparty_order_id).is_some());
+    assert_eq!(counterparty.positions[0].base_asset_amount, 0);
+}
+
+#[test]
+fn match_orders_cancels_oco_order_once_filled() {
+    let mut sim = get_simulator();
+
+    let counterparty_key = Pubkey::from_str("My11111111111111111111111111111111111111114").unwrap();
+    sim.add_user(
+        counterparty_key,
+        User {
+            authority: counterparty_key,
+            bank_balances: get_bank_balances(UserBankBalance {
+                bank_index: 0,
+                balance_type: BankBalanceType::Deposit,
+                balance: 100 * BANK_INTEREST_PRECISION,
+            }),
+            next_order_id: 1,
+            ..User::default()
+        },
+    );
+
+    sim.place_order(
+        &user_key(),
+        OrderParams {
+            order_type: OrderType::Limit,
+            direction: PositionDirection::Long,
+            base_asset_amount: BASE_PRECISION / 10,
+            price: 99 * MARK_PRICE_PRECISION,
+            market_index: 0,
+            post_only: true,
+            ..OrderParams::default()
+        },
+    )
+    .unwrap();
+    let order_id = sim.users[&user_key()].get_last_order_id();
+    sim.place_order(
+        &user_key(),
+        OrderParams {
+            order_type: OrderType::TriggerMarket,
+            direction: PositionDirection::Long,
+            base_asset_amount: BASE_PRECISION / 10,
+            trigger_price: 105 * MARK_PRICE_PRECISION,
+            trigger_condition: OrderTriggerCondition::Above,
+            market_index: 0,
+            ..OrderParams::default()
+        },
+    )
+    .unwrap();
+    let oco_order_id = sim.users[&user_key()].get_last_order_id();
+    for order in sim.users.get_mut(&user_key()).unwrap().orders.iter_mut() {
+        if order.order_id == order_id {
+            order.oco_order_id = oco_order_id;
+        } else if order.order_id == oco_order_id {
+            order.oco_order_id = order_id;
+        }
+    }
+
+    sim.advance(1, 1);
+
+    sim.place_order(
+        &counterparty_key,
+        OrderParams {
+            order_type: OrderType::Limit,
+            direction: PositionDirection::Short,
+            base_asset_amount: BASE_PRECISION / 10,
+            price: 98 * MARK_PRICE_PRECISION,
+            market_index: 0,
+            ..OrderParams::default()
+        },
+    )
+    .unwrap();
+    let counterparty_order_id = sim.users[&counterparty_key].get_last_order_id();
+
+    let base_asset_amount = sim
+        .match_orders(
+            &user_key(),
+            order_id,
+            &counterparty_key,
+            counterparty_order_id,
+            &filler_key(),
+        )
+        .unwrap();
+    assert_eq!(base_asset_amount, BASE_PRECISION / 10);
+
+    let user = &sim.users[&user_key()];
+    assert!(user.get_order(oco_order_id).is_none());
+    assert_eq!(
+        user.positions[0].base_asset_amount,
+        BASE_PRECISION_I128 / 10
+    );
+    assert_eq!(user.positions[0].open_orders, 0);
+}
+
+#[test]
+fn match_orders_rejects_later_post_only_order() {
+    let mut sim = get_simulator();
+
+    let counterparty_key = Pubkey::from_str("My11111111111111111111111111111111111111114").unwrap();
+    sim.add_user(
+        counterparty_key,
+        User {
+            authority: counterparty_key,
+            bank_balances: get_bank_balances(UserBankBalance {
+                bank_index: 0,
+                balance_type: BankBalanceType::Deposit,
+                balance: 100 * BANK_INTEREST_PRECISION,
+            }),
+            next_order_id: 1,
+            ..User::default()
+        },
+    );
+
+    sim.place_order(
+        &counterparty_key,
+        OrderParams {
+            order_type: OrderType::Limit,
+            direction: PositionDirection::Short,
+            base_asset_amount: BASE_PRECISION / 10,
+            price: 100 * MARK_PRICE_PRECISION,
+            market_index: 0,
+            post_only: true,
+            ..OrderParams::default()
+        },
+    )
+    .unwrap();
+
+    sim.advance(1, 1);
+
+    // amm moves up so a post-only bid can rest above the earlier ask
+    {
+        let amm = &mut sim.markets.get_mut(&0).unwrap().amm;
+        amm.base_asset_reserve = 99 * AMM_RESERVE_PRECISION;
+        amm.quote_asset_reserve = 100 * 100 * AMM_RESERVE_PRECISION / 99;
+    }
+    sim.place_order(
+        &user_key(),
+        OrderParams {
+            order_type: OrderType::Limit,
+            direction: PositionDirection::Long,
+            base_asset_amount: BASE_PRECISION / 10,
+            price: 101 * MARK_PRICE_PRECISION,
+            market_index: 0,
+            post_only: true,
+            ..OrderParams::default()
+        },
+    )
+    .unwrap();
+
+    let result = sim.match_orders(&user_key(), 1, &counterparty_key, 1, &filler_key());
+    assert_eq!(result, Err(ErrorCode::InvalidOrder));
+
+    let user = &sim.users[&user_key()];
+    assert_eq!(user.positions[0].base_asset_amount, 0);
+    assert_eq!(user.get_order(1).unwrap().status, OrderStatus::Open);
+}
+
+#[test]
+fn amm_jits_share_of_taker_auction() {
+    let mut sim = get_simulator();
+    sim.markets.get_mut(&0).unwrap().amm.amm_jit_intensity = 50;
+
+    sim.place_order(
+        &user_key(),
+        OrderParams {
+            order_type: OrderType::Market,
+            direction: PositionDirection::Long,
+            base_asset_amount: BASE_PRECISION,
+            price: 110 * MARK_PRICE_PRECISION,
+            market_index: 0,
+            auction_duration: 10,
+            ..OrderParams::default()
+        },
+    )
+    .unwrap();
+
+    let order_id = sim.users[&user_key()].get_last_order_id();
+    sim.advance(5, 5);
+
+    let order = sim.users[&user_key()].orders[0];
+    let auction_price = order
+        .get_limit_price(&sim.markets[&0].amm, None, sim.clock.slot)
+        .unwrap();
+    assert_eq!(auction_price, 105 * MARK_PRICE_PRECISION);
+
+    let (base_asset_amount, _) = sim
+        .fill_order(&user_key(), order_id, &filler_key(), &[])
+        .unwrap();
+
+    // amm only fills its share mid auction, at the auction price
+    assert_eq!(base_asset_amount, BASE_PRECISION / 2);
+
+    let user = &sim.users[&user_key()];
+    assert_eq!(user.orders[0].status, OrderStatus::Open);
+    assert_eq!(user.positions[0].base_asset_amount, BASE_PRECISION_I128 / 2);
+    assert_eq!(
+        user.positions[0].quote_entry_amount.unsigned_abs(),
+        // half the order at the auction price, rounded up in the amm's favor
+        52_500_001
+    );
+
+    let market = &sim.markets[&0];
+    assert_eq!(market.amm.net_base_asset_amount, BASE_PRECISION_I128 / 2);
+    assert!(market.amm.total_mm_fee > 0);
+}
+
+#[test]
+fn amm_fills_stop_at_max_net_base_asset_amount() {
+    let mut sim = get_simulator();
+    sim.markets
+        .get_mut(&0)
+        .unwrap()
+        .amm
+        .max_net_base_asset_amount = BASE_PRECISION / 2;
+
+    sim.place_order(
+        &user_key(),
+        OrderParams {
+            order_type: OrderType::Limit,
+            direction: PositionDirection::Long,
+            base_asset_amount: BASE_PRECISION,
+            price: 110 * MARK_PRICE_PRECISION,
+            market_index: 0,
+            ..OrderParams::default()
+        },
+    )
+    .unwrap();
+
+    let order_id = sim.users[&user_key()].get_last_order_id();
+    sim.advance(10, 5);
+
+    let (base_asset_amount, _) = sim
+        .fill_order(&user_key(), order_id, &filler_key(), &[])
+        .unwrap();
+    assert_eq!(base_asset_amount, BASE_PRECISION / 2);
+    assert_eq!(
+        sim.markets[&0].amm.net_base_asset_amount,
+        BASE_PRECISION_I128 / 2
+    );
+
+    // amm is at its cap, the rest of the order waits for a maker
+    let (base_asset_amount, _) = sim
+        .fill_order(&user_key(), order_id, &filler_key(), &[])
+        .unwrap();
+    assert_eq!(base_asset_amount, 0);
+
+    let user = &sim.users[&user_key()];
+    assert_eq!(user.orders[0].status, OrderStatus::Open);
+    assert_eq!(user.positions[0].base_asset_amount, BASE_PRECISION_I128 / 2);
+}
+
+#[test]
+fn oco_order_cancelled_only_once_fully_filled() {
+    let mut sim = get_simulator();
+
+    sim.place_order(
+        &user_key(),
+        OrderParams {
+            order_type: OrderType::Market,
+            direction: PositionDirection::Long,
+            base_asset_amount: BASE_PRECISION,
+            price: 110 * MARK_PRICE_PRECISION,
+            market_index: 0,
+            ..OrderParams::default()
+        },
+    )
+    .unwrap();
+    let order_id = sim.users[&user_key()].get_last_order_id();
+    sim.advance(10, 5);
+    sim.fill_order(&user_key(), order_id, &filler_key(), &[])
+        .unwrap();
+
+    let counterparty_key = Pubkey::from_str("My11111111111111111111111111111111111111114").unwrap();
+    sim.add_user(
+        counterparty_key,
+        User {
+            authority: counterparty_key,
+            bank_balances: get_bank_balances(UserBankBalance {
+                bank_index: 0,
+                balance_type: BankBalanceType::Deposit,
+                balance: 100 * BANK_INTEREST_PRECISION,
+            }),
+            next_order_id: 1,
+            ..User::default()
+        },
+    );
+
+    let counterparty_params = OrderParams {
+        order_type: OrderType::Limit,
+        direction: PositionDirection::Long,
+        base_asset_amount: BASE_PRECISION / 2,
+        price: 101 * MARK_PRICE_PRECISION,
+        market_index: 0,
+        ..OrderParams::default()
+    };
+
+    sim.place_order(&counterparty_key, counterparty_params.clone())
+        .unwrap();
+    let counterparty_order_id = sim.users[&counterparty_key].get_last_order_id();
+    sim.advance(1, 1);
+
+    // take profit linked to a stop loss
+    sim.place_order(
+        &user_key(),
+        OrderParams {
+            order_type: OrderType::Limit,
+            direction: PositionDirection::Short,
+            base_asset_amount: BASE_PRECISION,
+            price: 101 * MARK_PRICE_PRECISION,
+            reduce_only: true,
+            market_index: 0,
+            ..OrderParams::default()
+        },
+    )
+    .unwrap();
+    let take_profit_order_id = sim.users[&user_key()].get_last_order_id();
+    sim.place_order(
+        &user_key(),
+        OrderParams {
+            order_type: OrderType::TriggerMarket,
+            direction: PositionDirection::Short,
+            base_asset_amount: BASE_PRECISION,
+            trigger_price: 95 * MARK_PRICE_PRECISION,
+            trigger_condition: OrderTriggerCondition::Below,
+            reduce_only: true,
+            market_index: 0,
+            ..OrderParams::default()
+        },
+    )
+    .unwrap();
+    let stop_loss_order_id = sim.users[&user_key()].get_last_order_id();
+    for order in sim.users.get_mut(&user_key()).unwrap().orders.iter_mut() {
+        if order.order_id == take_profit_order_id {
+            order.oco_order_id = stop_loss_order_id;
+        } else if order.order_id == stop_loss_order_id {
+            order.oco_order_id = take_profit_order_id;
+        }
+    }
+
+    // partial fill of the take profit as the taker keeps the stop loss
+    let (base_asset_amount, _) = sim
+        .fill_order(
+            &user_key(),
+            take_profit_order_id,
+            &filler_key(),
+            &[(&counterparty_key, counterparty_order_id)],
+        )
+        .unwrap();
+    assert_eq!(base_asset_amount, BASE_PRECISION / 2);
+    assert!(sim.users[&user_key()]
+        .get_order(stop_loss_order_id)
+        .is_some());
+
+    // filling the rest as the maker cancels it
+    sim.advance(1, 1);
+    sim.place_order(&counterparty_key, counterparty_params)
+        .unwrap();
+    let counterparty_order_id = sim.users[&counterparty_key].get_last_order_id();
+    sim.fill_order(
+        &counterparty_key,
+        counterparty_order_id,
+        &filler_key(),
+        &[(&user_key(), take_profit_order_id)],
+    )
+    .unwrap();
+
+    let user = &sim.users[&user_key()];
+    assert!(user.get_order(take_profit_order_id).is_none());
+    assert!(user.get_order(stop_loss_order_id).is_none());
+    assert_eq!(user.positions[0].base_asset_amount, 0);
+    assert_eq!(user.positions[0].open_orders, 0);
+}
+
+#[test]
+fn transfer_position_rejected_when_it_breaches_initial_margin() {
+    let mut sim = get_simulator();
+
+    sim.place_order(
+        &user_key(),
+        OrderParams {
+            order_type: OrderType::Market,
+            direction: PositionDirection::Long,
+            base_asset_amount: BASE_PRECISION,
+            price: 110 * MARK_PRICE_PRECISION,
+            market_index: 0,
+            ..OrderParams::default()
+        },
+    )
+    .unwrap();
+    let order_id = sim.users[&user_key()].get_last_order_id();
+    sim.advance(10, 5);
+    sim.fill_order(&user_key(), order_id, &filler_key(), &[])
+        .unwrap();
+
+    let sub_account_keys = [
+        Pubkey::from_str("My11111111111111111111111111111111111111113").unwrap(),
+        Pubkey::from_str("My11111111111111111111111111111111111111114").unwrap(),
+    ];
+    let sub_account_balances = [BANK_INTEREST_PRECISION / 100, 100 * BANK_INTEREST_PRECISION];
+    for (sub_account_key, balance) in sub_account_keys.iter().zip(sub_account_balances) {
+        sim.add_user(
+            *sub_account_key,
+            User {
+                authority: user_key(),
+                bank_balances: get_bank_balances(UserBankBalance {
+                    bank_index: 0,
+                    balance_type: BankBalanceType::Deposit,
+                    balance,
+                }),
+                next_order_id: 1,
+                ..User::default()
+            },
+        );
+    }
+
+    let user_before = sim.users[&user_key()];
+    let sub_account_before = sim.users[&sub_account_keys[0]];
+
+    // the sub account can't carry the position on a cent of collateral
+    assert_eq!(
+        sim.transfer_position(&user_key(), &sub_account_keys[0], 0),
+        Err(ErrorCode::InsufficientCollateral)
+    );
+    assert_eq!(sim.users[&user_key()], user_before);
+    assert_eq!(sim.users[&sub_account_keys[0]], sub_account_before);
+
+    sim.transfer_position(&user_key(), &sub_account_keys[1], 0)
+        .unwrap();
+    assert!(sim.users[&user_key()].get_position(0).is_err());
+    let position = sim.users[&sub_account_keys[1]].get_position(0).unwrap();
+    assert_eq!(position.base_asset_amount, BASE_PRECISION_I128);
+    assert_eq!(
+        position.quote_asset_amount,
+        user_before.get_position(0).unwrap().quote_asset_amount
+    );
+}
@@ -4,16 +4,19 @@ use std::fmt::{Display, Formatter};
 use anchor_lang::prelude::*;
 use borsh::{BorshDeserialize, BorshSerialize};
 
-use crate::error::ClearingHouseResult;
+use crate::error::{ClearingHouseResult, ErrorCode};
 use crate::math::constants::{
-    AMM_RESERVE_PRECISION, BANK_WEIGHT_PRECISION, LIQUIDATION_FEE_PRECISION,
+    AMM_RESERVE_PRECISION, BANK_RESERVE_FACTOR_PRECISION, BANK_UTILIZATION_PRECISION,
+    BANK_WEIGHT_PRECISION, LIQUIDATION_FEE_PRECISION,
 };
 use crate::math::margin::{
     calculate_size_discount_asset_weight, calculate_size_premium_liability_weight,
     MarginRequirementType,
 };
 use crate::math_error;
+use crate::state::market::PoolBalance;
 use crate::state::oracle::OracleSource;
+use crate::validate;
 use solana_program::msg;
 
 #[account(zero_copy)]
@@ -47,6 +50,12 @@ pub struct Bank {
     pub imf_factor: u128,
     pub liquidation_fee: u128,
     pub withdraw_guard_threshold: u128, // no withdraw limits/guards when bank deposits below this threshold
+    pub interest_rate_model: InterestRateModel,
+    pub second_kink_utilization: u128,
+    pub second_kink_borrow_rate: u128,
+    pub fixed_borrow_rate: u128,
+    pub reserve_factor: u128, // share of borrow interest withheld from depositors
+    pub revenue_pool: PoolBalance, // protocol's share of borrow interest, sweepable to insurance vault
 }
 
 impl Bank {
@@ -112,6 +121,67 @@ impl Bank {
                 .ok_or_else(math_error!()),
         }
     }
+
+    pub fn validate_interest_rate_model(&self) -> ClearingHouseResult {
+        validate!(
+            self.optimal_utilization <= BANK_UTILIZATION_PRECISION,
+            ErrorCode::InvalidInterestRateModel,
+            "optimal_utilization must be <= {}",
+            BANK_UTILIZATION_PRECISION
+        )?;
+
+        validate!(
+            self.reserve_factor <= BANK_RESERVE_FACTOR_PRECISION,
+            ErrorCode::InvalidInterestRateModel,
+            "reserve_factor must be <= {}",
+            BANK_RESERVE_FACTOR_PRECISION
+        )?;
+
+        match self.interest_rate_model {
+            InterestRateModel::Kinked => {
+                validate!(
+                    self.optimal_borrow_rate <= self.max_borrow_rate,
+                    ErrorCode::InvalidInterestRateModel,
+                    "optimal_borrow_rate must be <= max_borrow_rate"
+                )?;
+            }
+            InterestRateModel::MultiKink => {
+                validate!(
+                    self.optimal_utilization < self.second_kink_utilization
+                        && self.second_kink_utilization < BANK_UTILIZATION_PRECISION,
+                    ErrorCode::InvalidInterestRateModel,
+                    "second_kink_utilization must be between optimal_utilization and {}",
+                    BANK_UTILIZATION_PRECISION
+                )?;
+
+                validate!(
+                    self.optimal_borrow_rate <= self.second_kink_borrow_rate
+                        && self.second_kink_borrow_rate <= self.max_borrow_rate,
+                    ErrorCode::InvalidInterestRateModel,
+                    "second_kink_borrow_rate must be between optimal_borrow_rate and max_borrow_rate"
+                )?;
+            }
+            InterestRateModel::Fixed => {}
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, BorshSerialize, BorshDeserialize, PartialEq, Eq, Debug)]
+pub enum InterestRateModel {
+    /// Linear up to optimal utilization, then linear up to max borrow rate
+    Kinked,
+    /// Adds a second kink between optimal utilization and full utilization, e.g. for a jump rate
+    MultiKink,
+    /// Borrow rate is fixed_borrow_rate regardless of utilization, for stable assets
+    Fixed,
+}
+
+impl Default for InterestRateModel {
+    fn default() -> Self {
+        InterestRateModel::Kinked
+    }
 }
 
 #[derive(Clone, Copy, BorshSerialize, BorshDeserialize, PartialEq, Eq, Debug)]
@@ -112,6 +112,7 @@ pub enum OrderAction {
     Fill,
     Trigger,
     Expire,
+    Modify,
 }
 
 #[derive(Clone, Copy, BorshSerialize, BorshDeserialize, PartialEq, Eq)]
@@ -124,6 +125,12 @@ pub enum OrderActionExplanation {
     CanceledForLiquidation,
     OrderFilledWithAMM,
     OrderFilledWithMatch,
+    CanceledForOcoOrder,
+    OrderExpired,
+    CanceledForSelfTrade,
+    FillOrKillNotFilled,
+    OrderFilledWithAMMJit,
+    QuoteAssetAmountFilled,
 }
 
 impl Default for OrderAction {
@@ -218,6 +225,7 @@ pub struct LiquidatePerpPnlForDepositRecord {
 pub struct PerpBankruptcyRecord {
     pub market_index: u64,
     pub pnl: i128,
+    pub if_payment: u128,
     pub cumulative_funding_rate_delta: i128,
 }
 
@@ -225,6 +233,7 @@ pub struct PerpBankruptcyRecord {
 pub struct BorrowBankruptcyRecord {
     pub bank_index: u64,
     pub borrow_amount: u128,
+    pub if_payment: u128,
     pub cumulative_deposit_interest_delta: u128,
 }
 
@@ -241,6 +250,19 @@ pub struct SettlePnlRecord {
     pub oracle_price: i128,
 }
 
+#[event]
+#[derive(Default)]
+pub struct TransferPositionRecord {
+    pub ts: i64,
+    pub from_user: Pubkey,
+    pub to_user: Pubkey,
+    pub market_index: u64,
+    pub base_asset_amount: i128,
+    pub quote_asset_amount: i128,
+    pub quote_entry_amount: i128,
+    pub oracle_price: i128,
+}
+
 pub fn emit_stack<T: AnchorSerialize + Discriminator, const N: usize>(event: T) {
     let mut data_buf = [0u8; N];
     let mut out_buf = [0u8; N];
@@ -1,5 +1,9 @@
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Eq)]
 pub enum FulfillmentMethod {
-    AMM,
-    Match,
+    // the amm fills up to the optional price before the next maker is matched
+    AMM(Option<u128>),
+    // index into the makers passed to fulfill_order
+    Match(usize),
+    // the amm fills a share of the taker at the auction price before the auction completes
+    AMMJit,
 }
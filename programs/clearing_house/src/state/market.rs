@@ -7,8 +7,10 @@ use switchboard_v2::AggregatorAccountData;
 use crate::error::{ClearingHouseResult, ErrorCode};
 use crate::math::amm;
 use crate::math::casting::{cast, cast_to_i128, cast_to_i64, cast_to_u128};
-use crate::math::constants::AMM_RESERVE_PRECISION;
 use crate::math::constants::LIQUIDATION_FEE_PRECISION;
+use crate::math::constants::{
+    AMM_RESERVE_PRECISION, DEFAULT_ORDER_TICK_SIZE_DENOMINATOR, PRICE_TO_PEG_PRECISION_RATIO,
+};
 use crate::math::margin::{
     calculate_size_discount_asset_weight, calculate_size_premium_liability_weight,
     MarginRequirementType,
@@ -16,7 +18,7 @@ use crate::math::margin::{
 use crate::math_error;
 use crate::state::bank::{BankBalance, BankBalanceType};
 use crate::state::oracle::{OraclePriceData, OracleSource};
-use crate::state::user::MarketPosition;
+use crate::state::user::{AuctionPriceCurve, MarketPosition};
 use crate::{
     AMM_TO_QUOTE_PRECISION_RATIO, BID_ASK_SPREAD_PRECISION, MARGIN_PRECISION, MARK_PRICE_PRECISION,
 };
@@ -43,6 +45,7 @@ pub struct Market {
     pub unsettled_maintenance_asset_weight: u8,
     pub unsettled_imf_factor: u128,
     pub liquidation_fee: u128,
+    pub auction_price_curve: AuctionPriceCurve,
 
     // upgrade-ability
     pub padding0: u32,
@@ -217,6 +220,11 @@ pub struct AMM {
     pub max_base_asset_amount_ratio: u16,
     pub max_slippage_ratio: u16,
     pub base_asset_amount_step_size: u128,
+    // max |net_base_asset_amount| the amm takes on, 0 for no cap
+    pub max_net_base_asset_amount: u128,
+    // min price increment post only orders slide by, 0 (markets created before it existed) means
+    // 1bp of the peg price
+    pub order_tick_size: u128,
 
     // market making
     pub market_position: MarketPosition,
@@ -249,6 +257,25 @@ pub struct AMM {
     pub last_update_slot: u64,
     pub last_oracle_valid: bool,
 
+    // dynamic spread coefficients
+    pub inventory_spread_scale: u32,
+    pub volatility_spread_scale: u32,
+    pub confidence_spread_scale: u32,
+
+    // oracle peg
+    pub oracle_peg_enabled: bool,
+    pub oracle_peg_slot_budget_pct: u32,
+    pub last_oracle_peg_slot: u64,
+
+    // share of a taker's unfilled size the amm can fill mid auction, 0 disables jit
+    pub amm_jit_intensity: u8,
+
+    // rebalance k
+    pub last_rebalance_k_ts: i64,
+    pub last_rebalance_k_total_fee: u128,
+    pub rebalance_k_day_start_ts: i64,
+    pub rebalance_k_day_start_sqrt_k: u128,
+
     pub padding0: u16,
     pub padding1: u32,
     pub padding2: u128,
@@ -270,6 +297,20 @@ impl AMM {
         }
     }
 
+    pub fn get_order_tick_size(&self) -> ClearingHouseResult<u128> {
+        if self.order_tick_size != 0 {
+            return Ok(self.order_tick_size);
+        }
+
+        Ok(self
+            .peg_multiplier
+            .checked_mul(PRICE_TO_PEG_PRECISION_RATIO)
+            .ok_or_else(math_error!())?
+            .checked_div(DEFAULT_ORDER_TICK_SIZE_DENOMINATOR)
+            .ok_or_else(math_error!())?
+            .max(1))
+    }
+
     pub fn mark_price(&self) -> ClearingHouseResult<u128> {
         amm::calculate_price(
             self.quote_asset_reserve,
@@ -49,6 +49,11 @@ pub struct State {
     pub min_auction_duration: u8,
     pub max_auction_duration: u8,
     pub liquidation_margin_buffer_ratio: u8,
+    pub insurance_fund_total_shares: u128,
+    pub insurance_fund_user_shares: u128, // protocol owns total - user shares
+    pub insurance_fund_unstaking_period: i64,
+    pub insurance_fund_unstaking_window: i64, // unstake request lapses if not completed within window after period
+    pub insurance_fund_shares_epoch: u64, // bumped when shares are written off after the vault is depleted
 
     // upgrade-ability
     pub padding0: u128,
@@ -8,11 +8,12 @@ use crate::math::amm::calculate_rolling_sum;
 use crate::math::auction::{calculate_auction_price, is_auction_complete};
 use crate::math::casting::cast_to_i128;
 use crate::math::constants::{QUOTE_ASSET_BANK_INDEX, THIRTY_DAY_I128};
+use crate::math::orders::calculate_twap_base_asset_amount_released;
 use crate::math::position::calculate_base_asset_value_and_pnl_with_oracle_price;
 use crate::math_error;
 use crate::state::bank::{BankBalance, BankBalanceType};
 use crate::state::market::AMM;
-use std::cmp::max;
+use std::cmp::{max, min};
 
 #[cfg(test)]
 mod tests;
@@ -325,6 +326,21 @@ pub struct Order {
     pub auction_start_price: u128,
     pub auction_end_price: u128,
     pub auction_duration: u8,
+    pub oco_order_id: u64,
+    pub trailing_stop_offset: u128,
+    pub trailing_stop_offset_type: TrailingStopOffsetType,
+    pub max_ts: i64,
+    pub iceberg_display_amount: u128,
+    pub iceberg_visible_amount: u128,
+    pub twap_slices: u8,
+    pub twap_slot_interval: u64,
+    pub twap_start_slot: u64,
+    pub self_trade_prevention: SelfTradePrevention,
+    pub fill_or_kill: bool,
+    pub quote_asset_amount: u128,
+    pub trigger_oracle: Pubkey,
+    pub auction_price_curve: AuctionPriceCurve,
+    pub auction_start_oracle_price: i128,
     pub padding: [u16; 3],
 }
 
@@ -358,10 +374,13 @@ impl Order {
             }
         } else if matches!(
             self.order_type,
-            OrderType::Market | OrderType::TriggerMarket
+            OrderType::Market
+                | OrderType::TriggerMarket
+                | OrderType::TrailingStop
+                | OrderType::Twap
         ) {
             if !is_auction_complete(self.slot, self.auction_duration, slot)? {
-                calculate_auction_price(self, slot)?
+                calculate_auction_price(self, slot, valid_oracle_price)?
             } else if self.price != 0 {
                 self.price
             } else {
@@ -395,17 +414,52 @@ impl Order {
             .ok_or_else(math_error!())
     }
 
+    pub fn get_visible_base_asset_amount_unfilled(&self) -> ClearingHouseResult<u128> {
+        let base_asset_amount_unfilled = self.get_base_asset_amount_unfilled()?;
+
+        if self.is_iceberg() {
+            Ok(min(base_asset_amount_unfilled, self.iceberg_visible_amount))
+        } else if self.order_type == OrderType::Twap {
+            // only the slices released as of the current slice's auction are fillable
+            let base_asset_amount_released =
+                calculate_twap_base_asset_amount_released(self, self.slot)?;
+            Ok(base_asset_amount_released.saturating_sub(self.base_asset_amount_filled))
+        } else {
+            Ok(base_asset_amount_unfilled)
+        }
+    }
+
+    pub fn is_iceberg(&self) -> bool {
+        self.iceberg_display_amount != 0
+    }
+
+    pub fn is_quote_sized(&self) -> bool {
+        self.quote_asset_amount != 0
+    }
+
     pub fn must_be_triggered(&self) -> bool {
         matches!(
             self.order_type,
-            OrderType::TriggerMarket | OrderType::TriggerLimit
+            OrderType::TriggerMarket | OrderType::TriggerLimit | OrderType::TrailingStop
         )
     }
 
+    pub fn has_cross_market_trigger(&self) -> bool {
+        self.trigger_oracle != Pubkey::default()
+    }
+
     pub fn is_jit_maker(&self) -> bool {
         self.post_only && self.immediate_or_cancel
     }
 
+    pub fn has_oco_order(&self) -> bool {
+        self.oco_order_id != 0
+    }
+
+    pub fn is_past_max_ts(&self, now: i64) -> bool {
+        self.max_ts != 0 && now > self.max_ts
+    }
+
     pub fn is_open_order_for_market(&self, market_index: u64) -> bool {
         self.market_index == market_index && self.status == OrderStatus::Open
     }
@@ -440,6 +494,21 @@ impl Default for Order {
             auction_start_price: 0,
             auction_end_price: 0,
             auction_duration: 0,
+            oco_order_id: 0,
+            trailing_stop_offset: 0,
+            trailing_stop_offset_type: TrailingStopOffsetType::Price,
+            max_ts: 0,
+            iceberg_display_amount: 0,
+            iceberg_visible_amount: 0,
+            twap_slices: 0,
+            twap_slot_interval: 0,
+            twap_start_slot: 0,
+            self_trade_prevention: SelfTradePrevention::None,
+            fill_or_kill: false,
+            quote_asset_amount: 0,
+            trigger_oracle: Pubkey::default(),
+            auction_price_curve: AuctionPriceCurve::Linear,
+            auction_start_oracle_price: 0,
             padding: [0; 3],
         }
     }
@@ -459,6 +528,8 @@ pub enum OrderType {
     Limit,
     TriggerMarket,
     TriggerLimit,
+    TrailingStop,
+    Twap,
 }
 
 #[derive(Clone, Copy, BorshSerialize, BorshDeserialize, PartialEq, Debug, Eq)]
@@ -483,6 +554,32 @@ impl Default for OrderTriggerCondition {
     }
 }
 
+#[derive(Clone, Copy, BorshSerialize, BorshDeserialize, PartialEq, Debug, Eq, Default)]
+pub enum TrailingStopOffsetType {
+    #[default]
+    Price,
+    Percentage,
+}
+
+#[derive(Clone, Copy, BorshSerialize, BorshDeserialize, PartialEq, Debug, Eq, Default)]
+pub enum SelfTradePrevention {
+    #[default]
+    None,
+    CancelTaker,
+    CancelMaker,
+    CancelBoth,
+    DecrementAndCancel,
+}
+
+#[derive(Clone, Copy, BorshSerialize, BorshDeserialize, PartialEq, Debug, Eq, Default)]
+pub enum AuctionPriceCurve {
+    #[default]
+    Linear,
+    ExponentialDecay,
+    Step,
+    OracleRelative,
+}
+
 #[account(zero_copy)]
 #[derive(Default, Eq, PartialEq, Debug)]
 #[repr(packed)]
@@ -501,6 +598,13 @@ pub struct UserStats {
     pub last_maker_volume_30d_ts: i64,
     pub last_taker_volume_30d_ts: i64,
     pub last_filler_volume_30d_ts: i64,
+
+    // insurance fund stake
+    pub insurance_fund_shares: u128,
+    pub unstake_request_shares: u128,
+    pub unstake_request_amount: u64,
+    pub unstake_request_ts: i64,
+    pub insurance_fund_shares_epoch: u64, // shares are void once this falls behind the state's epoch
 }
 
 impl UserStats {
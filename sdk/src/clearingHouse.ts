@@ -1378,7 +1378,7 @@ export class ClearingHouse {
 		userAccountPublicKey: PublicKey,
 		user: UserAccount,
 		order?: Order,
-		makerInfo?: MakerInfo
+		makerInfo?: MakerInfo | MakerInfo[]
 	): Promise<TransactionSignature> {
 		const { txSig } = await this.txSender.send(
 			wrapInTx(
@@ -1394,7 +1394,7 @@ export class ClearingHouse {
 		userAccountPublicKey: PublicKey,
 		userAccount: UserAccount,
 		order: Order,
-		makerInfo?: MakerInfo
+		makerInfo?: MakerInfo | MakerInfo[]
 	): Promise<TransactionInstruction> {
 		const userStatsPublicKey = getUserStatsAccountPublicKey(
 			this.program.programId,
@@ -1466,7 +1466,8 @@ export class ClearingHouse {
 			...marketAccountMap.values(),
 		];
 
-		if (makerInfo) {
+		const makerInfos = this.getMakerInfos(makerInfo);
+		for (const makerInfo of makerInfos) {
 			remainingAccounts.push({
 				pubkey: makerInfo.maker,
 				isWritable: true,
@@ -1480,9 +1481,11 @@ export class ClearingHouse {
 		}
 
 		const orderId = order.orderId;
-		const makerOrderId = makerInfo ? makerInfo.order.orderId : null;
+		const makerOrderIds = makerInfos.map(
+			(makerInfo) => makerInfo.order.orderId
+		);
 
-		return await this.program.instruction.fillOrder(orderId, makerOrderId, {
+		return await this.program.instruction.fillOrder(orderId, makerOrderIds, {
 			accounts: {
 				state: await this.getStatePublicKey(),
 				filler: fillerPublicKey,
@@ -1591,7 +1594,7 @@ export class ClearingHouse {
 
 	public async placeAndTake(
 		orderParams: OptionalOrderParams,
-		makerInfo?: MakerInfo
+		makerInfo?: MakerInfo | MakerInfo[]
 	): Promise<TransactionSignature> {
 		const { txSig, slot } = await this.txSender.send(
 			wrapInTx(await this.getPlaceAndTakeIx(orderParams, makerInfo)),
@@ -1604,7 +1607,7 @@ export class ClearingHouse {
 
 	public async getPlaceAndTakeIx(
 		orderParams: OptionalOrderParams,
-		makerInfo?: MakerInfo
+		makerInfo?: MakerInfo | MakerInfo[]
 	): Promise<TransactionInstruction> {
 		orderParams = this.getOrderParams(orderParams);
 		const userStatsPublicKey = await this.getUserStatsAccountPublicKey();
@@ -1615,9 +1618,8 @@ export class ClearingHouse {
 			writableBankIndex: QUOTE_ASSET_BANK_INDEX,
 		});
 
-		let makerOrderId = null;
-		if (makerInfo) {
-			makerOrderId = makerInfo.order.orderId;
+		const makerInfos = this.getMakerInfos(makerInfo);
+		for (const makerInfo of makerInfos) {
 			remainingAccounts.push({
 				pubkey: makerInfo.maker,
 				isSigner: false,
@@ -1630,9 +1632,13 @@ export class ClearingHouse {
 			});
 		}
 
+		const makerOrderIds = makerInfos.map(
+			(makerInfo) => makerInfo.order.orderId
+		);
+
 		return await this.program.instruction.placeAndTake(
 			orderParams,
-			makerOrderId,
+			makerOrderIds,
 			{
 				accounts: {
 					state: await this.getStatePublicKey(),
@@ -1645,6 +1651,23 @@ export class ClearingHouse {
 		);
 	}
 
+	/**
+	 * The program skips repeated makers, so only the first order per maker account is sent
+	 */
+	getMakerInfos(makerInfo?: MakerInfo | MakerInfo[]): MakerInfo[] {
+		if (makerInfo === undefined) {
+			return [];
+		}
+
+		const makerInfos = Array.isArray(makerInfo) ? makerInfo : [makerInfo];
+		return makerInfos.filter((makerInfo, index) => {
+			const firstIndex = makerInfos.findIndex((other) =>
+				other.maker.equals(makerInfo.maker)
+			);
+			return firstIndex === index;
+		});
+	}
+
 	public async placeAndMake(
 		orderParams: OptionalOrderParams,
 		takerInfo: TakerInfo
@@ -6,6 +6,7 @@ import {
 	LiquidationRecord,
 	OrderRecord,
 	SettlePnlRecord,
+	TransferPositionRecord,
 } from '../index';
 
 export type EventSubscriptionOptions = {
@@ -61,6 +62,7 @@ export type EventMap = {
 	FundingRateRecord: Event<FundingRateRecord>;
 	OrderRecord: Event<OrderRecord>;
 	SettlePnlRecord: Event<SettlePnlRecord>;
+	TransferPositionRecord: Event<TransferPositionRecord>;
 };
 
 export type EventType = keyof EventMap;
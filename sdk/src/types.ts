@@ -80,6 +80,26 @@ export class OrderTriggerCondition {
 	static readonly BELOW = { below: {} };
 }
 
+export class TrailingStopOffsetType {
+	static readonly PRICE = { price: {} };
+	static readonly PERCENTAGE = { percentage: {} };
+}
+
+export class SelfTradePrevention {
+	static readonly NONE = { none: {} };
+	static readonly CANCEL_TAKER = { cancelTaker: {} };
+	static readonly CANCEL_MAKER = { cancelMaker: {} };
+	static readonly CANCEL_BOTH = { cancelBoth: {} };
+	static readonly DECREMENT_AND_CANCEL = { decrementAndCancel: {} };
+}
+
+export class AuctionPriceCurve {
+	static readonly LINEAR = { linear: {} };
+	static readonly EXPONENTIAL_DECAY = { exponentialDecay: {} };
+	static readonly STEP = { step: {} };
+	static readonly ORACLE_RELATIVE = { oracleRelative: {} };
+}
+
 export function isVariant(object: unknown, type: string) {
 	return object.hasOwnProperty(type);
 }
@@ -262,6 +282,17 @@ export type SettlePnlRecord = {
 	oraclePrice: BN;
 };
 
+export type TransferPositionRecord = {
+	ts: BN;
+	fromUser: PublicKey;
+	toUser: PublicKey;
+	marketIndex: BN;
+	baseAssetAmount: BN;
+	quoteAssetAmount: BN;
+	quoteEntryAmount: BN;
+	oraclePrice: BN;
+};
+
 export type OrderRecord = {
 	ts: BN;
 	taker: PublicKey;
@@ -402,6 +433,7 @@ export type AMM = {
 	userLpShares: BN;
 	minimumQuoteAssetTradeSize: BN;
 	baseAssetAmountStepSize: BN;
+	orderTickSize: BN;
 	maxBaseAssetAmountRatio: number;
 	maxSlippageRatio: number;
 	lastOraclePrice: BN;
@@ -504,6 +536,21 @@ export type Order = {
 	auctionDuration: number;
 	auctionStartPrice: BN;
 	auctionEndPrice: BN;
+	ocoOrderId: BN;
+	trailingStopOffset: BN;
+	trailingStopOffsetType: TrailingStopOffsetType;
+	maxTs: BN;
+	icebergDisplayAmount: BN;
+	icebergVisibleAmount: BN;
+	twapSlices: number;
+	twapSlotInterval: BN;
+	twapStartSlot: BN;
+	selfTradePrevention: SelfTradePrevention;
+	fillOrKill: boolean;
+	quoteAssetAmount: BN;
+	triggerOracle: PublicKey;
+	auctionPriceCurve: AuctionPriceCurve;
+	auctionStartOraclePrice: BN;
 };
 
 export type OrderParams = {
@@ -520,8 +567,21 @@ export type OrderParams = {
 	triggerCondition: OrderTriggerCondition;
 	positionLimit: BN;
 	oraclePriceOffset: BN;
+	auctionDuration: number;
+	trailingStopOffset: BN;
+	trailingStopOffsetType: TrailingStopOffsetType;
+	maxTs: BN | null;
+	icebergDisplayAmount: BN | null;
+	twapSlices: number;
+	twapSlotInterval: BN;
+	selfTradePrevention: SelfTradePrevention;
+	postOnlySlide: boolean;
+	fillOrKill: boolean;
+	quoteAssetAmount: BN | null;
+	triggerOracle: PublicKey | null;
+	auctionPriceCurve: AuctionPriceCurve | null;
 	padding0: boolean;
-	padding1: BN;
+	padding1: boolean;
 	optionalAccounts: {
 		discountToken: boolean;
 		referrer: boolean;
@@ -553,8 +613,21 @@ export const DefaultOrderParams = {
 	triggerCondition: OrderTriggerCondition.ABOVE,
 	positionLimit: ZERO,
 	oraclePriceOffset: ZERO,
-	padding0: ZERO,
-	padding1: ZERO,
+	auctionDuration: 0,
+	trailingStopOffset: ZERO,
+	trailingStopOffsetType: TrailingStopOffsetType.PRICE,
+	maxTs: null,
+	icebergDisplayAmount: null,
+	twapSlices: 0,
+	twapSlotInterval: ZERO,
+	selfTradePrevention: SelfTradePrevention.NONE,
+	postOnlySlide: false,
+	fillOrKill: false,
+	quoteAssetAmount: null,
+	triggerOracle: null,
+	auctionPriceCurve: null,
+	padding0: false,
+	padding1: false,
 	optionalAccounts: {
 		discountToken: false,
 		referrer: false,